@@ -1,11 +1,17 @@
 use std::path::PathBuf;
 
+use anyhow::Result;
 use axum::{
+    extract::State,
+    http::header,
+    response::IntoResponse,
     routing::{get, post},
     Router,
 };
 use axum_server::tls_rustls::RustlsConfig;
 use clap::Parser;
+use habitat_admission::{metrics::Metrics, AdmissionState};
+use prometheus::{Encoder, TextEncoder};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -21,29 +27,55 @@ struct Args {
 
     #[arg(long, required = true, help = "Specify the file path to read the private key")]
     key_path: PathBuf,
+
+    #[arg(
+        long,
+        help = "Also validate task templates by creating dry-run pods in the server side"
+    )]
+    server_dry_run: bool,
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
 
     let args = Args::parse();
-    run(args.ip_addr, args.port, args.cert_path, args.key_path).await;
+    run(args).await
 }
 
-async fn run(ip_addr: std::net::IpAddr, port: u32, cert_path: PathBuf, key_path: PathBuf) {
-    let addr = format!("{}:{}", ip_addr, port);
+async fn run(args: Args) -> Result<()> {
+    let addr = format!("{}:{}", args.ip_addr, args.port);
+
+    // Build the client once, all the handlers share it
+    let state = AdmissionState {
+        client: kube::Client::try_default().await?,
+        server_dry_run: args.server_dry_run,
+        metrics: Metrics::default(),
+    };
 
     let app = Router::new()
         .route("/validate", post(habitat_admission::validate::handler))
         .route("/mutate", post(habitat_admission::mutate::handler))
         .layer(tower_http::trace::TraceLayer::new_for_http())
         // Reminder: routes added *after* TraceLayer are not subject to its logging behavior
-        .route("/health", get(|| async { "healthy" }));
+        .route("/health", get(|| async { "healthy" }))
+        .route("/metrics", get(metrics))
+        .with_state(state);
 
-    let config = RustlsConfig::from_pem_file(cert_path, key_path).await.unwrap();
-    axum_server::bind_rustls(addr.parse().unwrap(), config)
+    let config = RustlsConfig::from_pem_file(args.cert_path, args.key_path).await?;
+    axum_server::bind_rustls(addr.parse()?, config)
         .serve(app.into_make_service())
-        .await
-        .unwrap();
+        .await?;
+
+    Ok(())
+}
+
+async fn metrics(State(state): State<AdmissionState>) -> impl IntoResponse {
+    let mut buffer = vec![];
+    let encoder = TextEncoder::new();
+    encoder.encode(&state.metrics.gather(), &mut buffer).unwrap();
+    (
+        [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+        buffer,
+    )
 }
@@ -1,16 +1,171 @@
+use std::path::PathBuf;
+
 use anyhow::Result;
-use habitat_controller::manager::Manager;
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use clap::Parser;
+use habitat_controller::{config::Config, leader::LeaderElection, manager::Manager};
+use prometheus::{Encoder, TextEncoder};
+use tokio::time::Duration;
 use tracing::*;
 
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[arg(long, help = "Server addr", default_value = "0.0.0.0")]
+    ip_addr: std::net::IpAddr,
+
+    #[arg(long, help = "Server port", default_value_t = 8080)]
+    port: u16,
+
+    /// Elect a leader among the replicas, only the leader reconciles
+    #[arg(long)]
+    leader_elect: bool,
+
+    /// Seconds the followers wait before taking over a lease which isn't renewed
+    #[arg(long, default_value_t = 15)]
+    lease_duration: u64,
+
+    /// The namespace of the leader election lease
+    #[arg(long, default_value = "default")]
+    lease_namespace: String,
+
+    /// The YAML configuration file, overridden by the flags below
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Seconds to wait before reconciling again an object whose reconciliation failed once, doubled with
+    /// every consecutive failure
+    #[arg(long)]
+    min_error_requeue_seconds: Option<u64>,
+
+    /// The longest wait in seconds before reconciling again an object whose reconciliation failed
+    #[arg(long)]
+    error_requeue_seconds: Option<u64>,
+
+    /// The name of the controller in the events it reports
+    #[arg(long)]
+    reporter: Option<String>,
+
+    /// Only watch the jobs of this namespace, can be repeated. All the namespaces are watched by default
+    #[arg(long = "namespace", value_name = "NAMESPACE")]
+    namespaces: Vec<String>,
+
+    /// The label of the pods whose value is the name of their job
+    #[arg(long)]
+    task_owner_label: Option<String>,
+
+    /// The label of the pods whose value is the name of their task
+    #[arg(long)]
+    task_name_label: Option<String>,
+
+    /// The label of the pods whose value is their replica index
+    #[arg(long)]
+    replica_index_label: Option<String>,
+
+    /// The label of the jobs whose value is the name of their cron job
+    #[arg(long)]
+    cron_job_label: Option<String>,
+}
+
+impl Args {
+    /// The configuration file, overridden by the flags
+    fn config(&self) -> Result<Config> {
+        let mut config = match &self.config {
+            Some(path) => serde_yaml::from_str(&std::fs::read_to_string(path)?)?,
+            None => Config::default(),
+        };
+        if let Some(seconds) = self.min_error_requeue_seconds {
+            config.min_error_requeue_seconds = seconds;
+        }
+        if let Some(seconds) = self.error_requeue_seconds {
+            config.error_requeue_seconds = seconds;
+        }
+        if let Some(reporter) = &self.reporter {
+            config.reporter = reporter.clone();
+        }
+        if !self.namespaces.is_empty() {
+            config.namespaces = self.namespaces.clone();
+        }
+        let labels = &mut config.labels;
+        for (label, name) in [
+            (&mut labels.task_owner, &self.task_owner_label),
+            (&mut labels.task_name, &self.task_name_label),
+            (&mut labels.replica_index, &self.replica_index_label),
+            (&mut labels.cron_job, &self.cron_job_label),
+        ] {
+            if let Some(name) = name {
+                *label = name.clone();
+            }
+        }
+        Ok(config)
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
+    let args = Args::parse();
+    let config = args.config()?;
+
     let client = kube::Client::try_default().await?;
-    let (_manager, controller) = Manager::new(client).await;
+    let leader_election = args.leader_elect.then(|| {
+        // the hostname is the pod name, unique among the replicas
+        let identity =
+            std::env::var("HOSTNAME").unwrap_or_else(|_| format!("controller-{}", std::process::id()));
+        LeaderElection::new(
+            args.lease_namespace,
+            identity,
+            Duration::from_secs(args.lease_duration),
+        )
+    });
+    let (manager, controller) = Manager::new(client, config, leader_election).await;
+
+    let app = Router::new()
+        .route("/diagnostics", get(diagnostics))
+        .route("/metrics", get(metrics))
+        .layer(tower_http::trace::TraceLayer::new_for_http())
+        // Reminder: routes added *after* TraceLayer are not subject to its logging behavior
+        .route("/healthz", get(|| async { "healthy" }))
+        .route("/readyz", get(readyz))
+        .with_state(manager);
+    let server = axum::Server::bind(&(args.ip_addr, args.port).into()).serve(app.into_make_service());
 
+    // the controllers fail when habitat isn't installed or the leader election lease is lost, so the
+    // replica exits with an error and restarts
     tokio::select! {
-        _ = controller => warn!("controller exited"),
+        result = controller => {
+            result?;
+            warn!("controller exited");
+        }
+        result = server => warn!("server exited: {:?}", result),
     }
 
     Ok(())
 }
+
+async fn diagnostics(State(manager): State<Manager>) -> impl IntoResponse {
+    Json(manager.diagnostics().await)
+}
+
+async fn readyz(State(manager): State<Manager>) -> impl IntoResponse {
+    match manager.ready() {
+        true => (StatusCode::OK, "ready"),
+        false => (StatusCode::SERVICE_UNAVAILABLE, "not ready"),
+    }
+}
+
+async fn metrics(State(manager): State<Manager>) -> impl IntoResponse {
+    let mut buffer = vec![];
+    let encoder = TextEncoder::new();
+    encoder.encode(&manager.metrics(), &mut buffer).unwrap();
+    (
+        [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+        buffer,
+    )
+}
@@ -2,4 +2,13 @@ use kube::CustomResourceExt;
 
 fn main() {
     println!("{}", serde_yaml::to_string(&habitat_api::Job::crd()).unwrap());
+    println!("---");
+    println!("{}", serde_yaml::to_string(&habitat_api::Queue::crd()).unwrap());
+    println!("---");
+    println!("{}", serde_yaml::to_string(&habitat_api::CronJob::crd()).unwrap());
+    println!("---");
+    println!(
+        "{}",
+        serde_yaml::to_string(&habitat_api::JobTemplate::crd()).unwrap()
+    );
 }
@@ -0,0 +1,81 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::Utc;
+use clap::Parser;
+use habitat_api::batch::{LabelNames, REPLICA_INDEX_LABEL, TASK_NAME_LABEL, TASK_OWNER_LABEL};
+use habitat_controller::scheduler::Scheduler;
+use habitat_scheduler::{framework::Framework, plugins::Strategy, simulate::simulate, snapshot::Snapshot};
+use tokio::time::Duration;
+use tracing::*;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// The `schedulerName` of the pods to schedule
+    #[arg(long, default_value = "habitat")]
+    scheduler_name: String,
+
+    /// How the pods are placed among the feasible nodes, one of binpack, spread
+    #[arg(long, default_value = "binpack")]
+    strategy: Strategy,
+
+    /// Seconds between two scheduling cycles when no pod changes
+    #[arg(long, default_value_t = 5)]
+    interval: u64,
+
+    /// Instead of scheduling, print which pending jobs of a recorded snapshot would start, e.g. the output
+    /// of `kubectl get nodes,pods,priorityclasses,hqueues,hjobs -A -o yaml`
+    #[arg(long, value_name = "FILE")]
+    simulate: Option<PathBuf>,
+
+    /// The label of the pods whose value is the name of their job, the same as the controller's
+    #[arg(long, default_value = TASK_OWNER_LABEL)]
+    task_owner_label: String,
+
+    /// The label of the pods whose value is the name of their task, the same as the controller's
+    #[arg(long, default_value = TASK_NAME_LABEL)]
+    task_name_label: String,
+
+    /// The label of the pods whose value is their replica index, the same as the controller's
+    #[arg(long, default_value = REPLICA_INDEX_LABEL)]
+    replica_index_label: String,
+}
+
+impl Args {
+    /// The labels of the pods created for the jobs
+    fn labels(&self) -> LabelNames {
+        LabelNames {
+            task_owner: self.task_owner_label.clone(),
+            task_name: self.task_name_label.clone(),
+            replica_index: self.replica_index_label.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+    let args = Args::parse();
+    let framework = Framework::with_default_plugins(args.strategy);
+    let labels = args.labels();
+
+    if let Some(path) = args.simulate {
+        let snapshot = Snapshot::from_yaml(&std::fs::read_to_string(path)?)?;
+        for report in simulate(&snapshot, &framework, &labels, Utc::now()) {
+            println!("{}", report);
+        }
+        return Ok(());
+    }
+
+    let client = kube::Client::try_default().await?;
+    let scheduler = Scheduler::new(client, args.scheduler_name, framework, labels);
+
+    tokio::select! {
+        _ = scheduler.run(Duration::from_secs(args.interval)) => warn!("scheduler exited"),
+        _ = tokio::signal::ctrl_c() => info!("shutting down"),
+    }
+
+    Ok(())
+}
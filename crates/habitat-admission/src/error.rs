@@ -0,0 +1,228 @@
+use std::fmt;
+
+use kube::core::{
+    admission::AdmissionResponse,
+    response::{StatusCause, StatusDetails},
+};
+
+/// An invalid field of the admitted object
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    /// The path of the field, e.g. `spec.tasks[0].template.spec.containers[0].name`
+    pub field: String,
+    /// Why the field is invalid, e.g. `Required value`
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Parses an api server error entry in the form of `field.path: message`.
+    pub fn parse(entry: &str) -> Self {
+        match entry.split_once(": ") {
+            Some((field, message)) if !field.contains(' ') => Self::new(field, message),
+            _ => Self::new("", entry),
+        }
+    }
+
+    /// Nests the field under the given path prefix.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.field = if self.field.is_empty() {
+            prefix.trim_end_matches('.').to_string()
+        } else {
+            format!("{}{}", prefix, self.field)
+        };
+        self
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.field.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.field, self.message)
+        }
+    }
+}
+
+/// All the invalid fields found in the admitted object
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldErrors(pub Vec<FieldError>);
+
+impl FieldErrors {
+    pub fn push(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.0.push(FieldError::new(field, message));
+    }
+
+    pub fn extend(&mut self, errors: impl IntoIterator<Item = FieldError>) {
+        self.0.extend(errors);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Denies the admission, carrying every field error as a cause of the status.
+    pub fn deny(&self, res: AdmissionResponse) -> AdmissionResponse {
+        let mut res = res.deny(self);
+        res.result.code = 422;
+        res.result.reason = "Invalid".to_string();
+        res.result.details = Some(StatusDetails {
+            name: String::new(),
+            group: String::new(),
+            kind: String::new(),
+            uid: String::new(),
+            causes: self
+                .0
+                .iter()
+                .map(|err| StatusCause {
+                    reason: "FieldValueInvalid".to_string(),
+                    message: err.message.clone(),
+                    field: err.field.clone(),
+                })
+                .collect(),
+            retry_after_seconds: 0,
+        });
+        res
+    }
+}
+
+impl fmt::Display for FieldErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors = self.0.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        match errors.len() {
+            1 => write!(f, "{}", errors[0]),
+            _ => write!(f, "[{}]", errors.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for FieldErrors {}
+
+/// The check which denied an admission, bounded to label the decision metrics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DenialReason {
+    /// The tasks or their pod templates are invalid
+    PodSpec,
+    /// The job doesn't fit in the resource quotas or limit ranges of its namespace
+    Quota,
+    /// The queue of the job is closed
+    QueueClosed,
+    /// The queue of the job doesn't exist
+    QueueMissing,
+    /// The sweep of the job is invalid
+    Sweep,
+    /// The dependencies of the job are invalid
+    Dependency,
+}
+
+impl DenialReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DenialReason::PodSpec => "PodSpec",
+            DenialReason::Quota => "Quota",
+            DenialReason::QueueClosed => "QueueClosed",
+            DenialReason::QueueMissing => "QueueMissing",
+            DenialReason::Sweep => "Sweep",
+            DenialReason::Dependency => "Dependency",
+        }
+    }
+}
+
+/// The field errors found by the checks of an admission, labelled with the check which found the first one
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Denial {
+    pub reason: Option<DenialReason>,
+    pub errors: FieldErrors,
+}
+
+impl Denial {
+    /// Records the errors found by a check.
+    pub fn add(&mut self, reason: DenialReason, errors: FieldErrors) {
+        if !errors.is_empty() {
+            self.reason.get_or_insert(reason);
+            self.errors.extend(errors.0);
+        }
+    }
+
+    /// Records the errors found by other checks.
+    pub fn merge(&mut self, other: Denial) {
+        if let Some(reason) = other.reason {
+            self.add(reason, other.errors);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl fmt::Display for Denial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.errors.fmt(f)
+    }
+}
+
+impl std::error::Error for Denial {}
+
+#[cfg(test)]
+mod test {
+    use super::{Denial, DenialReason, FieldError, FieldErrors};
+
+    #[test]
+    fn test_field_errors_display() {
+        let mut errors = FieldErrors::default();
+        errors.push("spec.tasks", "Required value");
+        assert_eq!(errors.to_string(), "spec.tasks: Required value");
+
+        errors.push("spec.tasks[0].name", "Duplicate value: \"a\"");
+        assert_eq!(
+            errors.to_string(),
+            "[spec.tasks: Required value, spec.tasks[0].name: Duplicate value: \"a\"]"
+        );
+    }
+
+    #[test]
+    fn test_parse_field_error() {
+        assert_eq!(
+            FieldError::parse("spec.containers[0].image: Required value")
+                .with_prefix("spec.tasks[1].template."),
+            FieldError::new(
+                "spec.tasks[1].template.spec.containers[0].image",
+                "Required value"
+            )
+        );
+        assert_eq!(
+            FieldError::parse("something went wrong: oops"),
+            FieldError::new("", "something went wrong: oops")
+        );
+    }
+
+    #[test]
+    fn test_denial() {
+        let mut denial = Denial::default();
+        denial.add(DenialReason::Sweep, FieldErrors::default());
+        assert!(denial.is_empty());
+
+        let mut errors = FieldErrors::default();
+        errors.push("spec.tasks", "Required value");
+        denial.add(DenialReason::PodSpec, errors);
+        let mut queue = Denial::default();
+        queue.add(
+            DenialReason::QueueMissing,
+            FieldErrors(vec![FieldError::new("spec.queue", "Not found: \"default\"")]),
+        );
+        denial.merge(queue);
+        assert_eq!(denial.reason, Some(DenialReason::PodSpec));
+        assert_eq!(
+            denial.to_string(),
+            "[spec.tasks: Required value, spec.queue: Not found: \"default\"]"
+        );
+    }
+}
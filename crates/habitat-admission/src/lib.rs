@@ -1,4 +1,24 @@
+use kube::Client;
+use metrics::Metrics;
+
+pub mod error;
+pub mod metrics;
 pub mod mutate;
 pub mod validate;
 
+mod pod;
+mod queue;
+mod quota;
+mod sweep;
 mod util;
+
+/// State shared by the admission handlers
+#[derive(Clone)]
+pub struct AdmissionState {
+    /// Kubernetes client
+    pub client: Client,
+    /// Whether to also validate the task templates by creating dry-run pods in the server side
+    pub server_dry_run: bool,
+    /// Prometheus metrics of the handlers
+    pub metrics: Metrics,
+}
@@ -0,0 +1,111 @@
+use prometheus::{
+    histogram_opts, opts, proto::MetricFamily, Histogram, HistogramTimer, HistogramVec, IntCounterVec,
+    Registry,
+};
+
+/// Metrics of the admission handlers
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    /// Admission requests by route
+    pub requests: IntCounterVec,
+    /// Admission decisions by route, whether the object is allowed, and why
+    pub decisions: IntCounterVec,
+    /// Admission request durations by route
+    pub request_duration: HistogramVec,
+    /// Durations of the dry-run pods created to validate the task templates
+    pub dry_run_duration: Histogram,
+    /// Admitted objects which can't be decoded as jobs, by route
+    pub decode_errors: IntCounterVec,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let registry = Registry::new_custom(Some("habitat_admission".to_string()), None).unwrap();
+        let requests = IntCounterVec::new(opts!("requests_total", "admission requests"), &["route"]).unwrap();
+        let decisions = IntCounterVec::new(opts!("decisions_total", "admission decisions"), &[
+            "route", "allowed", "reason",
+        ])
+        .unwrap();
+        let request_duration = HistogramVec::new(
+            histogram_opts!(
+                "request_duration_seconds",
+                "The duration of admission requests in seconds",
+                vec![0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1., 5.]
+            ),
+            &["route"],
+        )
+        .unwrap();
+        let dry_run_duration = Histogram::with_opts(histogram_opts!(
+            "dry_run_duration_seconds",
+            "The duration of the dry-run pod creations in seconds",
+            vec![0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1., 5.]
+        ))
+        .unwrap();
+        let decode_errors = IntCounterVec::new(
+            opts!("decode_errors_total", "objects failed to decode as jobs"),
+            &["route"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(requests.clone())).unwrap();
+        registry.register(Box::new(decisions.clone())).unwrap();
+        registry.register(Box::new(request_duration.clone())).unwrap();
+        registry.register(Box::new(dry_run_duration.clone())).unwrap();
+        registry.register(Box::new(decode_errors.clone())).unwrap();
+
+        Self {
+            registry,
+            requests,
+            decisions,
+            request_duration,
+            dry_run_duration,
+            decode_errors,
+        }
+    }
+}
+
+impl Metrics {
+    /// Counts a request, and measures its duration until the returned timer is dropped.
+    pub fn count_and_measure(&self, route: &str) -> HistogramTimer {
+        self.requests.with_label_values(&[route]).inc();
+        self.request_duration.with_label_values(&[route]).start_timer()
+    }
+
+    /// Counts an admission decision.
+    pub fn decision(&self, route: &str, allowed: bool, reason: &str) {
+        self.decisions
+            .with_label_values(&[route, &allowed.to_string(), reason])
+            .inc();
+    }
+
+    /// Gathers all the metrics.
+    pub fn gather(&self) -> Vec<MetricFamily> {
+        self.registry.gather()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Metrics;
+
+    #[test]
+    fn test_decision() {
+        let metrics = Metrics::default();
+        metrics.decision("validate", false, "Quota");
+        metrics.decision("validate", false, "Quota");
+        metrics.decision("validate", true, "Accepted");
+
+        assert_eq!(
+            metrics
+                .decisions
+                .with_label_values(&["validate", "false", "Quota"])
+                .get(),
+            2
+        );
+        let families = metrics.gather();
+        assert!(families
+            .iter()
+            .any(|f| f.get_name() == "habitat_admission_decisions_total"));
+    }
+}
@@ -1,22 +1,29 @@
-use axum::Json;
-use habitat_api::Job;
-use kube::core::{
-    admission::{AdmissionRequest, AdmissionResponse, AdmissionReview},
-    DynamicObject, ResourceExt,
+use axum::{extract::State, Json};
+use habitat_api::{Job, JobTemplate};
+use kube::{
+    core::{
+        admission::{AdmissionRequest, AdmissionResponse, AdmissionReview},
+        DynamicObject, ResourceExt,
+    },
+    Api,
 };
 use std::error::Error;
 use tracing::*;
 
-use crate::util::try_cast_dynamic_obj_into_job;
+use crate::{util::try_cast_dynamic_obj_into_job, AdmissionState};
 
 pub async fn handler(
+    State(state): State<AdmissionState>,
     Json(body): Json<AdmissionReview<DynamicObject>>,
 ) -> Json<AdmissionReview<DynamicObject>> {
+    let metrics = &state.metrics;
+    let _timer = metrics.count_and_measure("mutate");
     // Parse incoming webhook AdmissionRequest first
     let req: AdmissionRequest<_> = match body.try_into() {
         Ok(req) => req,
         Err(err) => {
             error!("invalid request: {}", err.to_string());
+            metrics.decision("mutate", false, "InvalidRequest");
             return Json(AdmissionResponse::invalid(err.to_string()).into_review());
         }
     };
@@ -25,21 +32,33 @@ pub async fn handler(
     let mut res = AdmissionResponse::from(&req);
     // req.Object always exists for us, but could be None if extending to DELETE events
     if let Some(obj) = req.object {
-        let name = obj.name_any(); // apiserver may not have generated a name yet
+        // apiserver may not have generated a name yet
+        let name = obj.name_any();
+        // the object may not carry its namespace yet, fall back to the one of the request
+        let ns = obj.namespace().or(req.namespace);
 
-        res = match try_cast_dynamic_obj_into_job(&obj) {
-            Ok(job) => match mutate(res.clone(), &job) {
+        res = match (try_cast_dynamic_obj_into_job(&obj), ns) {
+            (Ok(job), Some(ns)) => match mutate(res.clone(), &job, &state, &ns).await {
                 Ok(res) => {
                     info!("accepted: {:?} on Job {}", req.operation, name);
+                    metrics.decision("mutate", true, "Accepted");
                     res
                 }
                 Err(err) => {
                     warn!("denied: {:?} on {} ({})", req.operation, name, err);
+                    metrics.decision("mutate", false, "TemplateError");
                     res.deny(err.to_string())
                 }
             },
-            Err(err) => {
+            (Ok(_), None) => {
+                warn!("invalid job: {:?} on {} (missing namespace)", req.operation, name);
+                metrics.decision("mutate", false, "MissingNamespace");
+                res.deny("unable to determine the namespace of the job")
+            }
+            (Err(err), _) => {
                 warn!("invalid job: {:?} on {} ({})", req.operation, name, err);
+                metrics.decode_errors.with_label_values(&["mutate"]).inc();
+                metrics.decision("mutate", false, "DecodeError");
                 res.deny(err)
             }
         };
@@ -49,6 +68,39 @@ pub async fn handler(
 }
 
 // The main handler and core business logic, failures here implies rejected applies
-fn mutate(res: AdmissionResponse, _job: &Job) -> Result<AdmissionResponse, Box<dyn Error>> {
-    Ok(res)
+async fn mutate(
+    res: AdmissionResponse,
+    job: &Job,
+    state: &AdmissionState,
+    ns: &str,
+) -> Result<AdmissionResponse, Box<dyn Error>> {
+    // the tasks are only instantiated once, so updates keep them
+    let template_name = match &job.spec.template_ref {
+        Some(name) if job.spec.tasks.is_empty() => name,
+        _ => return Ok(res),
+    };
+
+    let templates: Api<JobTemplate> = Api::namespaced(state.client.clone(), ns);
+    let template = templates
+        .get_opt(template_name)
+        .await?
+        .ok_or_else(|| format!("job template `{}` not found", template_name))?;
+    let instance = template
+        .instantiate(&job.spec.parameters)
+        .map_err(|err| format!("invalid job template `{}`: {}", template_name, err))?;
+
+    let mut expanded = job.clone();
+    let spec = &mut expanded.spec;
+    spec.scheduler_name = spec.scheduler_name.take().or(instance.scheduler_name);
+    spec.priority = spec.priority.take().or(instance.priority);
+    spec.queue = spec.queue.take().or(instance.queue);
+    spec.estimated_duration = spec.estimated_duration.or(instance.estimated_duration);
+    if spec.depends_on.is_empty() {
+        spec.depends_on = instance.depends_on;
+    }
+    spec.sweep = spec.sweep.take().or(instance.sweep);
+    spec.tasks = instance.tasks;
+
+    let patch = json_patch::diff(&serde_json::to_value(job)?, &serde_json::to_value(&expanded)?);
+    Ok(res.with_patch(patch)?)
 }
@@ -0,0 +1,326 @@
+use std::collections::HashSet;
+
+use habitat_api::{batch::PodSpec, resource::parse_quantity};
+use k8s_openapi::{
+    api::core::v1::{Container, ContainerPort, ResourceRequirements},
+    apimachinery::pkg::api::resource::Quantity,
+};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::error::FieldErrors;
+
+lazy_static! {
+    static ref DNS1123_LABEL: Regex = Regex::new(r"^[a-z0-9]([-a-z0-9]*[a-z0-9])?$").unwrap();
+}
+
+const RESTART_POLICIES: [&str; 3] = ["Always", "OnFailure", "Never"];
+const PORT_PROTOCOLS: [&str; 3] = ["TCP", "UDP", "SCTP"];
+
+/// Validates the pod spec in process, which mirrors the checks the api server does on pod creation for
+/// container names, ports, volume mount references, resource quantities and the restart policy.
+///
+/// The returned fields are relative to the pod template, e.g. `spec.containers[0].name`.
+pub fn validate_pod_spec(spec: &PodSpec) -> FieldErrors {
+    let mut errors = FieldErrors::default();
+    let volumes = validate_volumes(spec, &mut errors);
+
+    if spec.containers.is_empty() {
+        errors.push("spec.containers", "Required value");
+    }
+
+    let mut container_names = HashSet::new();
+    let init_containers = spec.init_containers.iter().flatten();
+    for (idx, container) in init_containers.enumerate() {
+        let path = format!("spec.initContainers[{}]", idx);
+        validate_container(container, &path, &mut container_names, &volumes, &mut errors);
+    }
+    for (idx, container) in spec.containers.iter().enumerate() {
+        let path = format!("spec.containers[{}]", idx);
+        validate_container(container, &path, &mut container_names, &volumes, &mut errors);
+    }
+
+    if let Some(restart_policy) = &spec.restart_policy {
+        if !RESTART_POLICIES.contains(&restart_policy.as_str()) {
+            errors.push(
+                "spec.restartPolicy",
+                format!(
+                    "Unsupported value: \"{}\": supported values: {}",
+                    restart_policy,
+                    quote_all(&RESTART_POLICIES)
+                ),
+            );
+        }
+    }
+
+    if let Some(deadline) = spec.active_deadline_seconds.filter(|deadline| *deadline <= 0) {
+        errors.push(
+            "spec.activeDeadlineSeconds",
+            format!("Invalid value: {}: must be greater than 0", deadline),
+        );
+    }
+
+    errors
+}
+
+fn validate_volumes<'a>(spec: &'a PodSpec, errors: &mut FieldErrors) -> HashSet<&'a str> {
+    let mut volumes = HashSet::new();
+    for (idx, volume) in spec.volumes.iter().flatten().enumerate() {
+        let path = format!("spec.volumes[{}].name", idx);
+        validate_dns1123_label(&volume.name, &path, errors);
+        if !volumes.insert(volume.name.as_str()) {
+            errors.push(path, format!("Duplicate value: \"{}\"", volume.name));
+        }
+    }
+    volumes
+}
+
+fn validate_container<'a>(
+    container: &'a Container,
+    path: &str,
+    container_names: &mut HashSet<&'a str>,
+    volumes: &HashSet<&str>,
+    errors: &mut FieldErrors,
+) {
+    let name_path = format!("{}.name", path);
+    validate_dns1123_label(&container.name, &name_path, errors);
+    if !container_names.insert(container.name.as_str()) {
+        errors.push(name_path, format!("Duplicate value: \"{}\"", container.name));
+    }
+
+    if container.image.as_deref().unwrap_or_default().trim().is_empty() {
+        errors.push(format!("{}.image", path), "Required value");
+    }
+
+    let mut port_names = HashSet::new();
+    for (idx, port) in container.ports.iter().flatten().enumerate() {
+        let path = format!("{}.ports[{}]", path, idx);
+        validate_container_port(port, &path, errors);
+        if let Some(name) = &port.name {
+            if !port_names.insert(name.as_str()) {
+                errors.push(format!("{}.name", path), format!("Duplicate value: \"{}\"", name));
+            }
+        }
+    }
+
+    for (idx, mount) in container.volume_mounts.iter().flatten().enumerate() {
+        let path = format!("{}.volumeMounts[{}]", path, idx);
+        if !volumes.contains(mount.name.as_str()) {
+            errors.push(format!("{}.name", path), format!("Not found: \"{}\"", mount.name));
+        }
+        if mount.mount_path.is_empty() {
+            errors.push(format!("{}.mountPath", path), "Required value");
+        }
+    }
+
+    if let Some(resources) = &container.resources {
+        validate_resources(resources, &format!("{}.resources", path), errors);
+    }
+}
+
+fn validate_container_port(port: &ContainerPort, path: &str, errors: &mut FieldErrors) {
+    if let Some(name) = &port.name {
+        validate_port_name(name, &format!("{}.name", path), errors);
+    }
+    validate_port_number(port.container_port, &format!("{}.containerPort", path), errors);
+    if let Some(host_port) = port.host_port {
+        validate_port_number(host_port, &format!("{}.hostPort", path), errors);
+    }
+    if let Some(protocol) = &port.protocol {
+        if !PORT_PROTOCOLS.contains(&protocol.as_str()) {
+            errors.push(
+                format!("{}.protocol", path),
+                format!(
+                    "Unsupported value: \"{}\": supported values: {}",
+                    protocol,
+                    quote_all(&PORT_PROTOCOLS)
+                ),
+            );
+        }
+    }
+}
+
+fn validate_resources(resources: &ResourceRequirements, path: &str, errors: &mut FieldErrors) {
+    let mut limits = vec![];
+    for (name, quantity) in resources.limits.iter().flatten() {
+        if let Some(value) = validate_quantity(quantity, &format!("{}.limits[{}]", path, name), errors) {
+            limits.push((name, value));
+        }
+    }
+
+    for (name, quantity) in resources.requests.iter().flatten() {
+        let path = format!("{}.requests[{}]", path, name);
+        let value = match validate_quantity(quantity, &path, errors) {
+            Some(value) => value,
+            None => continue,
+        };
+        if let Some((_, limit)) = limits.iter().find(|(limit_name, _)| *limit_name == name) {
+            if value > *limit {
+                errors.push(
+                    path,
+                    format!(
+                        "Invalid value: \"{}\": must be less than or equal to {} limit",
+                        quantity.0, name
+                    ),
+                );
+            }
+        }
+    }
+}
+
+fn validate_quantity(quantity: &Quantity, path: &str, errors: &mut FieldErrors) -> Option<f64> {
+    match parse_quantity(quantity) {
+        Ok(value) if value < 0.0 => {
+            errors.push(
+                path,
+                format!(
+                    "Invalid value: \"{}\": must be greater than or equal to 0",
+                    quantity.0
+                ),
+            );
+            None
+        }
+        Ok(value) => Some(value),
+        Err(err) => {
+            errors.push(path, format!("Invalid value: \"{}\": {}", quantity.0, err));
+            None
+        }
+    }
+}
+
+fn validate_dns1123_label(value: &str, path: &str, errors: &mut FieldErrors) {
+    if value.is_empty() {
+        errors.push(path, "Required value");
+    } else if value.len() > 63 || !DNS1123_LABEL.is_match(value) {
+        errors.push(
+            path,
+            format!(
+                "Invalid value: \"{}\": a lowercase RFC 1123 label must consist of lower case alphanumeric \
+                 characters or '-', and must start and end with an alphanumeric character",
+                value
+            ),
+        );
+    }
+}
+
+fn validate_port_name(value: &str, path: &str, errors: &mut FieldErrors) {
+    if value.len() > 15
+        || !DNS1123_LABEL.is_match(value)
+        || value.contains("--")
+        || !value.chars().any(|c| c.is_ascii_lowercase())
+    {
+        errors.push(
+            path,
+            format!(
+                "Invalid value: \"{}\": must be no more than 15 characters, contain only lower case \
+                 alphanumeric characters or '-', contain at least one letter and not contain '--'",
+                value
+            ),
+        );
+    }
+}
+
+fn validate_port_number(port: i32, path: &str, errors: &mut FieldErrors) {
+    if !(1..=65535).contains(&port) {
+        errors.push(
+            path,
+            format!("Invalid value: {}: must be between 1 and 65535, inclusive", port),
+        );
+    }
+}
+
+fn quote_all(values: &[&str]) -> String {
+    values
+        .iter()
+        .map(|v| format!("\"{}\"", v))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::validate_pod_spec;
+    use habitat_api::batch::PodSpec;
+
+    fn pod_spec(value: serde_json::Value) -> PodSpec {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn errors(value: serde_json::Value) -> Vec<String> {
+        validate_pod_spec(&pod_spec(value))
+            .0
+            .iter()
+            .map(|e| e.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_valid_pod_spec() {
+        let spec = pod_spec(serde_json::json!({
+            "restartPolicy": "Never",
+            "volumes": [{"name": "data", "emptyDir": {}}],
+            "containers": [{
+                "name": "main",
+                "image": "busybox",
+                "ports": [{"name": "http", "containerPort": 8080}],
+                "volumeMounts": [{"name": "data", "mountPath": "/data"}],
+                "resources": {"requests": {"cpu": "500m"}, "limits": {"cpu": "1"}}
+            }]
+        }));
+        assert!(validate_pod_spec(&spec).is_empty());
+    }
+
+    #[test]
+    fn test_validate_invalid_pod_spec() {
+        assert_eq!(
+            errors(serde_json::json!({
+                "containers": [{"name": "main", "image": "busybox"}, {"name": "main", "image": "busybox"}]
+            })),
+            vec![r#"spec.containers[1].name: Duplicate value: "main""#]
+        );
+
+        assert_eq!(
+            errors(serde_json::json!({
+                "containers": [{
+                    "name": "main",
+                    "image": "busybox",
+                    "volumeMounts": [{"name": "data", "mountPath": "/data"}]
+                }]
+            })),
+            vec![r#"spec.containers[0].volumeMounts[0].name: Not found: "data""#]
+        );
+
+        assert!(errors(serde_json::json!({
+            "containers": [{"name": "main", "image": "busybox", "ports": [{"containerPort": 70000}]}]
+        }))[0]
+            .starts_with("spec.containers[0].ports[0].containerPort: Invalid value: 70000"));
+
+        assert_eq!(
+            errors(serde_json::json!({
+                "containers": [{
+                    "name": "main",
+                    "image": "busybox",
+                    "resources": {"requests": {"cpu": "2"}, "limits": {"cpu": "1"}}
+                }]
+            })),
+            vec![
+                r#"spec.containers[0].resources.requests[cpu]: Invalid value: "2": must be less than or equal to cpu limit"#
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_collects_all_errors() {
+        assert_eq!(
+            errors(serde_json::json!({
+                "restartPolicy": "Sometimes",
+                "containers": [{"name": "main"}, {"name": "main", "image": "busybox"}]
+            })),
+            vec![
+                "spec.containers[0].image: Required value",
+                r#"spec.containers[1].name: Duplicate value: "main""#,
+                r#"spec.restartPolicy: Unsupported value: "Sometimes": supported values: "Always", "OnFailure", "Never""#,
+            ]
+        );
+    }
+}
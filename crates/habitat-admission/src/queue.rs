@@ -0,0 +1,33 @@
+use habitat_api::{
+    batch::{JobSpec, QueueState},
+    Queue,
+};
+use kube::{Api, Client};
+
+use crate::error::{Denial, DenialReason, FieldErrors};
+
+/// Rejects the job if it targets a queue which doesn't exist or is closed.
+pub async fn validate_queue(spec: &JobSpec, client: Client) -> Result<Denial, kube::Error> {
+    let mut denial = Denial::default();
+    if let Some(name) = &spec.queue {
+        let mut errors = FieldErrors::default();
+        match Api::<Queue>::all(client).get_opt(name).await? {
+            None => {
+                errors.push("spec.queue", format!("Not found: \"{}\"", name));
+                denial.add(DenialReason::QueueMissing, errors);
+            }
+            Some(queue) if queue.spec.state == QueueState::Closed => {
+                errors.push(
+                    "spec.queue",
+                    format!(
+                        "Forbidden: queue `{}` is closed and doesn't accept new jobs",
+                        name
+                    ),
+                );
+                denial.add(DenialReason::QueueClosed, errors);
+            }
+            Some(_) => (),
+        }
+    }
+    Ok(denial)
+}
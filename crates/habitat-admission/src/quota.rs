@@ -0,0 +1,242 @@
+use habitat_api::{
+    batch::JobSpec,
+    resource::{
+        format_quantity, job_min_limits, job_min_requests, pod_limits, pod_requests, to_resource_list,
+        ResourceList,
+    },
+};
+use k8s_openapi::api::core::v1::{Container, LimitRange, LimitRangeItem, ResourceQuota};
+use kube::{api::ListParams, Api, Client};
+
+use crate::error::FieldErrors;
+
+/// Rejects the job if its minimum number of pods can never fit in the ResourceQuotas or the LimitRanges of
+/// the namespace.
+pub async fn validate_quota(spec: &JobSpec, client: Client, ns: &str) -> Result<FieldErrors, kube::Error> {
+    let quotas = Api::<ResourceQuota>::namespaced(client.clone(), ns)
+        .list(&ListParams::default())
+        .await?;
+    let limit_ranges = Api::<LimitRange>::namespaced(client, ns)
+        .list(&ListParams::default())
+        .await?;
+
+    let mut errors = check_resource_quotas(spec, &quotas.items);
+    errors.extend(check_limit_ranges(spec, &limit_ranges.items).0);
+    Ok(errors)
+}
+
+/// Checks the total resources of the job against the hard limits of each ResourceQuota. The usage of the
+/// namespace is not taken into account, as the job could still fit once other workloads complete.
+pub fn check_resource_quotas(spec: &JobSpec, quotas: &[ResourceQuota]) -> FieldErrors {
+    let mut errors = FieldErrors::default();
+    let requests = job_min_requests(spec);
+    let limits = job_min_limits(spec);
+    let pods = spec.tasks.iter().map(|t| t.parallelism.min as f64).sum::<f64>();
+
+    for quota in quotas {
+        let spec = match &quota.spec {
+            // scoped quotas only apply to some of the pods, skip them rather than guess
+            Some(spec) if spec.scopes.is_none() && spec.scope_selector.is_none() => spec,
+            _ => continue,
+        };
+        let hard = spec.hard.as_ref().map(to_resource_list).unwrap_or_default();
+
+        let mut exceeded = vec![];
+        for (name, limited) in &hard {
+            let requested = match name.as_str() {
+                "pods" => Some(pods),
+                "cpu" | "memory" | "ephemeral-storage" => requests.get(name).copied(),
+                name => match (name.strip_prefix("requests."), name.strip_prefix("limits.")) {
+                    (Some(resource), _) => requests.get(resource).copied(),
+                    (_, Some(resource)) => limits.get(resource).copied(),
+                    _ => None,
+                },
+            };
+            if let Some(requested) = requested.filter(|requested| requested > limited) {
+                exceeded.push((name, requested, *limited));
+            }
+        }
+
+        if !exceeded.is_empty() {
+            let join = |f: &dyn Fn(f64, f64) -> f64| {
+                exceeded
+                    .iter()
+                    .map(|(name, requested, limited)| {
+                        format!("{}={}", name, format_quantity(f(*requested, *limited)))
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",")
+            };
+            errors.push(
+                "spec.tasks",
+                format!(
+                    "Forbidden: job can never fit in quota: {}, requested: {}, limited: {}",
+                    quota.metadata.name.as_deref().unwrap_or_default(),
+                    join(&|requested, _| requested),
+                    join(&|_, limited| limited),
+                ),
+            );
+        }
+    }
+
+    errors
+}
+
+/// Checks the resources of each container and pod against the `min` and `max` of the LimitRanges.
+pub fn check_limit_ranges(spec: &JobSpec, limit_ranges: &[LimitRange]) -> FieldErrors {
+    let mut errors = FieldErrors::default();
+    let items = limit_ranges
+        .iter()
+        .filter_map(|limit_range| limit_range.spec.as_ref())
+        .flat_map(|spec| spec.limits.iter());
+
+    for item in items {
+        for (idx, task) in spec.tasks.iter().enumerate() {
+            let path = format!("spec.tasks[{}].template.spec", idx);
+            let pod_spec = &task.template.spec;
+            match item.type_.as_str() {
+                "Container" => {
+                    let containers = pod_spec
+                        .init_containers
+                        .iter()
+                        .flatten()
+                        .enumerate()
+                        .map(|(i, c)| (format!("{}.initContainers[{}]", path, i), c))
+                        .chain(
+                            pod_spec
+                                .containers
+                                .iter()
+                                .enumerate()
+                                .map(|(i, c)| (format!("{}.containers[{}]", path, i), c)),
+                        );
+                    for (path, container) in containers {
+                        let (requests, limits) = container_resources(container);
+                        let field =
+                            |kind: &str, name: &str| format!("{}.resources.{}s[{}]", path, kind, name);
+                        check_limit_range_item(item, &requests, &limits, &field, &mut errors);
+                    }
+                }
+                "Pod" => {
+                    let (requests, limits) = (pod_requests(pod_spec), pod_limits(pod_spec));
+                    let field = |_: &str, _: &str| path.clone();
+                    check_limit_range_item(item, &requests, &limits, &field, &mut errors);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    errors
+}
+
+fn container_resources(container: &Container) -> (ResourceList, ResourceList) {
+    let resources = container.resources.as_ref();
+    let requests = resources.and_then(|r| r.requests.as_ref());
+    let limits = resources.and_then(|r| r.limits.as_ref());
+    (
+        requests.map(to_resource_list).unwrap_or_default(),
+        limits.map(to_resource_list).unwrap_or_default(),
+    )
+}
+
+fn check_limit_range_item(
+    item: &LimitRangeItem,
+    requests: &ResourceList,
+    limits: &ResourceList,
+    field: &dyn Fn(&str, &str) -> String,
+    errors: &mut FieldErrors,
+) {
+    let type_ = &item.type_;
+    for (name, max) in item.max.as_ref().map(to_resource_list).unwrap_or_default() {
+        for (kind, list) in [("request", requests), ("limit", limits)] {
+            if let Some(value) = list.get(&name).filter(|value| **value > max) {
+                errors.push(
+                    field(kind, &name),
+                    format!(
+                        "Forbidden: maximum {} usage per {} is {}, but {} is {}",
+                        name,
+                        type_,
+                        format_quantity(max),
+                        kind,
+                        format_quantity(*value)
+                    ),
+                );
+            }
+        }
+    }
+    for (name, min) in item.min.as_ref().map(to_resource_list).unwrap_or_default() {
+        if let Some(value) = requests.get(&name).filter(|value| **value < min) {
+            errors.push(
+                field("request", &name),
+                format!(
+                    "Forbidden: minimum {} usage per {} is {}, but request is {}",
+                    name,
+                    type_,
+                    format_quantity(min),
+                    format_quantity(*value)
+                ),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_limit_ranges, check_resource_quotas};
+    use habitat_api::batch::JobSpec;
+    use k8s_openapi::api::core::v1::{LimitRange, ResourceQuota};
+
+    fn job_spec() -> JobSpec {
+        serde_json::from_value(serde_json::json!({
+            "tasks": [{
+                "name": "worker",
+                "parallelism": {"min": 4, "max": 8},
+                "template": {"spec": {
+                    "containers": [{
+                        "name": "main",
+                        "image": "busybox",
+                        "resources": {"requests": {"cpu": "2", "memory": "1Gi"}, "limits": {"cpu": "2"}}
+                    }]
+                }}
+            }]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_check_resource_quotas() {
+        let quotas: Vec<ResourceQuota> = serde_json::from_value(serde_json::json!([
+            {"metadata": {"name": "compute"}, "spec": {"hard": {"requests.cpu": "4", "memory": "8Gi", "pods": "10"}}},
+            {"metadata": {"name": "scoped"}, "spec": {"hard": {"pods": "1"}, "scopes": ["BestEffort"]}}
+        ]))
+        .unwrap();
+
+        let errors = check_resource_quotas(&job_spec(), &quotas);
+        assert_eq!(
+            errors.to_string(),
+            "spec.tasks: Forbidden: job can never fit in quota: compute, requested: requests.cpu=8, limited: \
+             requests.cpu=4"
+        );
+    }
+
+    #[test]
+    fn test_check_limit_ranges() {
+        let limit_ranges: Vec<LimitRange> = serde_json::from_value(serde_json::json!([
+            {"metadata": {"name": "limits"}, "spec": {"limits": [
+                {"type": "Container", "max": {"cpu": "1"}},
+                {"type": "Pod", "min": {"memory": "2Gi"}}
+            ]}}
+        ]))
+        .unwrap();
+
+        let errors = check_limit_ranges(&job_spec(), &limit_ranges);
+        assert_eq!(
+            errors.to_string(),
+            "[spec.tasks[0].template.spec.containers[0].resources.requests[cpu]: Forbidden: maximum cpu usage \
+             per Container is 1, but request is 2, spec.tasks[0].template.spec.containers[0].resources.\
+             limits[cpu]: Forbidden: maximum cpu usage per Container is 1, but limit is 2, spec.tasks[0].\
+             template.spec: Forbidden: minimum memory usage per Pod is 2Gi, but request is \
+             1Gi]"
+        );
+    }
+}
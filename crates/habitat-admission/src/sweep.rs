@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+
+use habitat_api::batch::{CompletionMode, JobSpec, SweepSpec};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::error::FieldErrors;
+
+/// The most indexes of a sweep job, each of which creates the pods of all the tasks
+pub const MAX_SWEEP_INDEXES: u32 = 10_000;
+
+lazy_static! {
+    static ref ENV_VAR_NAME: Regex = Regex::new(r"^[-._a-zA-Z][-._a-zA-Z0-9]*$").unwrap();
+}
+
+/// Validates the parameter matrix of a sweep job, whose parameters are injected as environment variables.
+pub fn validate_sweep(spec: &JobSpec, sweep: &SweepSpec) -> FieldErrors {
+    let mut errors = FieldErrors::default();
+    if spec.completion_mode == CompletionMode::Indexed {
+        errors.push(
+            "spec.completionMode",
+            "Invalid value: \"Indexed\": may not be used with `sweep`",
+        );
+    }
+    if sweep.parameters.is_empty() {
+        errors.push("spec.sweep.parameters", "Required value");
+    }
+
+    let mut names = HashSet::new();
+    for (idx, parameter) in sweep.parameters.iter().enumerate() {
+        let path = format!("spec.sweep.parameters[{}]", idx);
+        if !ENV_VAR_NAME.is_match(&parameter.name) {
+            errors.push(
+                format!("{}.name", path),
+                format!(
+                    "Invalid value: {:?}: a valid environment variable name must consist of alphabetic \
+                     characters, digits, '_', '-', or '.', and must not start with a digit",
+                    parameter.name
+                ),
+            );
+        } else if !names.insert(parameter.name.as_str()) {
+            errors.push(
+                format!("{}.name", path),
+                format!("Duplicate value: {:?}", parameter.name),
+            );
+        }
+
+        match (&parameter.values, &parameter.range) {
+            (Some(_), Some(_)) => {
+                errors.push(path, "Invalid value: may not specify both `values` and `range`")
+            }
+            (None, None) => errors.push(path, "Required value: must specify `values` or `range`"),
+            (_, Some(range)) if range.step <= 0 => errors.push(
+                format!("{}.range.step", path),
+                format!("Invalid value: {}: must be greater than 0", range.step),
+            ),
+            _ if parameter.is_empty() => errors.push(path, "Invalid value: the parameter has no value"),
+            _ => (),
+        }
+    }
+
+    match sweep.checked_len() {
+        Some(len) if len <= MAX_SWEEP_INDEXES => (),
+        _ => errors.push(
+            "spec.sweep.parameters",
+            format!("Too many: must have at most {} combinations", MAX_SWEEP_INDEXES),
+        ),
+    }
+
+    if let Some(max_concurrent) = sweep.max_concurrent {
+        // the gang of a job starts with the first indexes, which have to run at the same time
+        let min = spec
+            .tasks
+            .iter()
+            .map(|t| t.parallelism.min)
+            .max()
+            .unwrap_or_default();
+        if max_concurrent < min.max(1) {
+            errors.push(
+                "spec.sweep.maxConcurrent",
+                format!(
+                    "Invalid value: {}: must be at least the parallelism.min of every task ({})",
+                    max_concurrent,
+                    min.max(1)
+                ),
+            );
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod test {
+    use super::validate_sweep;
+    use habitat_api::batch::{JobSpec, SweepSpec};
+
+    fn errors(sweep: serde_json::Value) -> Vec<String> {
+        let spec = serde_json::from_value::<JobSpec>(serde_json::json!({
+            "tasks": [{"name": "worker", "parallelism": {"min": 2}, "template": {"spec": {"containers": []}}}]
+        }))
+        .unwrap();
+        let sweep = serde_json::from_value::<SweepSpec>(sweep).unwrap();
+        validate_sweep(&spec, &sweep)
+            .0
+            .iter()
+            .map(|e| e.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_sweep() {
+        assert!(errors(serde_json::json!({
+            "parameters": [{"name": "LR", "values": ["0.1", "0.01"]}],
+            "maxConcurrent": 2
+        }))
+        .is_empty());
+
+        assert_eq!(
+            errors(serde_json::json!({
+                "parameters": [
+                    {"name": "1LR", "values": ["0.1"]},
+                    {"name": "EPOCHS", "range": {"start": 1, "stop": 10, "step": 0}},
+                    {"name": "EPOCHS", "values": []}
+                ],
+                "maxConcurrent": 1
+            })),
+            vec![
+                "spec.sweep.parameters[0].name: Invalid value: \"1LR\": a valid environment variable name must \
+                 consist of alphabetic characters, digits, '_', '-', or '.', and must not start with a digit",
+                "spec.sweep.parameters[1].range.step: Invalid value: 0: must be greater than 0",
+                "spec.sweep.parameters[2].name: Duplicate value: \"EPOCHS\"",
+                "spec.sweep.parameters[2]: Invalid value: the parameter has no value",
+                "spec.sweep.maxConcurrent: Invalid value: 1: must be at least the parallelism.min of every task (2)",
+            ]
+        );
+
+        assert_eq!(
+            errors(serde_json::json!({
+                "parameters": [
+                    {"name": "SEED", "range": {"start": 0, "stop": 1000}},
+                    {"name": "EPOCHS", "range": {"start": 0, "stop": 1000}}
+                ]
+            })),
+            vec!["spec.sweep.parameters: Too many: must have at most 10000 combinations"]
+        );
+    }
+}
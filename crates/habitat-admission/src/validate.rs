@@ -1,5 +1,8 @@
-use axum::Json;
-use habitat_api::{batch::TaskSpec, Job};
+use axum::{extract::State, Json};
+use habitat_api::{
+    batch::{ExitCodesOperator, PodFailurePolicy, TaskSpec},
+    Job,
+};
 use k8s_openapi::api::core::v1::Pod;
 use kube::{
     core::{
@@ -7,45 +10,83 @@ use kube::{
         params::PostParams,
         DynamicObject, ObjectMeta, ResourceExt,
     },
-    Api, Client,
+    Api,
 };
+use lazy_static::lazy_static;
+use regex::Regex;
 use std::error::Error;
 use tracing::*;
 
-
-use crate::util::try_cast_dynamic_obj_into_job;
+use crate::{
+    error::{Denial, DenialReason, FieldError, FieldErrors},
+    pod::validate_pod_spec,
+    queue::validate_queue,
+    quota::validate_quota,
+    sweep::validate_sweep,
+    util::try_cast_dynamic_obj_into_job,
+    AdmissionState,
+};
 
 pub async fn handler(
+    State(state): State<AdmissionState>,
     Json(body): Json<AdmissionReview<DynamicObject>>,
 ) -> Json<AdmissionReview<DynamicObject>> {
+    let metrics = &state.metrics;
+    let _timer = metrics.count_and_measure("validate");
     // Parse incoming webhook AdmissionRequest first
     let req: AdmissionRequest<_> = match body.try_into() {
         Ok(req) => req,
         Err(err) => {
             error!("invalid request: {}", err.to_string());
+            metrics.decision("validate", false, "InvalidRequest");
             return Json(AdmissionResponse::invalid(err.to_string()).into_review());
         }
     };
-    let client = kube::Client::try_default().await.unwrap();
 
     // Then construct a AdmissionResponse
     let mut res = AdmissionResponse::from(&req);
     // req.Object always exists for us, but could be None if extending to DELETE events
     if let Some(obj) = req.object {
-        let name = obj.name_any(); // apiserver may not have generated a name yet
-        res = match try_cast_dynamic_obj_into_job(&obj) {
-            Ok(job) => match validate(res.clone(), &job, client).await {
+        // apiserver may not have generated a name yet
+        let name = obj.name_any();
+        // the object may not carry its namespace yet, fall back to the one of the request
+        let ns = obj.namespace().or(req.namespace);
+        // the old object only exists for UPDATE events
+        let old_job = req
+            .old_object
+            .as_ref()
+            .and_then(|old| try_cast_dynamic_obj_into_job(old).ok());
+        res = match (try_cast_dynamic_obj_into_job(&obj), ns) {
+            (Ok(job), Some(ns)) => match validate(res.clone(), &job, old_job.as_ref(), &state, &ns).await {
                 Ok(res) => {
                     info!("accepted: {:?} on Job {}", req.operation, name);
+                    metrics.decision("validate", true, "Accepted");
                     res
                 }
                 Err(err) => {
                     warn!("denied: {:?} on {} ({})", req.operation, name, err);
-                    res.deny(err.to_string())
+                    match err.downcast_ref::<Denial>() {
+                        Some(denial) => {
+                            let reason = denial.reason.map_or("Invalid", |reason| reason.as_str());
+                            metrics.decision("validate", false, reason);
+                            denial.errors.deny(res)
+                        }
+                        None => {
+                            metrics.decision("validate", false, "InternalError");
+                            res.deny(err.to_string())
+                        }
+                    }
                 }
             },
-            Err(err) => {
+            (Ok(_), None) => {
+                warn!("invalid job: {:?} on {} (missing namespace)", req.operation, name);
+                metrics.decision("validate", false, "MissingNamespace");
+                res.deny("unable to determine the namespace of the job")
+            }
+            (Err(err), _) => {
                 warn!("invalid job: {:?} on {} ({})", req.operation, name, err);
+                metrics.decode_errors.with_label_values(&["validate"]).inc();
+                metrics.decision("validate", false, "DecodeError");
                 res.deny(err)
             }
         };
@@ -56,29 +97,75 @@ pub async fn handler(
 
 // The main handler and core business logic, failures here implies rejected applies
 async fn validate(
-    res: AdmissionResponse,
+    mut res: AdmissionResponse,
     obj: &Job,
-    client: Client,
+    old_obj: Option<&Job>,
+    state: &AdmissionState,
+    ns: &str,
 ) -> Result<AdmissionResponse, Box<dyn Error>> {
+    let mut denial = Denial::default();
+    let mut errors = FieldErrors::default();
     if obj.spec.tasks.is_empty() {
-        return Err("no task specified".into());
+        errors.push("spec.tasks", "Required value: no task specified");
+    }
+    denial.add(DenialReason::PodSpec, errors);
+
+    let mut errors = FieldErrors::default();
+    for (idx, dependency) in obj.spec.depends_on.iter().enumerate() {
+        if dependency.name == obj.name_any() {
+            errors.push(
+                format!("spec.dependsOn[{}].name", idx),
+                format!(
+                    "Invalid value: {:?}: a job can't depend on itself",
+                    dependency.name
+                ),
+            );
+        }
+    }
+
+    denial.add(DenialReason::Dependency, errors);
+
+    if let Some(sweep) = &obj.spec.sweep {
+        denial.add(DenialReason::Sweep, validate_sweep(&obj.spec, sweep));
     }
 
-    let pods: Api<Pod> = Api::namespaced(client, &obj.namespace().unwrap());
+    let mut errors = FieldErrors::default();
+    let pods: Api<Pod> = Api::namespaced(state.client.clone(), ns);
 
-    // If the task parallelism.min > parallelism.max, we reject it.
     for (idx, task) in obj.spec.tasks.iter().enumerate() {
+        let prefix = format!("spec.tasks[{}].", idx);
+
+        // If the task parallelism.min > parallelism.max, we reject it.
         if task.parallelism.min > task.parallelism.max {
-            return Err(format!(
-                "task `{}` parallelism.min can't greater than parallelism.max",
-                task.name
-            )
-            .into());
+            errors.push(
+                format!("{}parallelism.min", prefix),
+                format!(
+                    "Invalid value: {}: task `{}` parallelism.min can't greater than parallelism.max",
+                    task.parallelism.min, task.name
+                ),
+            );
+        }
+
+        if let Some(policy) = &task.pod_failure_policy {
+            validate_pod_failure_policy(policy, &format!("{}podFailurePolicy.", prefix), &mut errors);
+        }
+
+        let template_prefix = format!("{}template.", prefix);
+        let template_errors = validate_pod_spec(&task.template.spec);
+        if !template_errors.is_empty() || !state.server_dry_run {
+            errors.extend(
+                template_errors
+                    .0
+                    .into_iter()
+                    .map(|e| e.with_prefix(&template_prefix)),
+            );
+            continue;
         }
 
         // create a template pod and validate it in the server side
-        let pod = new_template_pod(task);
-        if let Err(err) = pods
+        let pod = new_template_pod(task)?;
+        let timer = state.metrics.dry_run_duration.start_timer();
+        let created = pods
             .create(
                 &PostParams {
                     dry_run: true,
@@ -86,28 +173,129 @@ async fn validate(
                 },
                 &pod,
             )
-            .await
-        {
-            return match err {
+            .await;
+        timer.observe_duration();
+        if let Err(err) = created {
+            match err {
                 kube::Error::Api(err) => {
-                    let message = format!(
-                        "spec.tasks[{}].template.{}",
-                        idx,
-                        err.message
-                            .trim_start_matches(&format!("Pod \"{}\" is invalid: ", pod.name_any()))
+                    let message = err
+                        .message
+                        .trim_start_matches(&format!("Pod \"{}\" is invalid: ", pod.name_any()));
+                    errors.extend(
+                        split_api_errors(message)
+                            .into_iter()
+                            .map(|e| e.with_prefix(&template_prefix)),
                     );
-                    Err(message.into())
                 }
-                _ => Err(err.into()),
-            };
+                _ => return Err(format!("failed to validate task `{}`: {}", task.name, err).into()),
+            }
+        }
+    }
+
+    // the resources of the job only make sense once every task is valid, and only change with its spec
+    denial.add(DenialReason::PodSpec, errors);
+    if denial.is_empty() && spec_changed(obj, old_obj) {
+        match validate_quota(&obj.spec, state.client.clone(), ns).await {
+            Ok(errors) => denial.add(DenialReason::Quota, errors),
+            // the quotas only reject early the jobs whose pods would be rejected, so don't block the job
+            Err(err) => {
+                warn!("failed to check the quotas of job {}: {}", obj.name_any(), err);
+                res.warnings.get_or_insert_with(Vec::new).push(format!(
+                    "the resource quotas of the namespace were not checked: {}",
+                    err
+                ));
+            }
         }
     }
 
-    Ok(res)
+    // only check the queue when it's assigned, so jobs already in a closed queue can still be updated
+    if old_obj.map(|old| &old.spec.queue) != Some(&obj.spec.queue) {
+        denial.merge(validate_queue(&obj.spec, state.client.clone()).await?);
+    }
+
+    if denial.is_empty() {
+        Ok(res)
+    } else {
+        Err(denial.into())
+    }
 }
 
-fn new_template_pod(task_spec: &TaskSpec) -> Pod {
-    Pod {
+/// Whether the job is created, or its spec is updated.
+fn spec_changed(obj: &Job, old_obj: Option<&Job>) -> bool {
+    match old_obj {
+        Some(old) => serde_json::to_value(&old.spec).ok() != serde_json::to_value(&obj.spec).ok(),
+        None => true,
+    }
+}
+
+fn validate_pod_failure_policy(policy: &PodFailurePolicy, prefix: &str, errors: &mut FieldErrors) {
+    for (idx, rule) in policy.rules.iter().enumerate() {
+        let path = format!("{}rules[{}]", prefix, idx);
+        match (&rule.on_exit_codes, rule.on_pod_conditions.is_empty()) {
+            (Some(_), false) => errors.push(
+                path,
+                "Invalid value: may not specify both `onExitCodes` and `onPodConditions`",
+            ),
+            (None, true) => errors.push(
+                path,
+                "Required value: must specify `onExitCodes` or `onPodConditions`",
+            ),
+            (Some(requirement), true) => {
+                if requirement.values.is_empty() {
+                    errors.push(format!("{}.onExitCodes.values", path), "Required value");
+                }
+                if requirement.operator == ExitCodesOperator::In && requirement.values.contains(&0) {
+                    errors.push(
+                        format!("{}.onExitCodes.values", path),
+                        "Invalid value: 0: must not be 0 for the In operator",
+                    );
+                }
+            }
+            (None, false) => {
+                for (idx, pattern) in rule.on_pod_conditions.iter().enumerate() {
+                    let path = format!("{}.onPodConditions[{}]", path, idx);
+                    if pattern.type_.is_empty() {
+                        errors.push(format!("{}.type", path), "Required value");
+                    }
+                    if !matches!(pattern.status.as_str(), "True" | "False" | "Unknown") {
+                        errors.push(
+                            format!("{}.status", path),
+                            format!(
+                                "Unsupported value: {:?}: supported values: \"True\", \"False\", \"Unknown\"",
+                                pattern.status
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Splits an aggregated api server error message like `[spec.a: Required value, spec.b: Invalid value]`
+/// into its field errors.
+fn split_api_errors(message: &str) -> Vec<FieldError> {
+    lazy_static! {
+        static ref FIELD_START: Regex = Regex::new(r", ([A-Za-z][\w.\[\]-]*): ").unwrap();
+    }
+
+    let message = match message.strip_prefix('[').and_then(|m| m.strip_suffix(']')) {
+        Some(message) => message,
+        None => return vec![FieldError::parse(message)],
+    };
+
+    let mut entries = vec![];
+    let mut start = 0;
+    for m in FIELD_START.find_iter(message) {
+        entries.push(&message[start..m.start()]);
+        start = m.start() + 2;
+    }
+    entries.push(&message[start..]);
+    entries.into_iter().map(FieldError::parse).collect()
+}
+
+fn new_template_pod(task_spec: &TaskSpec) -> Result<Pod, serde_json::Error> {
+    Ok(Pod {
         metadata: ObjectMeta {
             name: Some(task_spec.name.clone()),
             labels: task_spec
@@ -122,7 +310,79 @@ fn new_template_pod(task_spec: &TaskSpec) -> Pod {
                 .and_then(|m| m.annotations.clone()),
             ..Default::default()
         },
-        spec: Some(serde_json::from_str(&serde_json::to_string(&task_spec.template.spec).unwrap()).unwrap()),
+        spec: Some(serde_json::from_value(serde_json::to_value(
+            &task_spec.template.spec,
+        )?)?),
         ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{spec_changed, split_api_errors, validate_pod_failure_policy};
+    use crate::error::{FieldError, FieldErrors};
+    use habitat_api::{batch::PodFailurePolicy, Job};
+
+    #[test]
+    fn test_split_api_errors() {
+        assert_eq!(
+            split_api_errors("spec.containers[0].image: Required value"),
+            vec![FieldError::new("spec.containers[0].image", "Required value")]
+        );
+        assert_eq!(
+            split_api_errors(
+                r#"[spec.containers[0].image: Required value, spec.restartPolicy: Unsupported value: "x": supported values: "Always", "Never"]"#
+            ),
+            vec![
+                FieldError::new("spec.containers[0].image", "Required value"),
+                FieldError::new(
+                    "spec.restartPolicy",
+                    r#"Unsupported value: "x": supported values: "Always", "Never""#
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_pod_failure_policy() {
+        let policy = serde_json::from_value::<PodFailurePolicy>(serde_json::json!({
+            "rules": [
+                {"action": "Ignore", "onPodConditions": [{"type": "DisruptionTarget"}]},
+                {"action": "Ignore", "onPodConditions": [{"type": "", "status": "true"}]}
+            ]
+        }))
+        .unwrap();
+        let mut errors = FieldErrors::default();
+        validate_pod_failure_policy(&policy, "spec.tasks[0].podFailurePolicy.", &mut errors);
+        assert_eq!(errors.0, vec![
+            FieldError::new(
+                "spec.tasks[0].podFailurePolicy.rules[1].onPodConditions[0].type",
+                "Required value"
+            ),
+            FieldError::new(
+                "spec.tasks[0].podFailurePolicy.rules[1].onPodConditions[0].status",
+                r#"Unsupported value: "true": supported values: "True", "False", "Unknown""#
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_spec_changed() {
+        let job = serde_json::from_value::<Job>(serde_json::json!({
+            "apiVersion": "batch.habitat/v1beta1",
+            "kind": "Job",
+            "metadata": {"name": "train", "namespace": "default"},
+            "spec": {"tasks": []}
+        }))
+        .unwrap();
+        assert!(spec_changed(&job, None));
+
+        // the updates of the status or the metadata don't check the quotas again
+        let mut updated = job.clone();
+        updated.metadata.labels = Some([("team".to_string(), "a".to_string())].into());
+        assert!(!spec_changed(&updated, Some(&job)));
+
+        updated.spec.queue = Some("default".to_string());
+        assert!(spec_changed(&updated, Some(&job)));
     }
 }
@@ -1,7 +1,49 @@
-use kube::CustomResource;
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::core::v1::Pod;
+use kube::{CustomResource, ResourceExt};
 use schemars::JsonSchema;
 use serde::{de, Deserialize, Serialize};
 
+mod cron_job;
+mod failure_policy;
+mod indexes;
+mod job_template;
+mod labels;
+mod queue;
+mod sweep;
+
+pub use cron_job::{ConcurrencyPolicy, CronJob, CronJobSpec, CronJobStatus, JobTemplateSpec, CRON_JOB_LABEL};
+pub use failure_policy::{
+    ExitCodesOperator, OnExitCodesRequirement, OnPodConditionsPattern, PodFailurePolicy,
+    PodFailurePolicyAction, PodFailurePolicyRule,
+};
+pub use indexes::{format_index_ranges, parse_index_ranges};
+pub use job_template::{JobTemplate, TemplateParameter, TemplateSpec};
+pub use labels::LabelNames;
+pub use queue::{Queue, QueueSpec, QueueState, QueueStatus};
+pub use sweep::{SweepIndexPhase, SweepIndexStatus, SweepParameter, SweepRange, SweepSpec, SWEEP_INDEX_ENV};
+
+/// The default label of the pods created for a job, see [`LabelNames::task_owner`]
+pub const TASK_OWNER_LABEL: &str = "habitat-task-owner";
+/// The default label of the pods created for a job, see [`LabelNames::task_name`]
+pub const TASK_NAME_LABEL: &str = "habitat-task";
+/// The default label of the pods created for a job, see [`LabelNames::replica_index`]
+pub const REPLICA_INDEX_LABEL: &str = "habitat-replica-index";
+/// The condition of a job admitted by its queue
+pub const ADMITTED_CONDITION: &str = "Admitted";
+/// The condition of a job held in `Pending` until the jobs it depends on complete
+pub const WAITING_FOR_DEPENDENCIES_CONDITION: &str = "WaitingForDependencies";
+
+/// The replica index of a pod created for a job. The pods created before the index label was introduced
+/// only carry it as the suffix of their name.
+pub fn replica_index(pod: &Pod, labels: &LabelNames) -> Option<u32> {
+    match pod.labels().get(&labels.replica_index) {
+        Some(index) => index.parse().ok(),
+        None => pod.name_any().rsplit('-').next()?.parse().ok(),
+    }
+}
+
 #[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 #[kube(
@@ -17,6 +59,7 @@ use serde::{de, Deserialize, Serialize};
     printcolumn = r#"{"name": "Terminating", "jsonPath": ".status.terminating", "type": "integer", "priority": 1}"#,
     printcolumn = r#"{"name": "Succeeded", "jsonPath": ".status.succeeded", "type": "integer", "priority": 1}"#,
     printcolumn = r#"{"name": "Failed", "jsonPath": ".status.failed", "type": "integer", "priority": 1}"#,
+    printcolumn = r#"{"name": "Queue", "jsonPath": ".spec.queue", "type": "string", "priority": 1}"#,
     printcolumn = r#"{"name": "Status", "jsonPath": ".status.phase", "type": "string", "priority": 0}"#,
     printcolumn = r#"{"name": "Age", "jsonPath": ".metadata.creationTimestamp", "type": "date", "priority": 0}"#
 )]
@@ -28,8 +71,130 @@ pub struct JobSpec {
     /// If specified, indicates the Job's priority.
     pub priority: Option<Priority>,
 
-    /// The task specifications.
+    /// If specified, the job will be admitted by the specified queue.
+    /// If not specified, the job doesn't belong to any queue.
+    pub queue: Option<String>,
+
+    /// The expected running time of the job in seconds. Jobs with a known running time may be backfilled
+    /// ahead of a larger job waiting for resources, if they complete before it can start. If not specified,
+    /// the `activeDeadlineSeconds` of the tasks are used.
+    pub estimated_duration: Option<u64>,
+
+    /// The jobs in the same namespace which have to complete before this job is admitted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<JobDependency>,
+
+    /// The name of a `JobTemplate` in the same namespace the tasks are instantiated from. The other fields
+    /// default to the ones of the template.
+    pub template_ref: Option<String>,
+
+    /// The values of the template parameters.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub parameters: BTreeMap<String, String>,
+
+    /// The task specifications. Instantiated from the template if `templateRef` is specified.
+    #[serde(default)]
     pub tasks: Vec<TaskSpec>,
+
+    /// How the completion of the job is tracked.
+    #[serde(default)]
+    pub completion_mode: CompletionMode,
+
+    /// The most times a failed replica index runs again in `Indexed` completion mode, 6 by default. The job
+    /// fails once an index fails beyond it.
+    #[serde(default = "default_backoff_limit_per_index")]
+    pub backoff_limit_per_index: u32,
+
+    /// If specified, every task runs one pod for each combination of the swept parameters instead of up to
+    /// `parallelism.max` replicas. The pod of the index `i` is named `<job>-<task>-<i>`.
+    pub sweep: Option<SweepSpec>,
+}
+
+fn default_backoff_limit_per_index() -> u32 {
+    6
+}
+
+impl JobSpec {
+    /// The expected running time of the job in seconds, either estimated or bounded by the
+    /// `activeDeadlineSeconds` of every task.
+    pub fn expected_duration(&self) -> Option<u64> {
+        if self.estimated_duration.is_some() {
+            return self.estimated_duration;
+        }
+        self.tasks
+            .iter()
+            .map(|task| {
+                task.template
+                    .spec
+                    .active_deadline_seconds
+                    .and_then(|s| u64::try_from(s).ok())
+            })
+            .try_fold(0, |max, deadline| deadline.map(|d| max.max(d)))
+            .filter(|_| !self.tasks.is_empty())
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+pub enum CompletionMode {
+    /// NonIndexed means the job completes once its pods complete.
+    NonIndexed,
+    /// Indexed means each replica index up to `parallelism.max` of every task is a unit of work which has
+    /// to succeed exactly once. The indexes below `parallelism.min` run once the job is admitted, and the
+    /// other ones as the queue of the job admits them. The failed indexes run again up to
+    /// `backoffLimitPerIndex` times.
+    Indexed,
+}
+
+impl Default for CompletionMode {
+    fn default() -> Self {
+        Self::NonIndexed
+    }
+}
+
+/// A job which has to complete before another one starts
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct JobDependency {
+    /// The name of the job, in the same namespace.
+    pub name: String,
+
+    /// The phase the job has to complete with, `Succeeded` by default.
+    #[serde(default)]
+    pub phase: DependencyPhase,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+pub enum DependencyPhase {
+    /// The job has to succeed.
+    Succeeded,
+    /// The job has to fail, or to be terminated.
+    Failed,
+    /// The job has to complete, whatever its phase.
+    Any,
+}
+
+impl Default for DependencyPhase {
+    fn default() -> Self {
+        Self::Succeeded
+    }
+}
+
+impl DependencyPhase {
+    /// Whether a job in the given phase satisfies the dependency. Returns `None` while the job is not
+    /// completed yet.
+    pub fn is_satisfied_by(&self, phase: &JobStatusPhase) -> Option<bool> {
+        match (self, phase) {
+            (
+                _,
+                JobStatusPhase::Pending
+                | JobStatusPhase::Ready
+                | JobStatusPhase::Running
+                | JobStatusPhase::Terminating,
+            ) => None,
+            (Self::Any, _) => Some(true),
+            (Self::Succeeded, phase) => Some(*phase == JobStatusPhase::Succeeded),
+            (Self::Failed, phase) => Some(*phase != JobStatusPhase::Succeeded),
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
@@ -40,11 +205,17 @@ pub struct JobStatus {
 
     /// Represents the time when the job status phase became `Running`. It is represented in RFC3339 form
     /// and is in UTC.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub start_time: Option<k8s_openapi::apimachinery::pkg::apis::meta::v1::Time>,
 
     /// Represents the time when the job was completed (). It is represented in RFC3339 form and is in UTC.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub completion_time: Option<k8s_openapi::apimachinery::pkg::apis::meta::v1::Time>,
 
+    /// The generation of the job spec the status was written for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub observed_generation: Option<i64>,
+
     /// The number of pods which reached phase `Pending`.
     pub pending: u32,
 
@@ -59,14 +230,80 @@ pub struct JobStatus {
 
     /// The number of pods which reached phase `Failed`.    
     pub failed: u32,
+
+    /// The latest available observations of the job's current state.
+    #[serde(default)]
+    pub conditions: Vec<JobCondition>,
+
+    /// The replica indexes which succeeded in `Indexed` completion mode, as compressed ranges by task name,
+    /// e.g. `1-3,5,7-9`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub completed_indexes: BTreeMap<String, String>,
+
+    /// The results of the sweep indexes which have started.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sweep: Vec<SweepIndexStatus>,
+
+    /// The number of times the failed replica indexes ran again in `Indexed` completion mode, by task name
+    /// then index.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub index_retries: BTreeMap<String, BTreeMap<u32, u32>>,
+
+    /// The number of times the pods of every task were restarted by its pod failure policy, by task name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub restarts: BTreeMap<String, u32>,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+impl JobStatus {
+    /// Returns the condition of the given type.
+    pub fn condition(&self, type_: &str) -> Option<&JobCondition> {
+        self.conditions.iter().find(|c| c.type_ == type_)
+    }
+
+    /// Adds or replaces the condition of the same type. The last transition time is kept if the status of
+    /// the condition doesn't change.
+    pub fn set_condition(&mut self, mut condition: JobCondition) {
+        match self.conditions.iter_mut().find(|c| c.type_ == condition.type_) {
+            Some(current) => {
+                if current.status == condition.status {
+                    condition.last_transition_time = current.last_transition_time.clone();
+                }
+                *current = condition;
+            }
+            None => self.conditions.push(condition),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct JobCondition {
+    /// Type of job condition, e.g. `Admitted`.
+    #[serde(rename = "type")]
+    pub type_: String,
+
+    /// Status of the condition, one of `True`, `False`, `Unknown`.
+    pub status: String,
+
+    /// Last time the condition transitioned from one status to another.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_transition_time: Option<k8s_openapi::apimachinery::pkg::apis::meta::v1::Time>,
+
+    /// Unique, one-word, CamelCase reason for the condition's last transition.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+
+    /// Human-readable message indicating details about last transition.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 pub enum JobStatusPhase {
-    /// Pending means the job has been accepted by the system, but one or more of pods has not been
-    /// scheduled.
+    /// Pending means the job has been accepted by the system, but is waiting for its queue to admit it.
     Pending,
-    /// Ready means the scheduler approves the controller to create pods.
+    /// Ready means the scheduler approves the controller to create pods, but one or more of pods has not
+    /// been scheduled.
     Ready,
     /// Running means that if the job contains any `Running` pod, its status will be `Running`.
     Running,
@@ -96,6 +333,10 @@ pub struct TaskSpec {
 
     /// The pod template
     pub template: PodTemplate,
+
+    /// How the failed pods of the task are handled. If not specified, they are counted as failed.
+    #[serde(rename = "podFailurePolicy")]
+    pub pod_failure_policy: Option<PodFailurePolicy>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
@@ -120,13 +361,15 @@ pub struct PodMeta {
     pub labels: Option<std::collections::BTreeMap<String, String>>,
 }
 
+// the field docs are copied from the kubernetes api as is
+#[allow(clippy::doc_lazy_continuation)]
 #[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct PodSpec {
     /// Optional duration in seconds the pod may be active on the node relative
     /// to StartTime before the system will actively try to mark it failed and
     /// kill associated containers. Value must be a positive integer.
-    // pub active_deadline_seconds: Option<i64>,
+    pub active_deadline_seconds: Option<i64>,
 
     /// If specified, the pod's scheduling constraints
     // pub affinity: Option<k8s_openapi::api::core::v1::Affinity>,
@@ -430,7 +673,7 @@ impl JsonSchema for Priority {
 
 #[cfg(test)]
 mod test {
-    use super::Priority;
+    use super::{DependencyPhase, JobDependency, JobSpec, JobStatusPhase, Priority};
 
     #[test]
     fn test_priority_deserialize_sting() {
@@ -465,4 +708,51 @@ mod test {
             r#""high""#
         );
     }
+
+    #[test]
+    fn test_expected_duration() {
+        let spec = |estimated: Option<u64>, deadlines: &[Option<i64>]| -> JobSpec {
+            serde_json::from_value(serde_json::json!({
+                "estimatedDuration": estimated,
+                "tasks": deadlines.iter().enumerate().map(|(i, deadline)| serde_json::json!({
+                    "name": format!("task{}", i),
+                    "parallelism": {},
+                    "template": {"spec": {"containers": [], "activeDeadlineSeconds": deadline}}
+                })).collect::<Vec<_>>()
+            }))
+            .unwrap()
+        };
+
+        assert_eq!(spec(Some(60), &[Some(600)]).expected_duration(), Some(60));
+        assert_eq!(spec(None, &[Some(600), Some(300)]).expected_duration(), Some(600));
+        assert_eq!(spec(None, &[Some(600), None]).expected_duration(), None);
+        assert_eq!(spec(None, &[]).expected_duration(), None);
+    }
+
+    #[test]
+    fn test_dependency_phase() {
+        let dependency = serde_json::from_str::<JobDependency>(r#"{"name": "prepare"}"#).unwrap();
+        assert_eq!(dependency.phase, DependencyPhase::Succeeded);
+
+        assert_eq!(
+            DependencyPhase::Any.is_satisfied_by(&JobStatusPhase::Running),
+            None
+        );
+        assert_eq!(
+            DependencyPhase::Succeeded.is_satisfied_by(&JobStatusPhase::Succeeded),
+            Some(true)
+        );
+        assert_eq!(
+            DependencyPhase::Succeeded.is_satisfied_by(&JobStatusPhase::Failed),
+            Some(false)
+        );
+        assert_eq!(
+            DependencyPhase::Failed.is_satisfied_by(&JobStatusPhase::Terminated),
+            Some(true)
+        );
+        assert_eq!(
+            DependencyPhase::Any.is_satisfied_by(&JobStatusPhase::Failed),
+            Some(true)
+        );
+    }
 }
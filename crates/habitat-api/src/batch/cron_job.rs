@@ -0,0 +1,117 @@
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{JobSpec, PodMeta};
+
+/// The default label of the jobs created for a cron job, see
+/// [`LabelNames::cron_job`](super::LabelNames::cron_job)
+pub const CRON_JOB_LABEL: &str = "habitat-cron-job";
+
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[kube(
+    namespaced,
+    kind = "CronJob",
+    group = "batch.habitat",
+    version = "v1beta1",
+    shortname = "hcj",
+    shortname = "hcronjob",
+    status = "CronJobStatus",
+    printcolumn = r#"{"name": "Schedule", "jsonPath": ".spec.schedule", "type": "string", "priority": 0}"#,
+    printcolumn = r#"{"name": "Concurrency", "jsonPath": ".spec.concurrencyPolicy", "type": "string", "priority": 1}"#,
+    printcolumn = r#"{"name": "Last Schedule", "jsonPath": ".status.lastScheduleTime", "type": "date", "priority": 0}"#,
+    printcolumn = r#"{"name": "Age", "jsonPath": ".metadata.creationTimestamp", "type": "date", "priority": 0}"#
+)]
+pub struct CronJobSpec {
+    /// The schedule in cron format, e.g. `*/10 * * * *`, evaluated in UTC.
+    pub schedule: String,
+
+    /// Deadline in seconds for starting the job if it misses its scheduled time for any reason, e.g. when
+    /// the previous run is still active. Runs missing their deadline are skipped. If not specified, missed
+    /// runs are started as soon as possible.
+    pub starting_deadline_seconds: Option<u64>,
+
+    /// Specifies how to treat concurrent executions of a job.
+    #[serde(default)]
+    pub concurrency_policy: ConcurrencyPolicy,
+
+    /// The number of successful finished jobs to retain.
+    #[serde(default = "default_successful_jobs_history_limit")]
+    pub successful_jobs_history_limit: u32,
+
+    /// The number of failed finished jobs to retain.
+    #[serde(default = "default_failed_jobs_history_limit")]
+    pub failed_jobs_history_limit: u32,
+
+    /// The job that will be created when executing the cron job.
+    pub job_template: JobTemplateSpec,
+}
+
+fn default_successful_jobs_history_limit() -> u32 {
+    3
+}
+
+fn default_failed_jobs_history_limit() -> u32 {
+    1
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+pub enum ConcurrencyPolicy {
+    /// Allow allows the jobs to run concurrently.
+    Allow,
+    /// Forbid skips the next run if the previous one hasn't finished yet.
+    Forbid,
+    /// Replace cancels the currently running job and replaces it with a new one.
+    Replace,
+}
+
+impl Default for ConcurrencyPolicy {
+    fn default() -> Self {
+        Self::Allow
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub struct JobTemplateSpec {
+    /// Metadata of the jobs created from this template
+    pub metadata: Option<PodMeta>,
+
+    /// Specification of the desired behavior of the job.
+    pub spec: JobSpec,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CronJobStatus {
+    /// The names of the currently running jobs.
+    #[serde(default)]
+    pub active: Vec<String>,
+
+    /// The last time the job was successfully scheduled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_schedule_time: Option<Time>,
+
+    /// The last time the job successfully completed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_successful_time: Option<Time>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ConcurrencyPolicy, CronJobSpec};
+
+    #[test]
+    fn test_cron_job_defaults() {
+        let spec = serde_json::from_value::<CronJobSpec>(serde_json::json!({
+            "schedule": "*/5 * * * *",
+            "jobTemplate": {"spec": {"tasks": []}}
+        }))
+        .unwrap();
+        assert_eq!(spec.concurrency_policy, ConcurrencyPolicy::Allow);
+        assert_eq!(spec.successful_jobs_history_limit, 3);
+        assert_eq!(spec.failed_jobs_history_limit, 1);
+        assert_eq!(spec.starting_deadline_seconds, None);
+    }
+}
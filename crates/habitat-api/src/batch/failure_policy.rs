@@ -0,0 +1,204 @@
+use k8s_openapi::api::core::v1::Pod;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// How the failed pods of a task are handled
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PodFailurePolicy {
+    /// The rules evaluated in order against a failed pod. The first matching rule applies. The pods not
+    /// matching any rule are counted as failed.
+    pub rules: Vec<PodFailurePolicyRule>,
+
+    /// The most times the pods of the task are restarted by the `RestartTask` rules, 6 by default. The job
+    /// fails once the limit is exceeded. The pods deleted by the `Ignore` rules aren't counted.
+    #[serde(default = "default_restart_limit")]
+    pub restart_limit: u32,
+}
+
+impl Default for PodFailurePolicy {
+    fn default() -> Self {
+        Self {
+            rules: vec![],
+            restart_limit: default_restart_limit(),
+        }
+    }
+}
+
+fn default_restart_limit() -> u32 {
+    6
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PodFailurePolicyRule {
+    /// The action taken when the rule matches.
+    pub action: PodFailurePolicyAction,
+
+    /// Matches the exit codes of the terminated containers. Exclusive with `onPodConditions`.
+    pub on_exit_codes: Option<OnExitCodesRequirement>,
+
+    /// Matches the conditions of the pod, any of them. Exclusive with `onExitCodes`.
+    #[serde(default)]
+    pub on_pod_conditions: Vec<OnPodConditionsPattern>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+pub enum PodFailurePolicyAction {
+    /// FailJob marks the job as failed, and terminates its running pods.
+    FailJob,
+    /// Ignore deletes the failed pod without counting it, so that it runs again.
+    Ignore,
+    /// Count counts the pod as failed, as if no rule matched.
+    Count,
+    /// RestartTask deletes all the pods of the task, so that the whole task runs again.
+    RestartTask,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OnExitCodesRequirement {
+    /// The name of the container whose exit code is matched. If not specified, all the containers are.
+    pub container_name: Option<String>,
+
+    /// Whether the exit codes have to be `In` or `NotIn` the values.
+    pub operator: ExitCodesOperator,
+
+    /// The exit codes. The containers which exited with 0 never match.
+    pub values: Vec<i32>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+pub enum ExitCodesOperator {
+    In,
+    NotIn,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub struct OnPodConditionsPattern {
+    /// The type of the pod condition, e.g. `DisruptionTarget`.
+    #[serde(rename = "type")]
+    pub type_: String,
+
+    /// The status of the pod condition, `True` by default.
+    #[serde(default = "default_condition_status")]
+    pub status: String,
+}
+
+fn default_condition_status() -> String {
+    "True".to_string()
+}
+
+impl PodFailurePolicy {
+    /// The action of the first rule matching a failed pod, and why it matched.
+    pub fn match_pod(&self, pod: &Pod) -> Option<(PodFailurePolicyAction, String)> {
+        self.rules
+            .iter()
+            .find_map(|rule| rule.match_pod(pod).map(|reason| (rule.action, reason)))
+    }
+}
+
+impl PodFailurePolicyRule {
+    fn match_pod(&self, pod: &Pod) -> Option<String> {
+        let status = pod.status.as_ref()?;
+        if let Some(requirement) = &self.on_exit_codes {
+            let statuses = status.container_statuses.iter().flatten();
+            for container in statuses {
+                if requirement
+                    .container_name
+                    .as_ref()
+                    .map(|name| *name != container.name)
+                    .unwrap_or_default()
+                {
+                    continue;
+                }
+                let exit_code = container
+                    .state
+                    .as_ref()
+                    .and_then(|s| s.terminated.as_ref())
+                    .map(|t| t.exit_code)
+                    .filter(|code| *code != 0);
+                if let Some(exit_code) = exit_code {
+                    let matches = requirement.values.contains(&exit_code);
+                    if matches == (requirement.operator == ExitCodesOperator::In) {
+                        return Some(format!(
+                            "container {} exited with code {}",
+                            container.name, exit_code
+                        ));
+                    }
+                }
+            }
+        }
+
+        let conditions = status.conditions.iter().flatten();
+        for condition in conditions {
+            if self
+                .on_pod_conditions
+                .iter()
+                .any(|p| p.type_ == condition.type_ && p.status == condition.status)
+            {
+                return Some(format!(
+                    "pod has condition {}={}",
+                    condition.type_, condition.status
+                ));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PodFailurePolicy, PodFailurePolicyAction};
+    use k8s_openapi::api::core::v1::Pod;
+
+    #[test]
+    fn test_match_pod() {
+        let policy = serde_json::from_value::<PodFailurePolicy>(serde_json::json!({
+            "rules": [
+                {"action": "FailJob", "onExitCodes": {"containerName": "main", "operator": "In", "values": [42]}},
+                {"action": "Ignore", "onPodConditions": [{"type": "DisruptionTarget"}]},
+                {"action": "RestartTask", "onExitCodes": {"operator": "NotIn", "values": [1]}}
+            ]
+        }))
+        .unwrap();
+        let pod = |exit_codes: &[(&str, i32)], condition: Option<&str>| {
+            let statuses = exit_codes
+                .iter()
+                .map(|(name, code)| {
+                    serde_json::json!({
+                        "name": name, "image": "busybox", "imageID": "", "ready": false, "restartCount": 0,
+                        "state": {"terminated": {"exitCode": code}}
+                    })
+                })
+                .collect::<Vec<_>>();
+            let conditions = condition
+                .map(|type_| vec![serde_json::json!({"type": type_, "status": "True"})])
+                .unwrap_or_default();
+            serde_json::from_value::<Pod>(serde_json::json!({
+                "status": {"phase": "Failed", "containerStatuses": statuses, "conditions": conditions}
+            }))
+            .unwrap()
+        };
+
+        assert_eq!(
+            policy.match_pod(&pod(&[("main", 42)], None)),
+            Some((
+                PodFailurePolicyAction::FailJob,
+                "container main exited with code 42".to_string()
+            ))
+        );
+        // the exit code of another container doesn't fail the job
+        assert_eq!(
+            policy.match_pod(&pod(&[("sidecar", 42)], None)).map(|m| m.0),
+            Some(PodFailurePolicyAction::RestartTask)
+        );
+        assert_eq!(
+            policy
+                .match_pod(&pod(&[("main", 1)], Some("DisruptionTarget")))
+                .map(|m| m.0),
+            Some(PodFailurePolicyAction::Ignore)
+        );
+        assert_eq!(policy.match_pod(&pod(&[("main", 1), ("sidecar", 0)], None)), None);
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::BTreeSet;
+
+/// Parses compressed index ranges, e.g. `1-3,5,7-9`, keeping the indexes below `limit`. The invalid ranges
+/// are ignored.
+pub fn parse_index_ranges(ranges: &str, limit: u32) -> BTreeSet<u32> {
+    let mut indexes = BTreeSet::new();
+    for range in ranges.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+        let bounds = match range.split_once('-') {
+            Some((first, last)) => first.parse::<u32>().and_then(|f| last.parse().map(|l| (f, l))),
+            None => range.parse::<u32>().map(|i| (i, i)),
+        };
+        match bounds {
+            Ok((first, last)) if first < limit => indexes.extend(first..=last.min(limit - 1)),
+            _ => (),
+        }
+    }
+    indexes
+}
+
+/// Compresses indexes into ranges, e.g. `1-3,5,7-9`.
+pub fn format_index_ranges(indexes: &BTreeSet<u32>) -> String {
+    let mut ranges: Vec<(u32, u32)> = vec![];
+    for &index in indexes {
+        match ranges.last_mut() {
+            Some((_, last)) if last.checked_add(1) == Some(index) => *last = index,
+            _ => ranges.push((index, index)),
+        }
+    }
+    ranges
+        .into_iter()
+        .map(|(first, last)| match first == last {
+            true => first.to_string(),
+            false => format!("{}-{}", first, last),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use super::{format_index_ranges, parse_index_ranges};
+
+    #[test]
+    fn test_index_ranges() {
+        let indexes = BTreeSet::from([1, 2, 3, 5, 7, 8, 9]);
+        assert_eq!(format_index_ranges(&indexes), "1-3,5,7-9");
+        assert_eq!(parse_index_ranges("1-3,5,7-9", 10), indexes);
+        assert_eq!(format_index_ranges(&BTreeSet::new()), "");
+        assert_eq!(parse_index_ranges("", 10), BTreeSet::new());
+        assert_eq!(parse_index_ranges("0,x,2-1,4-4", 10), BTreeSet::from([0, 4]));
+
+        // the ranges beyond the replicas of the task are clamped
+        assert_eq!(parse_index_ranges("1-4294967295", 3), BTreeSet::from([1, 2]));
+        assert_eq!(parse_index_ranges("5,7-9", 3), BTreeSet::new());
+        assert_eq!(parse_index_ranges("0-2", 0), BTreeSet::new());
+        assert_eq!(
+            format_index_ranges(&BTreeSet::from([0, u32::MAX - 1, u32::MAX])),
+            "0,4294967294-4294967295"
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::JobSpec;
+
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[kube(
+    namespaced,
+    kind = "JobTemplate",
+    group = "batch.habitat",
+    version = "v1beta1",
+    shortname = "hjt",
+    shortname = "hjobtemplate",
+    printcolumn = r#"{"name": "Age", "jsonPath": ".metadata.creationTimestamp", "type": "date", "priority": 0}"#
+)]
+pub struct TemplateSpec {
+    /// The parameters of the template. Their values replace the `{{ name }}` placeholders in the string
+    /// fields of the job specification.
+    #[serde(default)]
+    pub parameters: Vec<TemplateParameter>,
+
+    /// The job specification the jobs referencing this template are instantiated from.
+    pub job: JobSpec,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub struct TemplateParameter {
+    /// The name of the parameter.
+    pub name: String,
+
+    /// The value of the parameter if a job doesn't specify it. If not specified, the jobs have to.
+    pub default: Option<String>,
+
+    /// Human-readable description of the parameter.
+    pub description: Option<String>,
+}
+
+impl JobTemplate {
+    /// Instantiates the job specification of the template with the given parameter values.
+    pub fn instantiate(&self, values: &BTreeMap<String, String>) -> Result<JobSpec, String> {
+        if let Some(name) = values
+            .keys()
+            .find(|name| !self.spec.parameters.iter().any(|p| &p.name == *name))
+        {
+            return Err(format!("unknown parameter `{}`", name));
+        }
+
+        let mut parameters = BTreeMap::new();
+        for parameter in &self.spec.parameters {
+            match values.get(&parameter.name).or(parameter.default.as_ref()) {
+                Some(value) => parameters.insert(parameter.name.as_str(), value.as_str()),
+                None => return Err(format!("missing value of parameter `{}`", parameter.name)),
+            };
+        }
+
+        let mut job = serde_json::to_value(&self.spec.job).map_err(|e| e.to_string())?;
+        substitute(&mut job, &parameters)?;
+        serde_json::from_value(job).map_err(|e| e.to_string())
+    }
+}
+
+/// Replaces the `{{ name }}` placeholders in all the strings of a value.
+fn substitute(value: &mut Value, parameters: &BTreeMap<&str, &str>) -> Result<(), String> {
+    match value {
+        Value::String(s) => *s = substitute_str(s, parameters)?,
+        Value::Array(values) => {
+            for value in values {
+                substitute(value, parameters)?;
+            }
+        }
+        Value::Object(map) => {
+            for value in map.values_mut() {
+                substitute(value, parameters)?;
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+fn substitute_str(s: &str, parameters: &BTreeMap<&str, &str>) -> Result<String, String> {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| format!("unclosed placeholder in `{}`", s))?;
+        let name = rest[start + 2..start + end].trim();
+        let value = parameters
+            .get(name)
+            .ok_or_else(|| format!("undeclared parameter `{}`", name))?;
+        result.push_str(&rest[..start]);
+        result.push_str(value);
+        rest = &rest[start + end + 2..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::JobTemplate;
+
+    fn template() -> JobTemplate {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "batch.habitat/v1beta1",
+            "kind": "JobTemplate",
+            "metadata": {"name": "train", "namespace": "default"},
+            "spec": {
+                "parameters": [{"name": "image"}, {"name": "epochs", "default": "10"}],
+                "job": {
+                    "queue": "training",
+                    "tasks": [{
+                        "name": "worker",
+                        "parallelism": {},
+                        "template": {"spec": {"containers": [{
+                            "name": "main",
+                            "image": "{{ image }}",
+                            "args": ["--epochs={{epochs}}"]
+                        }]}}
+                    }]
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_instantiate() {
+        let values = BTreeMap::from([("image".to_string(), "trainer:v2".to_string())]);
+        let spec = template().instantiate(&values).unwrap();
+        let container = &serde_json::to_value(&spec.tasks[0].template.spec).unwrap()["containers"][0];
+        assert_eq!(container["image"], "trainer:v2");
+        assert_eq!(container["args"][0], "--epochs=10");
+        assert_eq!(spec.queue.as_deref(), Some("training"));
+    }
+
+    #[test]
+    fn test_instantiate_invalid_parameters() {
+        assert_eq!(
+            template().instantiate(&BTreeMap::new()).unwrap_err(),
+            "missing value of parameter `image`"
+        );
+
+        let values = BTreeMap::from([
+            ("image".to_string(), "trainer:v2".to_string()),
+            ("lr".to_string(), "0.1".to_string()),
+        ]);
+        assert_eq!(
+            template().instantiate(&values).unwrap_err(),
+            "unknown parameter `lr`"
+        );
+    }
+}
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+use super::{CRON_JOB_LABEL, REPLICA_INDEX_LABEL, TASK_NAME_LABEL, TASK_OWNER_LABEL};
+
+/// The names of the labels set by the controller. All the components of a deployment have to agree on
+/// them, so the controller and the scheduler are configured with the same ones.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LabelNames {
+    /// The label of the pods created for a job, whose value is the name of the job
+    pub task_owner: String,
+    /// The label of the pods created for a job, whose value is the name of their task
+    pub task_name: String,
+    /// The label of the pods created for a job, whose value is their replica index within their task
+    pub replica_index: String,
+    /// The label of the jobs created for a cron job, whose value is the name of the cron job
+    pub cron_job: String,
+}
+
+impl Default for LabelNames {
+    fn default() -> Self {
+        Self {
+            task_owner: TASK_OWNER_LABEL.to_string(),
+            task_name: TASK_NAME_LABEL.to_string(),
+            replica_index: REPLICA_INDEX_LABEL.to_string(),
+            cron_job: CRON_JOB_LABEL.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LabelNames;
+
+    #[test]
+    fn test_partial_label_names() {
+        let names =
+            serde_json::from_value::<LabelNames>(serde_json::json!({"taskOwner": "team-job"})).unwrap();
+        assert_eq!(names.task_owner, "team-job");
+        assert_eq!(names.task_name, "habitat-task");
+        assert_eq!(names.cron_job, "habitat-cron-job");
+    }
+}
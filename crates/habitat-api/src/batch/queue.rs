@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[kube(
+    kind = "Queue",
+    group = "batch.habitat",
+    version = "v1beta1",
+    shortname = "hq",
+    shortname = "hqueue",
+    status = "QueueStatus",
+    printcolumn = r#"{"name": "Weight", "jsonPath": ".spec.weight", "type": "integer", "priority": 0}"#,
+    printcolumn = r#"{"name": "State", "jsonPath": ".spec.state", "type": "string", "priority": 0}"#,
+    printcolumn = r#"{"name": "Pending", "jsonPath": ".status.pending", "type": "integer", "priority": 1}"#,
+    printcolumn = r#"{"name": "Running", "jsonPath": ".status.running", "type": "integer", "priority": 1}"#,
+    printcolumn = r#"{"name": "Succeeded", "jsonPath": ".status.succeeded", "type": "integer", "priority": 1}"#,
+    printcolumn = r#"{"name": "Failed", "jsonPath": ".status.failed", "type": "integer", "priority": 1}"#,
+    printcolumn = r#"{"name": "Age", "jsonPath": ".metadata.creationTimestamp", "type": "date", "priority": 0}"#
+)]
+pub struct QueueSpec {
+    /// The upper bound of the resources the jobs of the queue can use. If not specified, the queue is only
+    /// bounded by the cluster capacity, as are the resources not listed.
+    pub capacity: Option<BTreeMap<String, Quantity>>,
+
+    /// The relative weight of the queue when sharing the cluster resources with the other queues. A weight
+    /// of 0 counts as 1.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+
+    /// Whether the resources the queue uses beyond its share can be reclaimed by the other queues.
+    #[serde(default = "default_reclaimable")]
+    pub reclaimable: bool,
+
+    /// The state of the queue. Closed queues don't accept new jobs.
+    #[serde(default)]
+    pub state: QueueState,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueStatus {
+    /// The number of jobs which reached phase `Pending`.
+    pub pending: u32,
+
+    /// The number of jobs which reached phase `Running`.
+    pub running: u32,
+
+    /// The number of jobs which reached phase `Terminating`.
+    pub terminating: u32,
+
+    /// The number of jobs which reached phase `Succeeded`.
+    pub succeeded: u32,
+
+    /// The number of jobs which reached phase `Failed` or `Terminated`.
+    pub failed: u32,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub enum QueueState {
+    /// Open means the queue accepts new jobs.
+    Open,
+    /// Closed means the queue rejects new jobs, the jobs already in the queue keep running.
+    Closed,
+}
+
+impl Default for QueueState {
+    fn default() -> Self {
+        Self::Open
+    }
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn default_reclaimable() -> bool {
+    true
+}
@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::TaskSpec;
+
+/// The environment variable holding the sweep index of a pod
+pub const SWEEP_INDEX_ENV: &str = "HABITAT_SWEEP_INDEX";
+
+/// A parameter matrix, each combination of which is run by an index of the job
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SweepSpec {
+    /// The swept parameters, injected as environment variables of the same names in all the containers.
+    pub parameters: Vec<SweepParameter>,
+
+    /// The maximum number of indexes running at the same time, whose pods are all requested by the job when
+    /// it is admitted. If not specified, as many indexes as the highest `parallelism.min` of the tasks run
+    /// at once, at least one.
+    pub max_concurrent: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+pub struct SweepParameter {
+    /// The name of the parameter, which is also the name of its environment variable.
+    pub name: String,
+
+    /// The values of the parameter. Exclusive with `range`.
+    pub values: Option<Vec<String>>,
+
+    /// The integer range of the parameter. Exclusive with `values`.
+    pub range: Option<SweepRange>,
+}
+
+/// The integers from `start` up to `stop` excluded, by `step`
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct SweepRange {
+    pub start: i64,
+    pub stop: i64,
+    #[serde(default = "default_step")]
+    pub step: i64,
+}
+
+fn default_step() -> i64 {
+    1
+}
+
+impl SweepParameter {
+    /// The number of values of the parameter.
+    pub fn len(&self) -> u64 {
+        match (&self.values, &self.range) {
+            (Some(values), _) => values.len() as u64,
+            (None, Some(range)) if range.step > 0 && range.stop > range.start => {
+                // computed in i128, as the span of the range may overflow an i64
+                let span = range.stop as i128 - range.start as i128;
+                ((span + range.step as i128 - 1) / range.step as i128) as u64
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The value at a position, without listing all the values of a range.
+    pub fn value(&self, position: u64) -> Option<String> {
+        if position >= self.len() {
+            return None;
+        }
+        match (&self.values, &self.range) {
+            (Some(values), _) => values.get(position as usize).cloned(),
+            (None, Some(range)) => {
+                Some((range.start as i128 + position as i128 * range.step as i128).to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl SweepSpec {
+    /// The number of combinations, i.e. of indexes, if it fits in an index.
+    pub fn checked_len(&self) -> Option<u32> {
+        self.parameters.iter().try_fold(1u32, |len, parameter| {
+            len.checked_mul(u32::try_from(parameter.len()).ok()?)
+        })
+    }
+
+    /// The number of combinations, i.e. of indexes. Saturates, as the admission rejects sweeps with too
+    /// many combinations.
+    pub fn len(&self) -> u32 {
+        self.checked_len().unwrap_or(u32::MAX)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The maximum number of indexes running at the same time, see [`SweepSpec::max_concurrent`].
+    pub fn concurrency(&self, tasks: &[TaskSpec]) -> u32 {
+        self.max_concurrent.unwrap_or_else(|| {
+            let min = tasks.iter().map(|t| t.parallelism.min).max().unwrap_or_default();
+            min.max(1)
+        })
+    }
+
+    /// The parameter values of an index. The last parameter varies the fastest.
+    pub fn combination(&self, index: u32) -> BTreeMap<String, String> {
+        let mut rest = index as u64;
+        let mut combination = BTreeMap::new();
+        for parameter in self.parameters.iter().rev() {
+            let len = parameter.len();
+            if len == 0 {
+                continue;
+            }
+            if let Some(value) = parameter.value(rest % len) {
+                combination.insert(parameter.name.clone(), value);
+            }
+            rest /= len;
+        }
+        combination
+    }
+}
+
+/// The result of a sweep index
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct SweepIndexStatus {
+    pub index: u32,
+
+    /// The parameter values of the index.
+    pub parameters: BTreeMap<String, String>,
+
+    pub phase: SweepIndexPhase,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+pub enum SweepIndexPhase {
+    /// Pending means one or more of the pods of the index has not been running yet.
+    Pending,
+    /// Running means one or more of the pods of the index is running.
+    Running,
+    /// Succeeded means that all the pods of the index succeeded.
+    Succeeded,
+    /// Failed means that one or more of the pods of the index failed.
+    Failed,
+}
+
+impl SweepIndexPhase {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Succeeded | Self::Failed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::SweepSpec;
+
+    #[test]
+    fn test_combination() {
+        let sweep = serde_json::from_value::<SweepSpec>(serde_json::json!({
+            "parameters": [
+                {"name": "LR", "values": ["0.1", "0.01"]},
+                {"name": "BATCH_SIZE", "range": {"start": 32, "stop": 129, "step": 32}}
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(sweep.len(), 8);
+        let combination = |lr: &str, batch_size: &str| {
+            BTreeMap::from([
+                ("LR".to_string(), lr.to_string()),
+                ("BATCH_SIZE".to_string(), batch_size.to_string()),
+            ])
+        };
+        assert_eq!(sweep.combination(0), combination("0.1", "32"));
+        assert_eq!(sweep.combination(3), combination("0.1", "128"));
+        assert_eq!(sweep.combination(5), combination("0.01", "64"));
+    }
+
+    #[test]
+    fn test_large_sweep() {
+        let sweep = serde_json::from_value::<SweepSpec>(serde_json::json!({
+            "parameters": [
+                {"name": "SEED", "range": {"start": i64::MIN, "stop": i64::MAX, "step": 2}},
+                {"name": "LR", "values": ["0.1", "0.01"]}
+            ]
+        }))
+        .unwrap();
+
+        // the combinations overflow the indexes, but the values are still picked without listing them
+        assert_eq!(sweep.parameters[0].len(), 1 << 63);
+        assert_eq!(sweep.checked_len(), None);
+        assert_eq!(sweep.len(), u32::MAX);
+        assert_eq!(sweep.combination(3)["SEED"], (i64::MIN + 2).to_string());
+        assert_eq!(sweep.combination(3)["LR"], "0.01");
+    }
+}
@@ -1,4 +1,5 @@
 pub mod batch;
+pub mod resource;
 
 /// Generated type, for crdgen
-pub use batch::Job;
+pub use batch::{CronJob, Job, JobTemplate, Queue};
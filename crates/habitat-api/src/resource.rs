@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::{
+    api::core::v1::{self as core, Container, ResourceRequirements},
+    apimachinery::pkg::api::resource::Quantity,
+};
+
+use crate::batch::{JobSpec, PodSpec};
+
+/// Amount of each resource in base units, keyed by the resource name
+pub type ResourceList = BTreeMap<String, f64>;
+
+/// Parses a kubernetes resource quantity (e.g. `500m`, `1.5Gi`, `2e3`) into its value in base units.
+///
+/// More info: https://kubernetes.io/docs/reference/kubernetes-api/common-definitions/quantity/
+pub fn parse_quantity(quantity: &Quantity) -> Result<f64, String> {
+    let s = quantity.0.trim();
+    let invalid = || {
+        format!(
+            "quantities must match the regular expression '{}'",
+            QUANTITY_PATTERN
+        )
+    };
+
+    let number_end = s
+        .char_indices()
+        .find(|(i, c)| !(c.is_ascii_digit() || *c == '.' || (*i == 0 && (*c == '+' || *c == '-'))))
+        .map(|(i, _)| i)
+        .unwrap_or(s.len());
+    let (number, suffix) = s.split_at(number_end);
+    if !number.chars().any(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let number = number.parse::<f64>().map_err(|_| invalid())?;
+
+    let multiplier = match suffix {
+        "" => 1.0,
+        "n" => 1e-9,
+        "u" => 1e-6,
+        "m" => 1e-3,
+        "k" => 1e3,
+        "M" => 1e6,
+        "G" => 1e9,
+        "T" => 1e12,
+        "P" => 1e15,
+        "E" => 1e18,
+        "Ki" => 1024_f64,
+        "Mi" => 1024_f64.powi(2),
+        "Gi" => 1024_f64.powi(3),
+        "Ti" => 1024_f64.powi(4),
+        "Pi" => 1024_f64.powi(5),
+        "Ei" => 1024_f64.powi(6),
+        exponent if exponent.starts_with(['e', 'E']) => {
+            let exponent = exponent[1..].parse::<i32>().map_err(|_| invalid())?;
+            10_f64.powi(exponent)
+        }
+        _ => return Err(invalid()),
+    };
+
+    Ok(number * multiplier)
+}
+
+/// Formats an amount in base units back to a human readable quantity.
+pub fn format_quantity(value: f64) -> String {
+    const BINARY_SUFFIXES: [&str; 6] = ["Ki", "Mi", "Gi", "Ti", "Pi", "Ei"];
+
+    if value.fract() != 0.0 {
+        return format!("{}m", (value * 1000.0).round());
+    }
+    let mut suffix = "";
+    let mut value = value;
+    for binary_suffix in BINARY_SUFFIXES {
+        if value == 0.0 || value % 1024.0 != 0.0 {
+            break;
+        }
+        value /= 1024.0;
+        suffix = binary_suffix;
+    }
+    format!("{}{}", value, suffix)
+}
+
+/// Computes the resources requested by a pod. As the init containers run in order prior to the containers,
+/// the effective request of each resource is the max of the highest init container request and the sum of
+/// the container requests.
+pub fn pod_requests(spec: &PodSpec) -> ResourceList {
+    pod_resources(&spec.containers, spec.init_containers.as_deref(), |r| {
+        r.requests.as_ref()
+    })
+}
+
+/// Computes the resource limits of a pod, in the same way as [`pod_requests`].
+pub fn pod_limits(spec: &PodSpec) -> ResourceList {
+    pod_resources(&spec.containers, spec.init_containers.as_deref(), |r| {
+        r.limits.as_ref()
+    })
+}
+
+/// Computes the resources requested by a created pod, in the same way as [`pod_requests`].
+pub fn core_pod_requests(spec: &core::PodSpec) -> ResourceList {
+    pod_resources(&spec.containers, spec.init_containers.as_deref(), |r| {
+        r.requests.as_ref()
+    })
+}
+
+/// Computes the resources requested by the minimum number of pods of a job. A sweep job requests the pods
+/// of all the indexes it runs at the same time.
+pub fn job_min_requests(spec: &JobSpec) -> ResourceList {
+    job_min_resources(spec, pod_requests)
+}
+
+/// Computes the resource limits of the minimum number of pods of a job.
+pub fn job_min_limits(spec: &JobSpec) -> ResourceList {
+    job_min_resources(spec, pod_limits)
+}
+
+/// Adds up the resources of `other` into `list`.
+pub fn add_resources(list: &mut ResourceList, other: &ResourceList) {
+    for (name, value) in other {
+        *list.entry(name.clone()).or_default() += value;
+    }
+}
+
+/// Parses the quantities of a container resource list, ignoring the invalid ones.
+pub fn to_resource_list(quantities: &BTreeMap<String, Quantity>) -> ResourceList {
+    quantities
+        .iter()
+        .filter_map(|(name, quantity)| parse_quantity(quantity).ok().map(|v| (name.clone(), v)))
+        .collect()
+}
+
+fn pod_resources<F>(
+    containers: &[Container],
+    init_containers: Option<&[Container]>,
+    select: F,
+) -> ResourceList
+where
+    F: Fn(&ResourceRequirements) -> Option<&BTreeMap<String, Quantity>>,
+{
+    let container_resources = |container: &Container| {
+        container
+            .resources
+            .as_ref()
+            .and_then(&select)
+            .map(to_resource_list)
+            .unwrap_or_default()
+    };
+
+    let mut resources = ResourceList::new();
+    for container in containers {
+        add_resources(&mut resources, &container_resources(container));
+    }
+    for container in init_containers.into_iter().flatten() {
+        for (name, value) in container_resources(container) {
+            let total = resources.entry(name).or_default();
+            *total = total.max(value);
+        }
+    }
+    resources
+}
+
+fn job_min_resources<F>(spec: &JobSpec, pod_resources: F) -> ResourceList
+where
+    F: Fn(&PodSpec) -> ResourceList,
+{
+    // every index of a sweep runs one pod of every task
+    let concurrency = spec.sweep.as_ref().map(|sweep| sweep.concurrency(&spec.tasks));
+    let mut resources = ResourceList::new();
+    for task in &spec.tasks {
+        let pods = concurrency.unwrap_or(task.parallelism.min);
+        for (name, value) in pod_resources(&task.template.spec) {
+            *resources.entry(name).or_default() += value * pods as f64;
+        }
+    }
+    resources
+}
+
+const QUANTITY_PATTERN: &str = r"^([+-]?[0-9.]+)([eEinumkKMGTP]*[-+]?[0-9]*)$";
+
+#[cfg(test)]
+mod test {
+    use super::{format_quantity, job_min_requests, parse_quantity};
+    use crate::batch::JobSpec;
+    use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+
+    fn parse(s: &str) -> Result<f64, String> {
+        parse_quantity(&Quantity(s.to_string()))
+    }
+
+    #[test]
+    fn test_parse_quantity() {
+        assert_eq!(parse("2").unwrap(), 2.0);
+        assert_eq!(parse("500m").unwrap(), 0.5);
+        assert_eq!(parse("1.5Gi").unwrap(), 1.5 * 1024.0 * 1024.0 * 1024.0);
+        assert_eq!(parse("1k").unwrap(), 1000.0);
+        assert_eq!(parse("2e3").unwrap(), 2000.0);
+        assert_eq!(parse("-1").unwrap(), -1.0);
+    }
+
+    #[test]
+    fn test_parse_invalid_quantity() {
+        assert!(parse("").is_err());
+        assert!(parse("abc").is_err());
+        assert!(parse("1Gb").is_err());
+        assert!(parse("1.2.3").is_err());
+        assert!(parse("1e").is_err());
+    }
+
+    #[test]
+    fn test_format_quantity() {
+        assert_eq!(format_quantity(0.5), "500m");
+        assert_eq!(format_quantity(2.0), "2");
+        assert_eq!(format_quantity(1536.0 * 1024.0 * 1024.0), "1536Mi");
+        assert_eq!(format_quantity(2.0 * 1024.0 * 1024.0 * 1024.0), "2Gi");
+    }
+
+    #[test]
+    fn test_job_min_requests() {
+        let spec: JobSpec = serde_json::from_value(serde_json::json!({
+            "tasks": [{
+                "name": "worker",
+                "parallelism": {"min": 2, "max": 4},
+                "template": {"spec": {
+                    "initContainers": [
+                        {"name": "init", "resources": {"requests": {"cpu": "3", "memory": "1Gi"}}}
+                    ],
+                    "containers": [
+                        {"name": "a", "resources": {"requests": {"cpu": "1", "memory": "1Gi"}}},
+                        {"name": "b", "resources": {"requests": {"cpu": "500m", "memory": "1Gi"}}}
+                    ]
+                }}
+            }]
+        }))
+        .unwrap();
+
+        let requests = job_min_requests(&spec);
+        assert_eq!(requests["cpu"], 6.0);
+        assert_eq!(requests["memory"], 4.0 * 1024.0 * 1024.0 * 1024.0);
+    }
+
+    #[test]
+    fn test_sweep_min_requests() {
+        let mut spec: JobSpec = serde_json::from_value(serde_json::json!({
+            "sweep": {"parameters": [{"name": "LR", "values": ["0.1", "0.01", "0.001"]}]},
+            "tasks": [
+                {"name": "ps", "parallelism": {}, "template": {"spec": {"containers": [
+                    {"name": "main", "resources": {"requests": {"cpu": "1"}}}
+                ]}}},
+                {"name": "worker", "parallelism": {"min": 2}, "template": {"spec": {"containers": [
+                    {"name": "main", "resources": {"requests": {"cpu": "2"}}}
+                ]}}}
+            ]
+        }))
+        .unwrap();
+
+        // the concurrent indexes run a pod of every task, as many as the highest minimum by default
+        assert_eq!(job_min_requests(&spec)["cpu"], 6.0);
+        spec.sweep.as_mut().unwrap().max_concurrent = Some(3);
+        assert_eq!(job_min_requests(&spec)["cpu"], 9.0);
+    }
+}
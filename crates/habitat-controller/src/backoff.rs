@@ -0,0 +1,74 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use rand::Rng;
+use tokio::time::Duration;
+
+/// Exponential backoff with jitter, tracked per object whose reconciliation fails
+#[derive(Clone)]
+pub struct Backoff {
+    /// The delay after the first failure
+    min: Duration,
+    /// The longest delay
+    max: Duration,
+    /// The consecutive failures by object
+    failures: Arc<Mutex<HashMap<String, u32>>>,
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max: max.max(min),
+            failures: Default::default(),
+        }
+    }
+
+    /// Counts a failure of the object, and returns how long to wait before retrying it.
+    pub fn next(&self, key: &str) -> Duration {
+        let mut failures = self.failures.lock().unwrap();
+        let count = failures.entry(key.to_string()).or_default();
+        *count = count.saturating_add(1);
+        let delay = self.delay(*count);
+        // the jitter spreads the retries of the objects failed at the same time
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+
+    /// Forgets the failures of the object once it is reconciled.
+    pub fn reset(&self, key: &str) {
+        self.failures.lock().unwrap().remove(key);
+    }
+
+    /// The delay before the jitter, doubled with every consecutive failure.
+    fn delay(&self, failures: u32) -> Duration {
+        let exponent = failures.saturating_sub(1).min(31);
+        self.min.saturating_mul(1 << exponent).min(self.max)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::time::Duration;
+
+    use super::Backoff;
+
+    #[test]
+    fn test_backoff() {
+        let backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60));
+        assert_eq!(backoff.delay(1), Duration::from_secs(1));
+        assert_eq!(backoff.delay(3), Duration::from_secs(4));
+        assert_eq!(backoff.delay(7), Duration::from_secs(60));
+        assert_eq!(backoff.delay(100), Duration::from_secs(60));
+
+        for _ in 0..3 {
+            backoff.next("default/job");
+        }
+        let delay = backoff.next("default/job");
+        assert!(delay >= Duration::from_secs(4) && delay <= Duration::from_secs(8));
+
+        backoff.reset("default/job");
+        assert!(backoff.next("default/job") <= Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+};
+
+use futures::{Stream, TryStreamExt};
+use habitat_api::Job;
+use k8s_openapi::api::core::v1::Pod;
+use kube::{
+    runtime::{reflector::ObjectRef, watcher},
+    ResourceExt,
+};
+
+/// The pods of the jobs, indexed by the uid of the job owning them
+///
+/// The cache is fed by the pod watchers of the watched namespaces, so the reconcilers read the pods
+/// from memory instead of listing them on every reconciliation.
+#[derive(Clone, Default)]
+pub struct PodCache {
+    index: Arc<RwLock<PodIndex>>,
+}
+
+#[derive(Default)]
+struct PodIndex {
+    /// The pods by the uid of their owner, then by name
+    by_owner: HashMap<String, HashMap<String, Arc<Pod>>>,
+    /// The uid of the owner of every cached pod
+    owners: HashMap<ObjectRef<Pod>, String>,
+    /// The namespaces whose pods have been listed, `None` for all the namespaces
+    listed: HashSet<Option<String>>,
+}
+
+impl PodCache {
+    /// The pods controlled by the owner, by name.
+    pub fn owned_pods(&self, owner_uid: &str) -> HashMap<String, Pod> {
+        self.index
+            .read()
+            .unwrap()
+            .by_owner
+            .get(owner_uid)
+            .map(|pods| {
+                pods.iter()
+                    .map(|(name, pod)| (name.clone(), pod.as_ref().clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// All the cached pods.
+    pub fn pods(&self) -> Vec<Pod> {
+        self.index
+            .read()
+            .unwrap()
+            .by_owner
+            .values()
+            .flat_map(|pods| pods.values().map(|pod| pod.as_ref().clone()))
+            .collect()
+    }
+
+    /// The number of watched namespaces whose pods have been listed once.
+    pub fn listed(&self) -> usize {
+        self.index.read().unwrap().listed.len()
+    }
+
+    /// Feeds the cache from the events of the pod watcher of a namespace, or of all the namespaces if
+    /// `None`.
+    ///
+    /// Yields the jobs owning the pods of every event once the cache is updated, so the reconciliations
+    /// they trigger never read older pods than the ones which triggered them.
+    pub fn feed(
+        &self,
+        events: impl Stream<Item = Result<watcher::Event<Pod>, watcher::Error>>,
+        namespace: Option<String>,
+    ) -> impl Stream<Item = Result<Vec<ObjectRef<Job>>, watcher::Error>> {
+        let cache = self.clone();
+        events.map_ok(move |event| cache.apply_watcher_event(namespace.as_deref(), &event))
+    }
+
+    /// Applies an event of the pod watcher of a namespace, or of all the namespaces if `None`.
+    ///
+    /// Returns the jobs owning the pods of the event.
+    pub fn apply_watcher_event(
+        &self,
+        namespace: Option<&str>,
+        event: &watcher::Event<Pod>,
+    ) -> Vec<ObjectRef<Job>> {
+        let mut index = self.index.write().unwrap();
+        let mut changed = vec![];
+        match event {
+            watcher::Event::Applied(pod) => {
+                changed.extend(index.remove(&ObjectRef::from_obj(pod)));
+                index.insert(pod);
+                changed.push(Arc::new(pod.clone()));
+            }
+            watcher::Event::Deleted(pod) => {
+                index.remove(&ObjectRef::from_obj(pod));
+                changed.push(Arc::new(pod.clone()));
+            }
+            watcher::Event::Restarted(pods) => {
+                // only the pods of the relisted namespace are replaced
+                let relisted = index
+                    .owners
+                    .keys()
+                    .filter(|pod| namespace.is_none() || pod.namespace.as_deref() == namespace)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                for pod in &relisted {
+                    // the jobs of the pods deleted while the watch was down are reconciled as well
+                    changed.extend(index.remove(pod));
+                }
+                for pod in pods {
+                    index.insert(pod);
+                    changed.push(Arc::new(pod.clone()));
+                }
+                index.listed.insert(namespace.map(String::from));
+            }
+        }
+
+        let mut owners = changed
+            .iter()
+            .filter_map(|pod| {
+                let owner = pod
+                    .owner_references()
+                    .iter()
+                    .find(|o| o.controller == Some(true))?;
+                ObjectRef::from_owner_ref(pod.namespace().as_deref(), owner, ())
+            })
+            .collect::<Vec<_>>();
+        owners.sort_by(|a, b| (&a.namespace, &a.name).cmp(&(&b.namespace, &b.name)));
+        owners.dedup();
+        owners
+    }
+}
+
+impl PodIndex {
+    fn insert(&mut self, pod: &Pod) {
+        let key = ObjectRef::from_obj(pod);
+        // the owner of a pod may have changed since it was cached
+        self.remove(&key);
+        let owner_uid = match controller_uid(pod) {
+            Some(owner_uid) => owner_uid.to_string(),
+            None => return,
+        };
+        self.by_owner
+            .entry(owner_uid.clone())
+            .or_default()
+            .insert(pod.name_any(), Arc::new(pod.clone()));
+        self.owners.insert(key, owner_uid);
+    }
+
+    /// Removes a pod from the cache, returning it if it was cached.
+    fn remove(&mut self, pod: &ObjectRef<Pod>) -> Option<Arc<Pod>> {
+        let owner_uid = self.owners.remove(pod)?;
+        let pods = self.by_owner.get_mut(&owner_uid)?;
+        let removed = pods.remove(&pod.name);
+        if pods.is_empty() {
+            self.by_owner.remove(&owner_uid);
+        }
+        removed
+    }
+}
+
+/// The uid of the object controlling the pod.
+pub(crate) fn controller_uid(pod: &Pod) -> Option<&str> {
+    pod.owner_references()
+        .iter()
+        .find(|o| o.controller == Some(true))
+        .map(|o| o.uid.as_str())
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+    use k8s_openapi::api::core::v1::Pod;
+    use kube::runtime::watcher::Event;
+
+    use super::PodCache;
+
+    fn pod(namespace: &str, name: &str, owner_uid: &str) -> Pod {
+        serde_json::from_value(serde_json::json!({
+            "metadata": {
+                "name": name,
+                "namespace": namespace,
+                "ownerReferences": [{
+                    "apiVersion": "batch.habitat/v1beta1",
+                    "kind": "Job",
+                    "name": "job",
+                    "uid": owner_uid,
+                    "controller": true
+                }]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_pod_cache() {
+        let cache = PodCache::default();
+        cache.apply_watcher_event(Some("a"), &Event::Applied(pod("a", "pod-0", "job-a")));
+        cache.apply_watcher_event(Some("a"), &Event::Applied(pod("a", "pod-1", "job-a")));
+        cache.apply_watcher_event(Some("b"), &Event::Applied(pod("b", "pod-0", "job-b")));
+        assert_eq!(cache.owned_pods("job-a").len(), 2);
+        assert_eq!(cache.owned_pods("job-b").len(), 1);
+
+        cache.apply_watcher_event(Some("a"), &Event::Deleted(pod("a", "pod-0", "job-a")));
+        assert_eq!(cache.owned_pods("job-a").into_keys().collect::<Vec<_>>(), vec![
+            "pod-1"
+        ]);
+
+        // relisting a namespace keeps the pods of the other namespaces
+        cache.apply_watcher_event(Some("a"), &Event::Restarted(vec![pod("a", "pod-2", "job-c")]));
+        assert!(cache.owned_pods("job-a").is_empty());
+        assert_eq!(cache.owned_pods("job-b").len(), 1);
+        assert_eq!(cache.owned_pods("job-c").len(), 1);
+    }
+
+    fn pod_phase(cache: &PodCache, owner_uid: &str) -> Option<String> {
+        cache.owned_pods(owner_uid)["pod-0"]
+            .status
+            .as_ref()?
+            .phase
+            .clone()
+    }
+
+    #[tokio::test]
+    async fn test_feed_triggers_after_update() {
+        let cache = PodCache::default();
+        let mut running = pod("a", "pod-0", "job-a");
+        running.status = Some(serde_json::from_value(serde_json::json!({"phase": "Running"})).unwrap());
+        let mut succeeded = running.clone();
+        succeeded.status = Some(serde_json::from_value(serde_json::json!({"phase": "Succeeded"})).unwrap());
+        let events = futures::stream::iter(vec![
+            Ok(Event::Restarted(vec![running])),
+            Ok(Event::Applied(succeeded)),
+        ]);
+        let mut triggers = Box::pin(cache.feed(events, Some("a".to_string())));
+        assert_eq!(cache.listed(), 0);
+
+        let owners = triggers.next().await.unwrap().unwrap();
+        assert_eq!(owners.len(), 1);
+        assert_eq!(owners[0].name, "job");
+        assert_eq!(cache.listed(), 1);
+        assert_eq!(pod_phase(&cache, "job-a").as_deref(), Some("Running"));
+
+        // the reconciliation triggered by the last pod succeeding reads it succeeded
+        let owners = triggers.next().await.unwrap().unwrap();
+        assert_eq!(owners[0].namespace.as_deref(), Some("a"));
+        assert_eq!(pod_phase(&cache, "job-a").as_deref(), Some("Succeeded"));
+        assert!(triggers.next().await.is_none());
+    }
+}
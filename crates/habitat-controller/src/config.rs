@@ -0,0 +1,82 @@
+use habitat_api::batch::LabelNames;
+use k8s_openapi::NamespaceResourceScope;
+use kube::{Api, Client, Resource};
+use serde::{Deserialize, Serialize};
+use tokio::time::Duration;
+
+use crate::backoff::Backoff;
+
+/// The configuration of the controller, read from an optional YAML file
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Config {
+    /// Seconds to wait before reconciling again an object whose reconciliation failed once. The wait
+    /// doubles with every consecutive failure.
+    pub min_error_requeue_seconds: u64,
+    /// The longest wait in seconds before reconciling again an object whose reconciliation failed
+    pub error_requeue_seconds: u64,
+    /// The name of the controller in the events it reports
+    pub reporter: String,
+    /// The namespaces whose jobs and cron jobs are watched, all the namespaces if empty. The cluster-scoped
+    /// queues, nodes and priority classes, and the task pods of the other namespaces, are still watched to
+    /// decide the admission of the jobs, if the controller is allowed to list them.
+    pub namespaces: Vec<String>,
+    /// The names of the labels set on the pods and jobs
+    pub labels: LabelNames,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            min_error_requeue_seconds: 1,
+            error_requeue_seconds: 5 * 60,
+            reporter: "habitat-controller".to_string(),
+            namespaces: vec![],
+            labels: LabelNames::default(),
+        }
+    }
+}
+
+impl Config {
+    /// The backoff of the objects whose reconciliation failed
+    pub fn error_backoff(&self) -> Backoff {
+        Backoff::new(
+            Duration::from_secs(self.min_error_requeue_seconds),
+            Duration::from_secs(self.error_requeue_seconds),
+        )
+    }
+
+    /// The apis of a namespaced resource, one per watched namespace.
+    pub fn namespaced_apis<K>(&self, client: &Client) -> Vec<Api<K>>
+    where
+        K: Resource<Scope = NamespaceResourceScope>,
+        K::DynamicType: Default,
+    {
+        if self.namespaces.is_empty() {
+            return vec![Api::all(client.clone())];
+        }
+        self.namespaces
+            .iter()
+            .map(|ns| Api::namespaced(client.clone(), ns))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Config;
+
+    #[test]
+    fn test_partial_config() {
+        let config = serde_json::from_value::<Config>(serde_json::json!({
+            "namespaces": ["team-a", "team-b"],
+            "labels": {"taskOwner": "team-job"}
+        }))
+        .unwrap();
+        assert_eq!(config.namespaces, vec!["team-a", "team-b"]);
+        assert_eq!(config.labels.task_owner, "team-job");
+        assert_eq!(config.labels.task_name, "habitat-task");
+        assert_eq!(config.error_requeue_seconds, 300);
+        assert_eq!(config.reporter, "habitat-controller");
+    }
+}
@@ -0,0 +1,343 @@
+use std::sync::Arc;
+
+use crate::{
+    error::{Error, Result},
+    manager::{Context, Reconciler},
+    status::apply_status,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use croner::Cron;
+use habitat_api::{
+    batch::{ConcurrencyPolicy, JobStatusPhase, LabelNames},
+    CronJob, Job,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use kube::{
+    api::{Api, DeleteParams, ListParams, PostParams},
+    core::ObjectMeta,
+    runtime::{
+        controller::Action,
+        events::{Event, EventType, Recorder},
+        reflector::ObjectRef,
+    },
+    Resource, ResourceExt,
+};
+use tokio::time::Duration;
+use tracing::{info, warn};
+
+/// The most missed start times a cron job catches up with
+const MAX_MISSED_START_TIMES: usize = 100;
+
+pub(crate) async fn reconciler(cron_job: Arc<CronJob>, ctx: Arc<Context>) -> Result<Action> {
+    let _timer = ctx.metrics.count_and_measure("CronJob");
+    ctx.diagnostics.write().await.last_event = Utc::now();
+    // cron jobs have no finalizer, but one deleted in the foreground waits for its jobs to be deleted first
+    let result = if cron_job.meta().deletion_timestamp.is_some() {
+        cron_job.cleanup(ctx.clone()).await
+    } else {
+        cron_job.reconcile(ctx.clone()).await
+    }
+    .map_err(Error::KubeError);
+    if result.is_ok() {
+        ctx.backoff
+            .reset(&ObjectRef::from_obj(cron_job.as_ref()).to_string());
+    }
+    result
+}
+
+pub(crate) fn error_policy(cron_job: Arc<CronJob>, error: &Error, ctx: Arc<Context>) -> Action {
+    ctx.metrics.reconcile_failure("CronJob", error);
+    cron_job.error_policy(error, ctx)
+}
+
+#[async_trait]
+impl Reconciler for CronJob {
+    async fn reconcile(&self, ctx: Arc<Context>) -> Result<Action, kube::Error> {
+        let name = self.name_any();
+        let ns = self.namespace().unwrap();
+        info!("reconcile cron job {}/{}", ns, name);
+
+        let client = ctx.client.clone();
+        let reporter = ctx.diagnostics.read().await.reporter.clone();
+        let recorder = Recorder::new(client.clone(), reporter, self.object_ref(&()));
+        let cron_jobs: Api<CronJob> = Api::namespaced(client.clone(), &ns);
+        let jobs: Api<Job> = Api::namespaced(client, &ns);
+
+        let uid = self.uid();
+        let (finished, mut active): (Vec<_>, Vec<_>) = jobs
+            .list(&ListParams::default().labels(&format!("{}={}", ctx.config.labels.cron_job, name)))
+            .await?
+            .into_iter()
+            .filter(|job| {
+                job.owner_references()
+                    .iter()
+                    .any(|o| Some(&o.uid) == uid.as_ref())
+            })
+            .partition(|job| {
+                matches!(
+                    job.status.as_ref().map(|s| &s.phase),
+                    Some(JobStatusPhase::Succeeded | JobStatusPhase::Failed | JobStatusPhase::Terminated)
+                )
+            });
+
+        // garbage collect the oldest finished jobs beyond the history limits
+        let (mut succeeded, mut failed): (Vec<_>, Vec<_>) = finished
+            .into_iter()
+            .partition(|job| job.status.as_ref().map(|s| &s.phase) == Some(&JobStatusPhase::Succeeded));
+        let mut status = self.status.clone().unwrap_or_default();
+        status.last_successful_time = succeeded
+            .iter()
+            .filter_map(|job| job.status.as_ref()?.completion_time.clone())
+            .max_by_key(|time| time.0)
+            .or(status.last_successful_time);
+        for (finished, limit) in [
+            (&mut succeeded, self.spec.successful_jobs_history_limit),
+            (&mut failed, self.spec.failed_jobs_history_limit),
+        ] {
+            finished.sort_by_key(|job| job.creation_timestamp().map(|t| t.0));
+            let excess = finished.len().saturating_sub(limit as usize);
+            for job in &finished[..excess] {
+                info!("delete finished job {}/{}", ns, job.name_any());
+                jobs.delete(&job.name_any(), &DeleteParams::background()).await?;
+            }
+        }
+
+        let cron = match Cron::new(&self.spec.schedule).parse() {
+            Ok(cron) => cron,
+            Err(err) => {
+                recorder
+                    .publish(Event {
+                        type_: EventType::Warning,
+                        reason: "InvalidSchedule".into(),
+                        note: Some(format!("Invalid schedule `{}`: {}", self.spec.schedule, err)),
+                        action: "Scheduling".into(),
+                        secondary: None,
+                    })
+                    .await?;
+                return Ok(Action::await_change());
+            }
+        };
+
+        let now = Utc::now();
+        let mut earliest = status
+            .last_schedule_time
+            .as_ref()
+            .or(self.creation_timestamp().as_ref())
+            .map(|t| t.0)
+            .unwrap_or(now);
+        if let Some(deadline) = self.spec.starting_deadline_seconds {
+            earliest = earliest.max(now - ChronoDuration::seconds(deadline as i64));
+        }
+
+        let (most_recent, too_many_missed) = most_recent_schedule(&cron, earliest, now);
+        if too_many_missed {
+            // only the most recent missed time runs, so the cron job catches up
+            recorder
+                .publish(Event {
+                    type_: EventType::Warning,
+                    reason: "TooManyMissedTimes".into(),
+                    note: Some(format!(
+                        "too many missed start times (> {}), set or decrease .spec.startingDeadlineSeconds",
+                        MAX_MISSED_START_TIMES
+                    )),
+                    action: "Scheduling".into(),
+                    secondary: None,
+                })
+                .await?;
+        }
+        match most_recent {
+            None => (),
+            Some(_) if self.spec.concurrency_policy == ConcurrencyPolicy::Forbid && !active.is_empty() => {
+                // retried until the starting deadline passes
+                info!(
+                    "cron job {}/{} skips its run, as job `{}` is still active",
+                    ns,
+                    name,
+                    active[0].name_any()
+                );
+            }
+            Some(scheduled) => {
+                if self.spec.concurrency_policy == ConcurrencyPolicy::Replace {
+                    for job in active.drain(..) {
+                        info!("replace active job {}/{}", ns, job.name_any());
+                        jobs.delete(&job.name_any(), &DeleteParams::background()).await?;
+                    }
+                }
+
+                let job = build_job(self, scheduled, &ctx.config.labels);
+                match jobs.create(&PostParams::default(), &job).await {
+                    Ok(_) => {
+                        recorder
+                            .publish(Event {
+                                type_: EventType::Normal,
+                                reason: "SuccessfulCreate".into(),
+                                note: Some(format!("Created job `{}`", job.name_any())),
+                                action: "Scheduling".into(),
+                                secondary: None,
+                            })
+                            .await?;
+                    }
+                    // the job was created, but the status wasn't updated
+                    Err(kube::Error::Api(err)) if err.code == 409 => (),
+                    Err(err) => return Err(err),
+                }
+                active.push(job);
+                status.last_schedule_time = Some(Time(scheduled));
+            }
+        }
+
+        status.active = active.iter().map(|job| job.name_any()).collect();
+        apply_status(&cron_jobs, self, &status).await?;
+
+        // wake up at the next scheduled time
+        let next = cron
+            .find_next_occurrence(&now, false)
+            .map(|next| (next - now).to_std().unwrap_or_default())
+            .unwrap_or_else(|_| Duration::from_secs(60 * 60));
+        Ok(Action::requeue(next + Duration::from_secs(1)))
+    }
+
+    async fn cleanup(&self, ctx: Arc<Context>) -> Result<Action, kube::Error> {
+        ctx.backoff.reset(&ObjectRef::from_obj(self).to_string());
+        Ok(Action::await_change())
+    }
+
+    fn error_policy(&self, error: &Error, ctx: Arc<Context>) -> Action {
+        warn!("reconcile cron job failed: {:?}", error);
+        Action::requeue(ctx.backoff.next(&ObjectRef::from_obj(self).to_string()))
+    }
+}
+
+/// The latest scheduled time after `earliest` and until `now`, if any, and whether too many start times
+/// were missed, e.g. when the controller was down for long.
+fn most_recent_schedule(
+    cron: &Cron,
+    earliest: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> (Option<DateTime<Utc>>, bool) {
+    let missed = cron
+        .iter_after(earliest)
+        .take_while(|t| *t <= now)
+        .take(MAX_MISSED_START_TIMES + 1)
+        .collect::<Vec<_>>();
+    if missed.len() <= MAX_MISSED_START_TIMES {
+        return (missed.last().copied(), false);
+    }
+
+    // look for the most recent time in a window before now, growing until it has one, instead of
+    // iterating over all the missed times
+    let mut window = ChronoDuration::minutes(1);
+    loop {
+        let start = (now - window).max(earliest);
+        if let Some(time) = cron.iter_after(start).take_while(|t| *t <= now).last() {
+            return (Some(time), true);
+        }
+        window = window * 2;
+    }
+}
+
+/// The job scheduled at the given time, named after the minute it is scheduled at, so a run is never
+/// created twice.
+fn build_job(cron_job: &CronJob, scheduled: DateTime<Utc>, label_names: &LabelNames) -> Job {
+    let template = &cron_job.spec.job_template;
+    let mut labels = template
+        .metadata
+        .as_ref()
+        .and_then(|m| m.labels.clone())
+        .unwrap_or_default();
+    labels.insert(label_names.cron_job.clone(), cron_job.name_any());
+
+    Job {
+        metadata: ObjectMeta {
+            name: Some(format!("{}-{}", cron_job.name_any(), scheduled.timestamp() / 60)),
+            namespace: cron_job.namespace(),
+            labels: Some(labels),
+            annotations: template.metadata.as_ref().and_then(|m| m.annotations.clone()),
+            owner_references: Some(vec![cron_job.controller_owner_ref(&()).unwrap()]),
+            ..Default::default()
+        },
+        spec: template.spec.clone(),
+        status: None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::{build_job, most_recent_schedule};
+    use crate::manager::build_pod;
+    use chrono::{DateTime, Utc};
+    use croner::Cron;
+    use habitat_api::{batch::LabelNames, CronJob};
+    use kube::ResourceExt;
+
+    fn time(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_most_recent_schedule() {
+        let cron = Cron::new("*/15 * * * *").parse().unwrap();
+        let earliest = time("2022-01-01T00:00:00Z");
+
+        assert_eq!(
+            most_recent_schedule(&cron, earliest, time("2022-01-01T00:10:00Z")),
+            (None, false)
+        );
+        assert_eq!(
+            most_recent_schedule(&cron, earliest, time("2022-01-01T00:50:00Z")),
+            (Some(time("2022-01-01T00:45:00Z")), false)
+        );
+        // the earliest time is already scheduled
+        assert_eq!(
+            most_recent_schedule(&cron, time("2022-01-01T00:45:00Z"), time("2022-01-01T00:50:00Z")),
+            (None, false)
+        );
+
+        // too many missed times still run the most recent one, so the cron job recovers once it's
+        // recorded as the last schedule time
+        let now = time("2022-02-01T00:05:00Z");
+        let (most_recent, too_many_missed) = most_recent_schedule(&cron, earliest, now);
+        assert_eq!(most_recent, Some(time("2022-02-01T00:00:00Z")));
+        assert!(too_many_missed);
+        assert_eq!(
+            most_recent_schedule(&cron, most_recent.unwrap(), now),
+            (None, false)
+        );
+    }
+
+    #[test]
+    fn test_runs_have_distinct_pods() {
+        let cron_job = serde_json::from_value::<CronJob>(serde_json::json!({
+            "apiVersion": "batch.habitat/v1beta1",
+            "kind": "CronJob",
+            "metadata": {"name": "nightly", "namespace": "default", "uid": "1234"},
+            "spec": {
+                "schedule": "0 0 * * *",
+                "jobTemplate": {"spec": {
+                    "tasks": [{"name": "worker", "parallelism": {"min": 2, "max": 2}, "template": {"spec": {"containers": []}}}]
+                }}
+            }
+        }))
+        .unwrap();
+
+        // the finished runs kept by the history limits keep their pods in the namespace
+        let mut names = HashSet::new();
+        for (uid, scheduled) in [("1", "2022-01-01T00:00:00Z"), ("2", "2022-01-02T00:00:00Z")] {
+            let labels = LabelNames::default();
+            let mut job = build_job(&cron_job, time(scheduled), &labels);
+            job.metadata.uid = Some(uid.to_string());
+            for index in 0..2 {
+                let pod = build_pod(&job, &job.spec.tasks[0], index, &labels);
+                assert!(
+                    names.insert(pod.name_any()),
+                    "pod {} is created twice",
+                    pod.name_any()
+                );
+            }
+        }
+        assert_eq!(names.len(), 4);
+    }
+}
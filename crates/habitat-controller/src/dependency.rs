@@ -0,0 +1,197 @@
+use std::collections::{HashMap, HashSet};
+
+use habitat_api::{
+    batch::{JobStatusPhase, WAITING_FOR_DEPENDENCIES_CONDITION},
+    Job,
+};
+use kube::{
+    api::Api,
+    runtime::reflector::{ObjectRef, Store},
+    ResourceExt,
+};
+
+use crate::{manager::new_condition, status::apply_job_status};
+
+/// Checks the dependencies of a pending job, and reports them in its `WaitingForDependencies` condition.
+///
+/// Returns whether the job has to keep waiting. A job whose dependency completed with an unexpected phase
+/// waits until the dependency is recreated, and so does a job depending on itself through its dependencies.
+/// A job whose condition changed waits for the update of its status to reconcile it again.
+pub(crate) async fn wait_for_dependencies(job: &Job, jobs: &Api<Job>) -> Result<bool, kube::Error> {
+    if job.spec.depends_on.is_empty() {
+        return Ok(false);
+    }
+
+    let mut waiting = vec![];
+    let mut unsatisfiable = vec![];
+    for dependency in &job.spec.depends_on {
+        let phase = match jobs.get_opt(&dependency.name).await? {
+            Some(dep) => dep.status.map(|s| s.phase).unwrap_or_default(),
+            None => {
+                waiting.push(format!("job `{}` not found", dependency.name));
+                continue;
+            }
+        };
+        match dependency.phase.is_satisfied_by(&phase) {
+            Some(true) => (),
+            Some(false) => unsatisfiable.push(format!(
+                "job `{}` is {:?}, expected {:?}",
+                dependency.name, phase, dependency.phase
+            )),
+            None => waiting.push(format!("job `{}` is {:?}", dependency.name, phase)),
+        }
+    }
+
+    if !waiting.is_empty() {
+        if let Some(cycle) = dependency_cycle(job, jobs).await? {
+            unsatisfiable.push(format!("jobs {} depend on each other", cycle.join(" -> ")));
+        }
+    }
+
+    let condition = if !unsatisfiable.is_empty() {
+        new_condition(
+            WAITING_FOR_DEPENDENCIES_CONDITION,
+            true,
+            "DependencyFailed",
+            Some(unsatisfiable.join(", ")),
+        )
+    } else if !waiting.is_empty() {
+        new_condition(
+            WAITING_FOR_DEPENDENCIES_CONDITION,
+            true,
+            "Waiting",
+            Some(waiting.join(", ")),
+        )
+    } else {
+        new_condition(
+            WAITING_FOR_DEPENDENCIES_CONDITION,
+            false,
+            "DependenciesCompleted",
+            None,
+        )
+    };
+
+    let mut status = job.status.clone().unwrap_or_default();
+    let current = status.condition(WAITING_FOR_DEPENDENCIES_CONDITION).cloned();
+    status.set_condition(condition);
+    let changed = current.as_ref().map(|c| (&c.status, &c.reason, &c.message))
+        != status
+            .condition(WAITING_FOR_DEPENDENCIES_CONDITION)
+            .map(|c| (&c.status, &c.reason, &c.message));
+    if changed {
+        apply_job_status(jobs, job, status).await?;
+    }
+
+    Ok(changed || !unsatisfiable.is_empty() || !waiting.is_empty())
+}
+
+/// The dependencies of the job leading back to it, if any.
+async fn dependency_cycle(job: &Job, jobs: &Api<Job>) -> Result<Option<Vec<String>>, kube::Error> {
+    let mut graph = HashMap::new();
+    graph.insert(job.name_any(), dependency_names(job));
+    let mut unvisited = dependency_names(job);
+    while let Some(name) = unvisited.pop() {
+        if graph.contains_key(&name) {
+            continue;
+        }
+        let names = jobs
+            .get_opt(&name)
+            .await?
+            .map(|dep| dependency_names(&dep))
+            .unwrap_or_default();
+        unvisited.extend(names.iter().cloned());
+        graph.insert(name, names);
+    }
+    Ok(find_cycle(&job.name_any(), &graph))
+}
+
+fn dependency_names(job: &Job) -> Vec<String> {
+    job.spec.depends_on.iter().map(|d| d.name.clone()).collect()
+}
+
+/// The path from `start` back to it in the dependency graph, if any.
+fn find_cycle(start: &str, graph: &HashMap<String, Vec<String>>) -> Option<Vec<String>> {
+    let mut path = vec![start.to_string()];
+    let mut visited = HashSet::new();
+    // depth-first, with the index of the next dependency to visit of every job of the path
+    let mut next = vec![0];
+    while let (Some(name), Some(idx)) = (path.last(), next.last_mut()) {
+        let dependency = graph.get(name).and_then(|deps| deps.get(*idx));
+        *idx += 1;
+        match dependency {
+            Some(dependency) if dependency == start => {
+                path.push(dependency.clone());
+                return Some(path);
+            }
+            Some(dependency) if visited.insert(dependency.clone()) => {
+                path.push(dependency.clone());
+                next.push(0);
+            }
+            Some(_) => (),
+            None => {
+                path.pop();
+                next.pop();
+            }
+        }
+    }
+    None
+}
+
+/// The jobs which depend on the given one, to be reconciled when it changes.
+pub(crate) fn dependents(store: &Store<Job>, job: &Job) -> Vec<ObjectRef<Job>> {
+    let name = job.name_any();
+    store
+        .state()
+        .into_iter()
+        .filter(|dependent| {
+            dependent.namespace() == job.namespace()
+                && dependent
+                    .status
+                    .as_ref()
+                    .map(|s| s.phase.clone())
+                    .unwrap_or_default()
+                    == JobStatusPhase::Pending
+                && dependent.spec.depends_on.iter().any(|d| d.name == name)
+        })
+        .map(|dependent| ObjectRef::from_obj(dependent.as_ref()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::find_cycle;
+
+    #[test]
+    fn test_find_cycle() {
+        let graph = |edges: &[(&str, &[&str])]| {
+            edges
+                .iter()
+                .map(|(name, deps)| (name.to_string(), deps.iter().map(|d| d.to_string()).collect()))
+                .collect::<HashMap<String, Vec<String>>>()
+        };
+
+        let dag = graph(&[("a", &["b", "c"]), ("b", &["c"]), ("c", &[])]);
+        assert_eq!(find_cycle("a", &dag), None);
+
+        let cyclic = graph(&[("a", &["b", "c"]), ("b", &["c"]), ("c", &["d"]), ("d", &["a"])]);
+        assert_eq!(
+            find_cycle("a", &cyclic),
+            Some(
+                vec!["a", "b", "c", "d", "a"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect()
+            )
+        );
+        assert_eq!(
+            find_cycle("a", &graph(&[("a", &["a"])])).map(|c| c.len()),
+            Some(2)
+        );
+
+        // the jobs of a cycle the job depends on report it themselves
+        let downstream = graph(&[("a", &["b"]), ("b", &["c"]), ("c", &["b"])]);
+        assert_eq!(find_cycle("a", &downstream), None);
+    }
+}
@@ -1,12 +1,62 @@
+use kube::runtime::finalizer;
 use thiserror::Error;
 
+use crate::leader::LeaseLost;
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Finalizer Error: {0}")]
-    FinalizerError(#[source] kube::runtime::finalizer::Error<kube::Error>),
+    FinalizerError(#[source] finalizer::Error<kube::Error>),
 
     #[error("Kube Error: {0}")]
     KubeError(#[source] kube::Error),
 }
 
+impl Error {
+    /// The label of the error in the metrics
+    pub fn metric_label(&self) -> &'static str {
+        match self {
+            Error::FinalizerError(_) => "finalizer",
+            Error::KubeError(_) => "kube",
+        }
+    }
+
+    /// The api error of the failed reconciliation, if any
+    pub fn kube_error(&self) -> Option<&kube::Error> {
+        match self {
+            Error::KubeError(err)
+            | Error::FinalizerError(
+                finalizer::Error::ApplyFailed(err)
+                | finalizer::Error::CleanupFailed(err)
+                | finalizer::Error::AddFinalizer(err)
+                | finalizer::Error::RemoveFinalizer(err),
+            ) => Some(err),
+            Error::FinalizerError(finalizer::Error::UnnamedObject) => None,
+        }
+    }
+
+    /// Whether retrying can't succeed until the object changes, e.g. when the api server rejects the pods
+    /// of an invalid task template. Other errors, like conflicts, throttling or timeouts, are transient.
+    pub fn is_permanent(&self) -> bool {
+        match self.kube_error() {
+            Some(kube::Error::Api(err)) => matches!(err.code, 400 | 422),
+            Some(kube::Error::SerdeError(_)) => true,
+            Some(_) => false,
+            None => true,
+        }
+    }
+}
+
+/// Why the controllers of the manager stopped
+#[derive(Error, Debug)]
+pub enum ManagerError {
+    /// The CRDs aren't installed, or the queues, jobs and cron jobs can't be listed, e.g. when the
+    /// namespace-scoped controller isn't allowed to list the cluster-scoped queues
+    #[error("is habitat installed? {0}")]
+    NotInstalled(#[source] kube::Error),
+
+    #[error(transparent)]
+    LeaseLost(#[from] LeaseLost),
+}
+
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,261 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use k8s_openapi::api::core::v1::Pod;
+use kube::api::{Api, DeleteParams, PostParams};
+use tokio::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::cache::controller_uid;
+
+/// How long to wait for the pod cache to observe a write before reconciling without it, in case its event
+/// was missed
+pub const EXPECTATIONS_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// The pod creations and deletions issued for every job, which the pod cache hasn't observed yet
+///
+/// A job whose writes aren't observed yet isn't reconciled, so its pods aren't created or deleted again
+/// from a stale view of them.
+#[derive(Clone, Default)]
+pub struct Expectations {
+    pending: Arc<Mutex<HashMap<String, PendingWrites>>>,
+}
+
+struct PendingWrites {
+    /// The names of the pods created, but not observed yet
+    creations: HashSet<String>,
+    /// The names of the pods deleted, but still observed alive
+    deletions: HashSet<String>,
+    /// When the last write was issued
+    since: Instant,
+}
+
+impl Expectations {
+    /// Creates a pod of the owner, expecting the pod cache to observe it.
+    ///
+    /// Returns whether the pod was created. A pod of the owner which already exists was created by a
+    /// previous reconciliation, so it's expected as well. A pod of another owner with the same name is an
+    /// error.
+    pub async fn create_pod(&self, pods: &Api<Pod>, owner_uid: &str, pod: &Pod) -> Result<bool, kube::Error> {
+        let name = pod.metadata.name.clone().unwrap_or_default();
+        self.expect(owner_uid, |writes| {
+            writes.creations.insert(name.clone());
+        });
+        let result = match pods.create(&PostParams::default(), pod).await {
+            Ok(_) => Ok(true),
+            Err(kube::Error::Api(mut err)) if err.reason == "AlreadyExists" => {
+                match pods.get_opt(&name).await {
+                    Ok(Some(existing)) if controller_uid(&existing) == Some(owner_uid) => Ok(false),
+                    Ok(_) => {
+                        err.message = format!("{}, and isn't owned by the job", err.message);
+                        Err(kube::Error::Api(err))
+                    }
+                    Err(get_err) => Err(get_err),
+                }
+            }
+            Err(err) => Err(err),
+        };
+        if result.is_err() {
+            // the failed creation will never be observed
+            self.expect(owner_uid, |writes| {
+                writes.creations.remove(&name);
+            });
+        }
+        result
+    }
+
+    /// Deletes a pod of the owner, expecting the pod cache to observe it terminating.
+    ///
+    /// Returns whether the pod was deleted. A pod which doesn't exist anymore was deleted by a previous
+    /// reconciliation.
+    pub async fn delete_pod(
+        &self,
+        pods: &Api<Pod>,
+        owner_uid: &str,
+        name: &str,
+    ) -> Result<bool, kube::Error> {
+        self.expect(owner_uid, |writes| {
+            writes.deletions.insert(name.to_string());
+        });
+        match pods.delete(name, &DeleteParams::default()).await {
+            Ok(_) => Ok(true),
+            Err(kube::Error::Api(err)) if err.code == 404 => Ok(false),
+            Err(err) => {
+                self.expect(owner_uid, |writes| {
+                    writes.deletions.remove(name);
+                });
+                Err(err)
+            }
+        }
+    }
+
+    /// Whether the pod cache observed all the writes issued for the owner, given the pods it caches.
+    pub fn satisfied(&self, owner_uid: &str, owned_pods: &HashMap<String, Pod>) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        let writes = match pending.get_mut(owner_uid) {
+            Some(writes) => writes,
+            None => return true,
+        };
+        writes.creations.retain(|name| !owned_pods.contains_key(name));
+        writes.deletions.retain(|name| {
+            owned_pods
+                .get(name)
+                .map(|pod| pod.metadata.deletion_timestamp.is_none())
+                .unwrap_or(false)
+        });
+
+        if writes.creations.is_empty() && writes.deletions.is_empty() {
+            pending.remove(owner_uid);
+            return true;
+        }
+        if writes.since.elapsed() > EXPECTATIONS_TIMEOUT {
+            warn!(
+                "pod cache didn't observe the creation of {:?} and the deletion of {:?} for owner {}",
+                writes.creations, writes.deletions, owner_uid
+            );
+            pending.remove(owner_uid);
+            return true;
+        }
+        false
+    }
+
+    /// Forgets the writes issued for an owner which is deleted.
+    pub fn forget(&self, owner_uid: &str) {
+        self.pending.lock().unwrap().remove(owner_uid);
+    }
+
+    fn expect(&self, owner_uid: &str, f: impl FnOnce(&mut PendingWrites)) {
+        let mut pending = self.pending.lock().unwrap();
+        let writes = pending
+            .entry(owner_uid.to_string())
+            .or_insert_with(|| PendingWrites {
+                creations: Default::default(),
+                deletions: Default::default(),
+                since: Instant::now(),
+            });
+        f(writes);
+        writes.since = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use http::{Request, Response};
+    use hyper::Body;
+    use k8s_openapi::{api::core::v1::Pod, apimachinery::pkg::apis::meta::v1::Time};
+    use kube::{Api, Client};
+
+    use super::Expectations;
+
+    fn pod(name: &str) -> (String, Pod) {
+        let mut pod = Pod::default();
+        pod.metadata.name = Some(name.to_string());
+        (name.to_string(), pod)
+    }
+
+    #[test]
+    fn test_expectations() {
+        let expectations = Expectations::default();
+        assert!(expectations.satisfied("job", &HashMap::new()));
+
+        expectations.expect("job", |writes| {
+            writes.creations.insert("pod-0".to_string());
+        });
+        expectations.expect("job", |writes| {
+            writes.deletions.insert("pod-1".to_string());
+        });
+        let mut owned_pods = HashMap::from([pod("pod-1")]);
+        assert!(!expectations.satisfied("job", &owned_pods));
+
+        // the creation is observed, the deleted pod is still alive
+        owned_pods.extend([pod("pod-0")]);
+        assert!(!expectations.satisfied("job", &owned_pods));
+
+        // the deleted pod is observed terminating
+        owned_pods.get_mut("pod-1").unwrap().metadata.deletion_timestamp = Some(Time(chrono::Utc::now()));
+        assert!(expectations.satisfied("job", &owned_pods));
+
+        expectations.expect("other", |writes| {
+            writes.creations.insert("pod-0".to_string());
+        });
+        expectations.forget("other");
+        assert!(expectations.satisfied("other", &HashMap::new()));
+    }
+
+    fn owned_pod(name: &str, owner_uid: &str) -> serde_json::Value {
+        serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": name,
+                "namespace": "default",
+                "ownerReferences": [{
+                    "apiVersion": "batch.habitat/v1beta1",
+                    "kind": "Job",
+                    "name": "job",
+                    "uid": owner_uid,
+                    "controller": true
+                }]
+            }
+        })
+    }
+
+    /// Creates the pod against an api server which already has a pod of the same name.
+    async fn create_existing_pod(
+        expectations: &Expectations,
+        existing_owner_uid: &str,
+    ) -> Result<bool, kube::Error> {
+        let (service, mut handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
+        let pods: Api<Pod> = Api::default_namespaced(Client::new(service, "default"));
+        let existing = owned_pod("job-worker-0", existing_owner_uid);
+        let server = tokio::spawn(async move {
+            let (request, send) = handle.next_request().await.unwrap();
+            assert_eq!(request.method(), http::Method::POST);
+            let conflict = serde_json::json!({
+                "kind": "Status",
+                "apiVersion": "v1",
+                "status": "Failure",
+                "message": "pods \"job-worker-0\" already exists",
+                "reason": "AlreadyExists",
+                "code": 409
+            });
+            send.send_response(
+                Response::builder()
+                    .status(409)
+                    .body(Body::from(conflict.to_string()))
+                    .unwrap(),
+            );
+
+            let (request, send) = handle.next_request().await.unwrap();
+            assert_eq!(request.method(), http::Method::GET);
+            send.send_response(
+                Response::builder()
+                    .body(Body::from(existing.to_string()))
+                    .unwrap(),
+            );
+        });
+
+        let pod = serde_json::from_value(owned_pod("job-worker-0", "job")).unwrap();
+        let result = expectations.create_pod(&pods, "job", &pod).await;
+        server.await.unwrap();
+        result
+    }
+
+    #[tokio::test]
+    async fn test_create_existing_pod() {
+        // the pod was created by a previous reconciliation of the job
+        let expectations = Expectations::default();
+        assert!(!create_existing_pod(&expectations, "job").await.unwrap());
+        assert!(!expectations.satisfied("job", &HashMap::new()));
+
+        // the pod of another job is never observed as a pod of the job
+        let expectations = Expectations::default();
+        let err = create_existing_pod(&expectations, "other").await.unwrap_err();
+        assert!(matches!(err, kube::Error::Api(err) if err.reason == "AlreadyExists"));
+        assert!(expectations.satisfied("job", &HashMap::new()));
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::{BTreeMap, HashSet};
+
+use habitat_api::{
+    batch::{JobStatusPhase, LabelNames, PodFailurePolicyAction},
+    Job,
+};
+use k8s_openapi::{api::core::v1::Pod, apimachinery::pkg::apis::meta::v1::Time};
+use kube::{
+    api::Api,
+    runtime::events::{Event, EventType, Recorder},
+    ResourceExt,
+};
+use tracing::info;
+
+use crate::{
+    manager::{new_condition, Context},
+    status::apply_job_status,
+};
+
+/// The condition of a job failed by the pod failure policy of one of its tasks
+pub const FAILED_CONDITION: &str = "Failed";
+
+/// The action of the pod failure policy of its task for a failed pod, and why the rule matched.
+pub(crate) fn pod_failure_action(
+    job: &Job,
+    pod: &Pod,
+    labels: &LabelNames,
+) -> Option<(PodFailurePolicyAction, String)> {
+    let phase = pod.status.as_ref().and_then(|s| s.phase.as_deref());
+    if phase != Some("Failed") || pod.metadata.deletion_timestamp.is_some() {
+        return None;
+    }
+    let task_name = pod.labels().get(&labels.task_name)?;
+    let task = job.spec.tasks.iter().find(|t| &t.name == task_name)?;
+    task.pod_failure_policy.as_ref()?.match_pod(pod)
+}
+
+/// Counts a restart of the pods of a task by its pod failure policy.
+///
+/// Returns why the job fails instead, if the task was already restarted as many times as its restart limit.
+pub(crate) fn count_restart(
+    job: &Job,
+    task_name: &str,
+    restarts: &mut BTreeMap<String, u32>,
+) -> Result<(), String> {
+    let limit = job
+        .spec
+        .tasks
+        .iter()
+        .find(|t| t.name == task_name)
+        .and_then(|t| t.pod_failure_policy.as_ref())
+        .map(|p| p.restart_limit)
+        .unwrap_or_default();
+    let count = restarts.entry(task_name.to_string()).or_default();
+    if *count >= limit {
+        return Err(format!(
+            "task `{}` was restarted {} times, reaching its restart limit",
+            task_name, count
+        ));
+    }
+    *count += 1;
+    Ok(())
+}
+
+/// Applies the pod failure policies to the failed pods of a job, counting the restarts of every task.
+///
+/// Returns the names of the deleted pods, or `None` if the status of the job was updated, i.e. the job
+/// failed or the restart of a task was counted, which reconciles the job again.
+pub(crate) async fn apply_pod_failure_policies<'a>(
+    job: &Job,
+    owned_pods: impl IntoIterator<Item = &'a Pod> + Clone,
+    jobs: &Api<Job>,
+    pods: &Api<Pod>,
+    recorder: &Recorder,
+    ctx: &Context,
+) -> Result<Option<HashSet<String>>, kube::Error> {
+    let uid = job.uid().unwrap_or_default();
+    let mut deleted = HashSet::new();
+    for pod in owned_pods.clone() {
+        if deleted.contains(&pod.name_any()) {
+            // already restarted with its task
+            continue;
+        }
+        let (action, reason) = match pod_failure_action(job, pod, &ctx.config.labels) {
+            Some(action) => action,
+            None => continue,
+        };
+        let message = format!("Pod `{}` failed: {}", pod.name_any(), reason);
+        info!("{}, {:?}", message, action);
+
+        match action {
+            PodFailurePolicyAction::Count => (),
+            PodFailurePolicyAction::Ignore => {
+                if ctx.expectations.delete_pod(pods, &uid, &pod.name_any()).await? {
+                    ctx.metrics.pods_deleted.inc();
+                }
+                deleted.insert(pod.name_any());
+            }
+            PodFailurePolicyAction::RestartTask => {
+                let task = pod.labels().get(&ctx.config.labels.task_name);
+                let mut status = job.status.clone().unwrap_or_default();
+                if let Err(reason) = count_restart(
+                    job,
+                    task.map(String::as_str).unwrap_or_default(),
+                    &mut status.restarts,
+                ) {
+                    let message = format!("{}, {}", message, reason);
+                    fail_job(job, &message, owned_pods, jobs, pods, recorder, ctx).await?;
+                    return Ok(None);
+                }
+                // the restart is counted before the pods are deleted, so it's never lost
+                apply_job_status(jobs, job, status).await?;
+
+                for task_pod in owned_pods.clone() {
+                    if task_pod.labels().get(&ctx.config.labels.task_name) == task
+                        && task_pod.metadata.deletion_timestamp.is_none()
+                        && deleted.insert(task_pod.name_any())
+                        && ctx
+                            .expectations
+                            .delete_pod(pods, &uid, &task_pod.name_any())
+                            .await?
+                    {
+                        ctx.metrics.pods_deleted.inc();
+                    }
+                }
+                recorder
+                    .publish(Event {
+                        type_: EventType::Warning,
+                        reason: "RestartTask".into(),
+                        note: Some(format!(
+                            "{}, restarting task `{}`",
+                            message,
+                            task.cloned().unwrap_or_default()
+                        )),
+                        action: "Restarting".into(),
+                        secondary: None,
+                    })
+                    .await?;
+                return Ok(None);
+            }
+            PodFailurePolicyAction::FailJob => {
+                fail_job(job, &message, owned_pods, jobs, pods, recorder, ctx).await?;
+                return Ok(None);
+            }
+        }
+    }
+    Ok(Some(deleted))
+}
+
+/// Marks the job as failed, and terminates its pods which are still active.
+pub(crate) async fn fail_job<'a>(
+    job: &Job,
+    message: &str,
+    owned_pods: impl IntoIterator<Item = &'a Pod>,
+    jobs: &Api<Job>,
+    pods: &Api<Pod>,
+    recorder: &Recorder,
+    ctx: &Context,
+) -> Result<(), kube::Error> {
+    let uid = job.uid().unwrap_or_default();
+    for pod in owned_pods {
+        let phase = pod.status.as_ref().and_then(|s| s.phase.as_deref());
+        if !matches!(phase, Some("Succeeded" | "Failed"))
+            && pod.metadata.deletion_timestamp.is_none()
+            && ctx.expectations.delete_pod(pods, &uid, &pod.name_any()).await?
+        {
+            ctx.metrics.pods_deleted.inc();
+        }
+    }
+
+    let mut status = job.status.clone().unwrap_or_default();
+    status.phase = JobStatusPhase::Failed;
+    status.set_condition(new_condition(
+        FAILED_CONDITION,
+        true,
+        "PodFailurePolicy",
+        Some(message.to_string()),
+    ));
+    status.completion_time = Some(Time(chrono::Utc::now()));
+    apply_job_status(jobs, job, status).await?;
+
+    recorder
+        .publish(Event {
+            type_: EventType::Warning,
+            reason: "FailJob".into(),
+            note: Some(message.to_string()),
+            action: "Failing".into(),
+            secondary: None,
+        })
+        .await
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::count_restart;
+    use habitat_api::Job;
+
+    #[test]
+    fn test_count_restart() {
+        let job = serde_json::from_value::<Job>(serde_json::json!({
+            "apiVersion": "batch.habitat/v1beta1",
+            "kind": "Job",
+            "metadata": {"name": "train", "namespace": "default"},
+            "spec": {
+                "tasks": [{
+                    "name": "worker",
+                    "parallelism": {"min": 1},
+                    "template": {"spec": {"containers": []}},
+                    "podFailurePolicy": {
+                        "rules": [{"action": "RestartTask", "onExitCodes": {"operator": "In", "values": [1]}}],
+                        "restartLimit": 2
+                    }
+                }]
+            }
+        }))
+        .unwrap();
+
+        let mut restarts = BTreeMap::new();
+        assert!(count_restart(&job, "worker", &mut restarts).is_ok());
+        assert!(count_restart(&job, "worker", &mut restarts).is_ok());
+        assert_eq!(restarts["worker"], 2);
+        assert_eq!(
+            count_restart(&job, "worker", &mut restarts),
+            Err("task `worker` was restarted 2 times, reaching its restart limit".to_string())
+        );
+        assert_eq!(restarts["worker"], 2);
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use habitat_api::{
+    batch::{format_index_ranges, parse_index_ranges, replica_index, JobStatusPhase, LabelNames},
+    resource::core_pod_requests,
+    Job,
+};
+use habitat_scheduler::fairshare::Cluster;
+use k8s_openapi::api::core::v1::Pod;
+use kube::ResourceExt;
+
+use crate::manager::build_pod;
+
+/// The succeeded replica indexes of every task, both the ones recorded in the job status and the ones of
+/// the succeeded pods.
+pub(crate) fn completed_indexes<'a>(
+    job: &Job,
+    pods: impl IntoIterator<Item = &'a Pod>,
+    labels: &LabelNames,
+) -> BTreeMap<String, BTreeSet<u32>> {
+    let mut completed = job
+        .spec
+        .tasks
+        .iter()
+        .map(|task| {
+            let recorded = job
+                .status
+                .as_ref()
+                .and_then(|s| s.completed_indexes.get(&task.name))
+                .map(|ranges| parse_index_ranges(ranges, task.parallelism.max))
+                .unwrap_or_default();
+            (task.name.clone(), recorded)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    for pod in pods {
+        let succeeded = pod.status.as_ref().and_then(|s| s.phase.as_deref()) == Some("Succeeded");
+        let task = pod
+            .labels()
+            .get(&labels.task_name)
+            .and_then(|t| completed.get_mut(t));
+        if let (true, Some(indexes), Some(index)) = (succeeded, task, replica_index(pod, labels)) {
+            indexes.insert(index);
+        }
+    }
+    completed
+}
+
+/// Whether a pod of a failed index has to be deleted, so that the index runs again.
+pub(crate) fn is_failed_index(
+    pod: &Pod,
+    completed: &BTreeMap<String, BTreeSet<u32>>,
+    labels: &LabelNames,
+) -> bool {
+    let failed = pod.status.as_ref().and_then(|s| s.phase.as_deref()) == Some("Failed");
+    let completed = pod
+        .labels()
+        .get(&labels.task_name)
+        .and_then(|t| completed.get(t))
+        .zip(replica_index(pod, labels))
+        .map(|(indexes, index)| indexes.contains(&index))
+        .unwrap_or_default();
+    failed && !completed && pod.metadata.deletion_timestamp.is_none()
+}
+
+/// Counts a run again of the failed index of a pod.
+///
+/// Returns why the job fails instead, if the index already ran again as many times as the backoff limit per
+/// index.
+pub(crate) fn count_index_retry(
+    job: &Job,
+    pod: &Pod,
+    retries: &mut BTreeMap<String, BTreeMap<u32, u32>>,
+    labels: &LabelNames,
+) -> Result<(), String> {
+    let (task, index) = match (pod.labels().get(&labels.task_name), replica_index(pod, labels)) {
+        (Some(task), Some(index)) => (task, index),
+        _ => return Ok(()),
+    };
+    let count = retries.entry(task.clone()).or_default().entry(index).or_default();
+    if *count >= job.spec.backoff_limit_per_index {
+        return Err(format!(
+            "index {} of task `{}` ran again {} times, reaching the backoff limit per index",
+            index, task, count
+        ));
+    }
+    *count += 1;
+    Ok(())
+}
+
+/// The pods of the replica indexes which haven't succeeded yet.
+pub(crate) fn build_indexed_pods(
+    job: &Job,
+    completed: &BTreeMap<String, BTreeSet<u32>>,
+    labels: &LabelNames,
+) -> Vec<Pod> {
+    let mut pods = vec![];
+    for task in &job.spec.tasks {
+        let indexes = completed.get(&task.name);
+        for index in 0..task.parallelism.max {
+            if !indexes.map(|i| i.contains(&index)).unwrap_or_default() {
+                pods.push(build_pod(job, task, index, labels));
+            }
+        }
+    }
+    pods
+}
+
+/// Keeps the pods to create of the replica indexes below `parallelism.min`, which the job was admitted with,
+/// and of the indexes above it the cluster admits, in index order.
+pub(crate) fn admitted_indexed_pods(
+    job: &Job,
+    pods: Vec<Pod>,
+    cluster: &Cluster,
+    labels: &LabelNames,
+) -> Vec<Pod> {
+    let is_elastic = |pod: &Pod| {
+        let task = pod
+            .labels()
+            .get(&labels.task_name)
+            .and_then(|name| job.spec.tasks.iter().find(|t| &t.name == name));
+        match (task, replica_index(pod, labels)) {
+            (Some(task), Some(index)) => index >= task.parallelism.min,
+            _ => false,
+        }
+    };
+    let (mut admitted, elastic): (Vec<_>, Vec<_>) = pods.into_iter().partition(|pod| !is_elastic(pod));
+    let requests = elastic
+        .iter()
+        .map(|pod| pod.spec.as_ref().map(core_pod_requests).unwrap_or_default())
+        .collect::<Vec<_>>();
+    let priority = cluster.priority(&job.spec.priority);
+    let count = cluster.admit_elastic_pods(job.spec.queue.as_deref(), priority, &requests);
+    admitted.extend(elastic.into_iter().take(count));
+    admitted
+}
+
+/// The phase of an indexed job, which succeeds once every index of every task succeeded.
+pub(crate) fn indexed_phase(
+    job: &Job,
+    completed: &BTreeMap<String, BTreeSet<u32>>,
+    running: bool,
+) -> JobStatusPhase {
+    let done = job.spec.tasks.iter().all(|task| {
+        let indexes = completed.get(&task.name);
+        (0..task.parallelism.max).all(|index| indexes.map(|i| i.contains(&index)).unwrap_or_default())
+    });
+    match (done, running) {
+        (true, _) => JobStatusPhase::Succeeded,
+        (false, true) => JobStatusPhase::Running,
+        (false, false) => JobStatusPhase::Ready,
+    }
+}
+
+/// The completed indexes as compressed ranges by task name, for the job status.
+pub(crate) fn format_completed_indexes(
+    completed: &BTreeMap<String, BTreeSet<u32>>,
+) -> BTreeMap<String, String> {
+    completed
+        .iter()
+        .filter(|(_, indexes)| !indexes.is_empty())
+        .map(|(task, indexes)| (task.clone(), format_index_ranges(indexes)))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use super::{
+        admitted_indexed_pods, build_indexed_pods, completed_indexes, count_index_retry, indexed_phase,
+        is_failed_index,
+    };
+    use habitat_api::{
+        batch::{JobStatusPhase, LabelNames},
+        Job,
+    };
+    use habitat_scheduler::fairshare::Cluster;
+    use k8s_openapi::api::core::v1::Pod;
+    use kube::ResourceExt;
+
+    fn pod(index: u32, phase: &str) -> Pod {
+        serde_json::from_value(serde_json::json!({
+            "metadata": {
+                "name": format!("worker-{}", index),
+                "labels": {"habitat-task": "worker", "habitat-replica-index": index.to_string()}
+            },
+            "status": {"phase": phase}
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_completed_indexes() {
+        let job = serde_json::from_value::<Job>(serde_json::json!({
+            "apiVersion": "batch.habitat/v1beta1",
+            "kind": "Job",
+            "metadata": {"name": "indexed", "namespace": "default", "uid": "1234"},
+            "spec": {
+                "completionMode": "Indexed",
+                "tasks": [{"name": "worker", "parallelism": {"min": 1, "max": 5}, "template": {"spec": {"containers": []}}}]
+            },
+            "status": {"phase": "Running", "pending": 0, "running": 0, "terminating": 0, "succeeded": 0, "failed": 0,
+                       "completedIndexes": {"worker": "0-1"}}
+        }))
+        .unwrap();
+        let pods = vec![pod(2, "Succeeded"), pod(3, "Failed"), pod(4, "Running")];
+
+        let labels = LabelNames::default();
+        let completed = completed_indexes(&job, &pods, &labels);
+        assert_eq!(completed["worker"], BTreeSet::from([0, 1, 2]));
+        assert!(!is_failed_index(&pods[0], &completed, &labels));
+        assert!(is_failed_index(&pods[1], &completed, &labels));
+
+        let names = build_indexed_pods(&job, &completed, &labels)
+            .iter()
+            .map(|p| p.name_any())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["indexed-worker-3", "indexed-worker-4"]);
+        assert_eq!(indexed_phase(&job, &completed, true), JobStatusPhase::Running);
+    }
+
+    #[test]
+    fn test_admitted_indexed_pods() {
+        let job = serde_json::from_value::<Job>(serde_json::json!({
+            "apiVersion": "batch.habitat/v1beta1",
+            "kind": "Job",
+            "metadata": {"name": "indexed", "namespace": "default", "uid": "1234"},
+            "spec": {
+                "completionMode": "Indexed",
+                "tasks": [{
+                    "name": "worker",
+                    "parallelism": {"min": 1, "max": 4},
+                    "template": {"spec": {"containers": [{"name": "main", "resources": {"requests": {"cpu": "1"}}}]}}
+                }]
+            }
+        }))
+        .unwrap();
+        let labels = LabelNames::default();
+        let pods = build_indexed_pods(&job, &BTreeMap::new(), &labels);
+
+        // the minimum index is admitted with the job, and the cluster has room for a single other one
+        let cluster = Cluster {
+            capacity: [("cpu".to_string(), 2.0)].into_iter().collect(),
+            allocated: [("cpu".to_string(), 1.0)].into_iter().collect(),
+            ..Default::default()
+        };
+        let names = admitted_indexed_pods(&job, pods, &cluster, &labels)
+            .iter()
+            .map(|p| p.name_any())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["indexed-worker-0", "indexed-worker-1"]);
+    }
+
+    #[test]
+    fn test_count_index_retry() {
+        let job = serde_json::from_value::<Job>(serde_json::json!({
+            "apiVersion": "batch.habitat/v1beta1",
+            "kind": "Job",
+            "metadata": {"name": "indexed", "namespace": "default"},
+            "spec": {
+                "completionMode": "Indexed",
+                "backoffLimitPerIndex": 1,
+                "tasks": [{"name": "worker", "parallelism": {"min": 1, "max": 2}, "template": {"spec": {"containers": []}}}]
+            }
+        }))
+        .unwrap();
+        let labels = LabelNames::default();
+
+        let mut retries = BTreeMap::new();
+        assert!(count_index_retry(&job, &pod(0, "Failed"), &mut retries, &labels).is_ok());
+        assert!(count_index_retry(&job, &pod(1, "Failed"), &mut retries, &labels).is_ok());
+        assert_eq!(
+            count_index_retry(&job, &pod(0, "Failed"), &mut retries, &labels),
+            Err(
+                "index 0 of task `worker` ran again 1 times, reaching the backoff limit per index"
+                    .to_string()
+            )
+        );
+        assert_eq!(retries["worker"], BTreeMap::from([(0, 1), (1, 1)]));
+    }
+}
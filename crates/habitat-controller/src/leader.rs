@@ -0,0 +1,232 @@
+use chrono::{DateTime, Utc};
+use futures::{future::Future, FutureExt};
+use k8s_openapi::{
+    api::coordination::v1::{Lease, LeaseSpec},
+    apimachinery::pkg::apis::meta::v1::MicroTime,
+};
+use kube::{
+    api::{Api, PostParams},
+    client::Client,
+    core::ObjectMeta,
+};
+use thiserror::Error;
+use tokio::time::{sleep, timeout, Duration};
+use tracing::{info, warn};
+
+/// The default name of the lease held by the leader
+pub const LEASE_NAME: &str = "habitat-controller";
+
+/// Lease based leader election, so that only one replica of the controller reconciles at a time
+#[derive(Clone, Debug)]
+pub struct LeaderElection {
+    /// The name of the lease
+    pub lease_name: String,
+    /// The namespace of the lease
+    pub namespace: String,
+    /// The identity of this replica, unique among the replicas
+    pub identity: String,
+    /// How long the followers wait before taking over a lease which isn't renewed
+    pub lease_duration: Duration,
+    /// How long the leader keeps reconciling without renewing the lease, shorter than the lease duration
+    /// so that it stops before a follower takes over
+    pub renew_deadline: Duration,
+}
+
+/// The lease was lost, so another replica may be reconciling already
+#[derive(Error, Debug)]
+#[error("{identity} lost lease {lease_name}")]
+pub struct LeaseLost {
+    pub identity: String,
+    pub lease_name: String,
+}
+
+impl LeaderElection {
+    /// Creates the leader election, with a renew deadline of two thirds of the lease duration, e.g. 10s
+    /// for a 15s lease.
+    pub fn new(namespace: impl Into<String>, identity: impl Into<String>, lease_duration: Duration) -> Self {
+        Self {
+            lease_name: LEASE_NAME.to_string(),
+            namespace: namespace.into(),
+            identity: identity.into(),
+            lease_duration,
+            renew_deadline: lease_duration * 2 / 3,
+        }
+    }
+
+    /// Stands by until this replica acquires the lease, then runs the future until it completes or the
+    /// lease is lost.
+    ///
+    /// Losing the lease is an error, as the reconciliations were stopped midway: the process should exit,
+    /// and stand by again once restarted.
+    pub async fn run(
+        &self,
+        client: Client,
+        future: impl Future<Output = ()> + Send,
+    ) -> Result<(), LeaseLost> {
+        let leases = Api::<Lease>::namespaced(client, &self.namespace);
+        // retry a few times per renew deadline, so that a failed renewal doesn't lose the lease
+        let retry_period = self.renew_deadline / 4;
+
+        let mut renewed = loop {
+            match timeout(self.renew_deadline, self.try_acquire_or_renew(&leases)).await {
+                Ok(Ok(Some(renewed))) => break renewed,
+                Ok(Ok(None)) => (),
+                Ok(Err(err)) => warn!("failed to acquire lease {}: {:?}", self.lease_name, err),
+                Err(_) => warn!("timed out acquiring lease {}", self.lease_name),
+            }
+            sleep(retry_period).await;
+        };
+        info!(
+            "{} acquired lease {}, start reconciling",
+            self.identity, self.lease_name
+        );
+
+        let renew = async {
+            let renew_deadline = chrono::Duration::from_std(self.renew_deadline).unwrap();
+            loop {
+                sleep(retry_period).await;
+                // the deadline is measured from the renew time written into the lease, which the followers
+                // measure the lease duration from
+                let remaining = (renewed + renew_deadline - Utc::now())
+                    .to_std()
+                    .unwrap_or_default();
+                match timeout(remaining, self.try_acquire_or_renew(&leases)).await {
+                    Ok(Ok(Some(renew_time))) => renewed = renew_time,
+                    Ok(Ok(None)) => break,
+                    Ok(Err(err)) => warn!("failed to renew lease {}: {:?}", self.lease_name, err),
+                    Err(_) => warn!("timed out renewing lease {}", self.lease_name),
+                }
+                if Utc::now() - renewed >= renew_deadline {
+                    break;
+                }
+            }
+            warn!("{} lost lease {}", self.identity, self.lease_name);
+        };
+
+        let stopped = futures::future::select(future.boxed(), renew.boxed()).await;
+        match stopped {
+            futures::future::Either::Left(_) => Ok(()),
+            futures::future::Either::Right(_) => Err(LeaseLost {
+                identity: self.identity.clone(),
+                lease_name: self.lease_name.clone(),
+            }),
+        }
+    }
+
+    /// Acquires the lease if it's free or expired, or renews it if it's already held.
+    ///
+    /// Returns the renew time written into the lease if this replica holds it.
+    async fn try_acquire_or_renew(&self, leases: &Api<Lease>) -> Result<Option<DateTime<Utc>>, kube::Error> {
+        let now = Utc::now();
+        let mut lease = match leases.get_opt(&self.lease_name).await? {
+            Some(lease) => lease,
+            None => {
+                let lease = Lease {
+                    metadata: ObjectMeta {
+                        name: Some(self.lease_name.clone()),
+                        namespace: Some(self.namespace.clone()),
+                        ..Default::default()
+                    },
+                    spec: Some(self.lease_spec(now, now, 0)),
+                };
+                let created = ignore_conflict(leases.create(&PostParams::default(), &lease).await)?;
+                return Ok(created.then(|| now));
+            }
+        };
+
+        let spec = lease.spec.take().unwrap_or_default();
+        let held = spec.holder_identity.as_deref() == Some(self.identity.as_str());
+        if !held && !is_expired(&spec, now) {
+            return Ok(None);
+        }
+
+        let (acquire_time, transitions) = match held {
+            true => (
+                spec.acquire_time.map(|t| t.0).unwrap_or(now),
+                spec.lease_transitions.unwrap_or_default(),
+            ),
+            false => (now, spec.lease_transitions.unwrap_or_default() + 1),
+        };
+        lease.spec = Some(self.lease_spec(acquire_time, now, transitions));
+        // the resource version of the lease makes the replacement fail if another replica updated it first
+        let replaced = ignore_conflict(
+            leases
+                .replace(&self.lease_name, &PostParams::default(), &lease)
+                .await,
+        )?;
+        Ok(replaced.then(|| now))
+    }
+
+    fn lease_spec(
+        &self,
+        acquire_time: DateTime<Utc>,
+        renew_time: DateTime<Utc>,
+        transitions: i32,
+    ) -> LeaseSpec {
+        LeaseSpec {
+            holder_identity: Some(self.identity.clone()),
+            lease_duration_seconds: Some(self.lease_duration.as_secs() as i32),
+            acquire_time: Some(MicroTime(acquire_time)),
+            renew_time: Some(MicroTime(renew_time)),
+            lease_transitions: Some(transitions),
+        }
+    }
+}
+
+/// Whether the lease isn't held anymore, because its holder didn't renew it in time.
+fn is_expired(spec: &LeaseSpec, now: DateTime<Utc>) -> bool {
+    match (
+        &spec.holder_identity,
+        &spec.renew_time,
+        spec.lease_duration_seconds,
+    ) {
+        (Some(_), Some(renew_time), Some(duration)) => {
+            renew_time.0 + chrono::Duration::seconds(duration as i64) < now
+        }
+        _ => true,
+    }
+}
+
+/// A conflict means another replica updated the lease first, so it holds the lease.
+fn ignore_conflict(result: Result<Lease, kube::Error>) -> Result<bool, kube::Error> {
+    match result {
+        Ok(_) => Ok(true),
+        Err(kube::Error::Api(err)) if err.code == 409 => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, TimeZone, Utc};
+    use k8s_openapi::{api::coordination::v1::LeaseSpec, apimachinery::pkg::apis::meta::v1::MicroTime};
+
+    use super::{is_expired, LeaderElection};
+
+    #[test]
+    fn test_is_expired() {
+        let renew_time = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let spec = LeaseSpec {
+            holder_identity: Some("controller-0".to_string()),
+            lease_duration_seconds: Some(15),
+            renew_time: Some(MicroTime(renew_time)),
+            ..Default::default()
+        };
+
+        assert!(!is_expired(&spec, renew_time + Duration::seconds(10)));
+        assert!(is_expired(&spec, renew_time + Duration::seconds(16)));
+        // a released lease is free
+        let released = LeaseSpec {
+            holder_identity: None,
+            ..spec
+        };
+        assert!(is_expired(&released, renew_time));
+    }
+
+    #[test]
+    fn test_renew_deadline() {
+        // the leader stops reconciling before a follower can take over
+        let election = LeaderElection::new("default", "controller-0", std::time::Duration::from_secs(15));
+        assert_eq!(election.renew_deadline, std::time::Duration::from_secs(10));
+    }
+}
@@ -1,2 +1,18 @@
+pub mod backoff;
+pub mod cache;
+pub mod config;
+pub mod cron_job;
+pub mod dependency;
 pub mod error;
+pub mod expectations;
+pub mod failure_policy;
+pub mod indexed;
+pub mod leader;
 pub mod manager;
+pub mod metrics;
+pub mod preempt;
+pub mod queue;
+pub mod scheduler;
+pub mod snapshot;
+pub mod status;
+pub mod sweep;
@@ -1,56 +1,123 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
-use crate::error::{Error, Result};
+use crate::{
+    backoff::Backoff,
+    cache::PodCache,
+    config::Config,
+    dependency::{dependents, wait_for_dependencies},
+    error::{Error, ManagerError, Result},
+    expectations::Expectations,
+    failure_policy::{apply_pod_failure_policies, fail_job},
+    indexed::{
+        admitted_indexed_pods, build_indexed_pods, completed_indexes, count_index_retry,
+        format_completed_indexes, indexed_phase, is_failed_index,
+    },
+    leader::LeaderElection,
+    metrics::Metrics,
+    preempt::preempt,
+    snapshot::{is_forbidden, SnapshotStores},
+    status::apply_job_status,
+    sweep::{build_sweep_pods, index_statuses, sweep_phase},
+};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use futures::{future::BoxFuture, FutureExt, StreamExt};
+use futures::{future::BoxFuture, FutureExt, Stream, StreamExt};
 use habitat_api::{
-    batch::{JobStatus, JobStatusPhase},
-    Job,
+    batch::{
+        replica_index, CompletionMode, JobCondition, JobStatus, JobStatusPhase, LabelNames, TaskSpec,
+        ADMITTED_CONDITION,
+    },
+    resource::job_min_requests,
+    CronJob, Job, Queue,
+};
+use habitat_scheduler::fairshare::Cluster;
+use k8s_openapi::{
+    api::core::v1::{Pod, PodSpec},
+    apimachinery::pkg::apis::meta::v1::Time,
 };
-use k8s_openapi::api::core::v1::{Pod, PodSpec};
 use kube::{
-    api::{Api, DeleteParams, ListParams, Patch, PatchParams, PostParams},
+    api::{Api, ListParams},
     client::Client,
     core::ObjectMeta,
     runtime::{
-        controller::{Action, Controller},
+        applier,
+        controller::{trigger_self, Action, Controller, ReconcileRequest},
         events::{Event, EventType, Recorder, Reporter},
         finalizer::{finalizer, Event as Finalizer},
+        reflector::{self, reflector, store::Writer, ObjectRef, Store},
+        watcher::{self, watcher},
+        WatchStreamExt,
     },
     Resource, ResourceExt,
 };
+use prometheus::proto::MetricFamily;
 use serde::Serialize;
-use tokio::{sync::RwLock, time::Duration};
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        Mutex, RwLock,
+    },
+    time::Duration,
+};
 use tracing::{info, warn};
 
 const FINALIZER_NAME: &str = "controller.batch.habitat";
-const TASK_OWNER_LABEL: &str = "habitat-task-owner";
-const TASK_NAME_LABEL: &str = "habitat-task";
+
+/// The job and pod changes buffered for a job controller, beyond which all its jobs are reconciled
+const JOB_TRIGGERS_CAPACITY: usize = 1024;
+
+/// The job changes buffered for the queue controller, beyond which all the queues are reconciled
+const QUEUE_TRIGGERS_CAPACITY: usize = 1024;
+
+/// The condition of a job whose reconciliation failed permanently, until the job changes
+pub const RECONCILE_FAILED_CONDITION: &str = "ReconcileFailed";
 
 // Context for our reconciler
 #[derive(Clone)]
 pub struct Context {
     /// Kubernetes client
-    client: Client,
+    pub(crate) client: Client,
     /// Diagnostics read by the web server
-    diagnostics: Arc<RwLock<Diagnostics>>,
+    pub(crate) diagnostics: Arc<RwLock<Diagnostics>>,
+    /// Serializes the admission of jobs, so each decision sees the jobs admitted before
+    pub(crate) admission: Arc<Mutex<()>>,
+    /// Prometheus metrics
+    pub(crate) metrics: Metrics,
+    /// The configuration of the controller
+    pub(crate) config: Config,
+    /// The backoff of the objects whose reconciliation failed
+    pub(crate) backoff: Backoff,
+    /// The jobs of the watched namespaces, read by the reconcilers instead of listing them
+    pub(crate) jobs: Vec<Store<Job>>,
+    /// The pods of the jobs, read by the reconcilers instead of listing them
+    pub(crate) pods: PodCache,
+    /// The pod creations and deletions not observed in the pod cache yet
+    pub(crate) expectations: Expectations,
+    /// The other objects the admission of the jobs is decided from
+    pub(crate) snapshot: SnapshotStores,
 }
 
 /// Diagnostics to be exposed by the web server
 #[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Diagnostics {
-    #[serde(deserialize_with = "from_ts")]
+    /// The time of the last reconciliation
     pub last_event: DateTime<Utc>,
     #[serde(skip)]
     pub reporter: Reporter,
 }
 
 impl Diagnostics {
-    fn new() -> Self {
+    fn new(reporter: &str) -> Self {
         Self {
             last_event: Utc::now(),
-            reporter: "habitat-controller".into(),
+            reporter: reporter.into(),
         }
     }
 }
@@ -59,59 +126,455 @@ impl Diagnostics {
 pub struct Manager {
     /// Diagnostics populated by the reconciler
     diagnostics: Arc<RwLock<Diagnostics>>,
+    /// Metrics populated by the reconcilers
+    metrics: Metrics,
+    /// The jobs watched by the manager, one store per watched namespace
+    jobs: Vec<Store<Job>>,
+    /// The number of job stores synced, once the CRDs are installed
+    synced: Arc<AtomicUsize>,
+    /// The pods of the jobs
+    pods: PodCache,
+    /// The other objects the admission of the jobs is decided from
+    snapshot: SnapshotStores,
 }
 
 impl Manager {
-    pub async fn new(client: Client) -> (Self, BoxFuture<'static, ()>) {
-        let diagnostics = Arc::new(RwLock::new(Diagnostics::new()));
+    /// Creates the manager, and the future running its controllers.
+    ///
+    /// The future checks that the CRDs are installed before starting the controllers, so that the
+    /// manager can be served while it is not ready yet. With leader election, the controllers only run
+    /// while this replica holds the lease. The future fails if the CRDs aren't installed, or once the lease
+    /// is lost.
+    pub async fn new(
+        client: Client,
+        config: Config,
+        leader_election: Option<LeaderElection>,
+    ) -> (Self, BoxFuture<'static, Result<(), ManagerError>>) {
+        let diagnostics = Arc::new(RwLock::new(Diagnostics::new(&config.reporter)));
+        let metrics = Metrics::default();
+        let (jobs, jobs_writers): (Vec<_>, Vec<_>) = config
+            .namespaced_apis::<Job>(&client)
+            .iter()
+            .map(|_| reflector::store())
+            .unzip();
+        let synced = Arc::new(AtomicUsize::new(0));
+        let pods = PodCache::default();
+        let (snapshot, snapshot_reflectors) = SnapshotStores::new(client.clone(), &config);
         let context = Arc::new(Context {
             client: client.clone(),
             diagnostics: diagnostics.clone(),
+            admission: Arc::new(Mutex::new(())),
+            metrics: metrics.clone(),
+            backoff: config.error_backoff(),
+            jobs: jobs.clone(),
+            pods: pods.clone(),
+            expectations: Expectations::default(),
+            snapshot: snapshot.clone(),
+            config,
         });
 
-        let pods = Api::<Pod>::all(client.clone());
-        let jobs = Api::<Job>::all(client);
+        let controller = run_controllers(
+            client,
+            context,
+            jobs_writers,
+            snapshot_reflectors,
+            synced.clone(),
+            leader_election,
+        )
+        .boxed();
+        let manager = Self {
+            diagnostics,
+            metrics,
+            jobs,
+            synced,
+            pods,
+            snapshot,
+        };
+        (manager, controller)
+    }
 
-        // Ensure CRD is installed before loop-watching
-        let _ = jobs
-            .list(&ListParams::default().limit(1))
-            .await
-            .expect("is habitat installed?");
+    /// Metrics getter
+    pub fn metrics(&self) -> Vec<MetricFamily> {
+        let jobs = self
+            .jobs
+            .iter()
+            .flat_map(|store| store.state())
+            .collect::<Vec<_>>();
+        self.metrics.gather(&jobs)
+    }
+
+    /// State getter
+    pub async fn diagnostics(&self) -> Diagnostics {
+        self.diagnostics.read().await.clone()
+    }
+
+    /// Whether the CRDs are installed, and the jobs and pods of every watched namespace, and the other
+    /// objects the admission of the jobs is decided from, have been listed once
+    pub fn ready(&self) -> bool {
+        self.synced.load(Ordering::Relaxed) == self.jobs.len()
+            && self.pods.listed() == self.jobs.len()
+            && self.snapshot.synced()
+    }
+}
+
+async fn run_controllers(
+    client: Client,
+    context: Arc<Context>,
+    jobs_writers: Vec<Writer<Job>>,
+    snapshot_reflectors: BoxFuture<'static, ()>,
+    synced: Arc<AtomicUsize>,
+    leader_election: Option<LeaderElection>,
+) -> Result<(), ManagerError> {
+    let config = &context.config;
+    let queues = Api::<Queue>::all(client.clone());
+    let namespaced_apis = config
+        .namespaced_apis::<Job>(&client)
+        .into_iter()
+        .zip(config.namespaced_apis::<Pod>(&client))
+        .zip(config.namespaced_apis::<CronJob>(&client))
+        .map(|((jobs, pods), cron_jobs)| (jobs, pods, cron_jobs))
+        .collect::<Vec<_>>();
+
+    // Ensure CRDs are installed before loop-watching
+    let lp = ListParams::default().limit(1);
+    // a namespace-scoped controller may not be allowed to list the cluster-scoped queues, whose jobs are
+    // admitted without them then
+    let queues_listed = match queues.list(&lp).await {
+        Err(err) if is_forbidden(&err) && !config.namespaces.is_empty() => {
+            warn!(
+                "not allowed to list the queues, their statuses aren't updated: {}",
+                err
+            );
+            false
+        }
+        result => result.map(|_| true).map_err(ManagerError::NotInstalled)?,
+    };
+    for (jobs, _, cron_jobs) in &namespaced_apis {
+        jobs.list(&lp).await.map_err(ManagerError::NotInstalled)?;
+        cron_jobs.list(&lp).await.map_err(ManagerError::NotInstalled)?;
+    }
+
+    // All good. Start controllers.
+    // the jobs and pods trigger the controllers from the stores, once they are updated, so the
+    // reconciliations never read older objects than the ones which triggered them. The followers keep their
+    // stores synced as well, and drop the triggers.
+    let (queue_triggers, queue_receiver) = broadcast::channel(QUEUE_TRIGGERS_CAPACITY);
+    let (job_triggers, job_receivers): (Vec<_>, Vec<_>) = namespaced_apis
+        .iter()
+        .map(|_| broadcast::channel(JOB_TRIGGERS_CAPACITY))
+        .unzip();
+    let mut reflectors = jobs_writers
+        .into_iter()
+        .zip(&namespaced_apis)
+        .zip(context.jobs.clone())
+        .zip(job_triggers.clone())
+        .map(|(((writer, (jobs, _, _)), store), job_triggers)| {
+            let synced = synced.clone();
+            let queue_triggers = queue_triggers.clone();
+            let mut listed = false;
+            let mut job_queues = HashMap::new();
+            reflector(writer, watcher(jobs.clone(), ListParams::default()))
+                .for_each(move |event| {
+                    if let Ok(event) = &event {
+                        for job in job_changes(&store, event) {
+                            let _ = job_triggers.send(job);
+                        }
+                        for queue in queue_changes(&mut job_queues, event) {
+                            let _ = queue_triggers.send(ObjectRef::new(&queue));
+                        }
+                    }
+                    if let (false, Ok(watcher::Event::Restarted(_))) = (listed, event) {
+                        listed = true;
+                        synced.fetch_add(1, Ordering::Relaxed);
+                    }
+                    futures::future::ready(())
+                })
+                .boxed()
+        })
+        .collect::<Vec<_>>();
+    let namespaces = match config.namespaces.is_empty() {
+        true => vec![None],
+        false => config.namespaces.iter().cloned().map(Some).collect(),
+    };
+    reflectors.push(snapshot_reflectors);
+    for (((_, pods, _), namespace), triggers) in namespaced_apis.iter().zip(namespaces).zip(job_triggers) {
+        let lp = ListParams::default().labels(&config.labels.task_owner);
+        let pods_reflector =
+            context
+                .pods
+                .feed(watcher(pods.clone(), lp), namespace)
+                .for_each(move |owners| {
+                    for owner in owners.into_iter().flatten() {
+                        let _ = triggers.send(owner);
+                    }
+                    futures::future::ready(())
+                });
+        reflectors.push(pods_reflector.boxed());
+    }
 
-        // All good. Start controller and return its future.
-        let controller = Controller::new(jobs, ListParams::default())
+    let mut controllers = vec![];
+    let job_controllers = namespaced_apis
+        .into_iter()
+        .zip(context.jobs.clone())
+        .zip(job_receivers);
+    for (((jobs, _, cron_jobs), store), job_triggers) in job_controllers {
+        let job_controller = run_job_controller(store, job_triggers, context.clone());
+        let cron_job_controller = Controller::new(cron_jobs, ListParams::default())
             .shutdown_on_signal()
-            .owns(pods, ListParams::default())
-            .run(reconciler, error_policy, context)
+            .owns(jobs.clone(), ListParams::default())
+            .run(
+                crate::cron_job::reconciler,
+                crate::cron_job::error_policy,
+                context.clone(),
+            )
             .filter_map(|x| async move { std::result::Result::ok(x) })
-            .for_each(|_| futures::future::ready(()))
-            .boxed();
+            .for_each(|_| futures::future::ready(()));
+        controllers.push(job_controller.boxed());
+        controllers.push(cron_job_controller.boxed());
+    }
+    if queues_listed {
+        controllers.push(run_queue_controller(queues, queue_receiver, context.clone()).boxed());
+    }
+
+    // the followers keep their stores synced, so they are ready to take over
+    let controllers = futures::future::join_all(controllers).map(|_| ());
+    let controllers = match leader_election {
+        Some(election) => async move { Ok(election.run(client, controllers).await?) }.boxed(),
+        None => controllers.map(Ok).boxed(),
+    };
+    // the reflectors never end, so stop with the controllers on shutdown
+    let reflectors = futures::future::join_all(reflectors).map(|_| Ok(())).boxed();
+    futures::future::select(controllers, reflectors)
+        .await
+        .factor_first()
+        .0
+}
+
+/// Runs the controller of the jobs of a namespace.
+///
+/// The jobs are reconciled from the job store shared with the admissions, so an admission always sees
+/// the jobs admitted before. With `Controller::owns`, the pods would be watched apart from the pod cache,
+/// and a reconciliation could read an older pod than the one which triggered it. The jobs and their pods
+/// trigger their reconciliations from the job store and the pod cache instead, once they're updated.
+async fn run_job_controller(
+    store: Store<Job>,
+    job_triggers: broadcast::Receiver<ObjectRef<Job>>,
+    context: Arc<Context>,
+) {
+    let queue = store_triggers(job_triggers, store.clone())
+        .backoff(watcher::default_backoff())
+        .take_until(shutdown_signal());
+    applier(
+        |job, ctx| Box::pin(reconciler(job, ctx)),
+        error_policy,
+        context,
+        store,
+        queue,
+    )
+    .for_each(|_| futures::future::ready(()))
+    .await
+}
+
+/// Runs the controller of the queues.
+///
+/// The queues count their jobs in the job stores, so the jobs trigger the reconciliation of their queues
+/// once the stores are updated.
+async fn run_queue_controller(
+    queues: Api<Queue>,
+    job_triggers: broadcast::Receiver<ObjectRef<Queue>>,
+    context: Arc<Context>,
+) {
+    let (store, writer) = reflector::store();
+    let queue_triggers = trigger_self(
+        reflector(writer, watcher(queues, ListParams::default())).applied_objects(),
+        (),
+    );
+    let job_triggers = store_triggers(job_triggers, store.clone());
 
-        (Self { diagnostics }, controller)
+    let queue = futures::stream::select(queue_triggers.boxed(), job_triggers.boxed())
+        .backoff(watcher::default_backoff())
+        .take_until(shutdown_signal());
+    applier(
+        |queue, ctx| Box::pin(crate::queue::reconciler(queue, ctx)),
+        crate::queue::error_policy,
+        context,
+        store,
+        queue,
+    )
+    .for_each(|_| futures::future::ready(()))
+    .await
+}
+
+/// The jobs to reconcile after a job event, and the jobs waiting for them, which are reconciled once they
+/// complete. `store` is the job store the event was applied to.
+fn job_changes(store: &Store<Job>, event: &watcher::Event<Job>) -> Vec<ObjectRef<Job>> {
+    match event {
+        watcher::Event::Applied(job) => {
+            let mut changed = vec![ObjectRef::from_obj(job)];
+            changed.extend(dependents(store, job));
+            changed
+        }
+        watcher::Event::Deleted(job) => dependents(store, job),
+        watcher::Event::Restarted(jobs) => jobs.iter().map(ObjectRef::from_obj).collect(),
     }
+}
 
-    /// State getter
-    pub async fn diagnostics(&self) -> Diagnostics {
-        self.diagnostics.read().await.clone()
+/// The queues to recount after a job event, including the queues the jobs were moved out of. `job_queues`
+/// tracks the queue of every job seen by the events.
+fn queue_changes(
+    job_queues: &mut HashMap<ObjectRef<Job>, String>,
+    event: &watcher::Event<Job>,
+) -> BTreeSet<String> {
+    let mut changed = BTreeSet::new();
+    let jobs = match event {
+        watcher::Event::Applied(job) => std::slice::from_ref(job),
+        watcher::Event::Deleted(job) => {
+            changed.extend(job_queues.remove(&ObjectRef::from_obj(job)));
+            changed.extend(job.spec.queue.clone());
+            return changed;
+        }
+        watcher::Event::Restarted(jobs) => {
+            changed.extend(job_queues.drain().map(|(_, queue)| queue));
+            jobs
+        }
+    };
+    for job in jobs {
+        let job_ref = ObjectRef::from_obj(job);
+        changed.extend(job_queues.remove(&job_ref));
+        if let Some(queue) = &job.spec.queue {
+            changed.insert(queue.clone());
+            job_queues.insert(job_ref, queue.clone());
+        }
     }
+    changed
+}
+
+/// The reconciliations of the objects which changed in a cache, e.g. the jobs whose pods changed in the pod
+/// cache. All the objects are reconciled when some triggers were dropped, as the controller lagged behind
+/// the cache.
+fn store_triggers<K>(
+    receiver: broadcast::Receiver<ObjectRef<K>>,
+    store: Store<K>,
+) -> impl Stream<Item = Result<ReconcileRequest<K>, watcher::Error>>
+where
+    K: Resource<DynamicType = ()> + Clone + 'static,
+{
+    futures::stream::unfold(receiver, move |mut receiver| {
+        let store = store.clone();
+        async move {
+            let objs = match receiver.recv().await {
+                Ok(obj) => vec![obj],
+                Err(RecvError::Lagged(_)) => store
+                    .state()
+                    .iter()
+                    .map(|obj| ObjectRef::from_obj(obj.as_ref()))
+                    .collect(),
+                Err(RecvError::Closed) => return None,
+            };
+            Some((futures::stream::iter(objs), receiver))
+        }
+    })
+    .flatten()
+    .map(|obj| Ok(ReconcileRequest::from(obj)))
+}
+
+/// Completes on Ctrl+C or SIGTERM, like `Controller::shutdown_on_signal`.
+async fn shutdown_signal() {
+    futures::future::select(
+        tokio::signal::ctrl_c().map(|_| ()).boxed(),
+        #[cfg(unix)]
+        async {
+            match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+                Ok(mut terminate) => {
+                    terminate.recv().await;
+                }
+                Err(_) => futures::future::pending().await,
+            }
+        }
+        .boxed(),
+        #[cfg(not(unix))]
+        futures::future::pending::<()>(),
+    )
+    .await;
 }
 
 async fn reconciler(job: Arc<Job>, ctx: Arc<Context>) -> Result<Action> {
+    let _timer = ctx.metrics.count_and_measure("Job");
+    ctx.diagnostics.write().await.last_event = Utc::now();
     let client = ctx.client.clone();
     let ns = job.namespace().unwrap();
     let jobs: Api<Job> = Api::namespaced(client, &ns);
 
-    finalizer(&jobs, FINALIZER_NAME, job, |event| async {
+    let result = finalizer(&jobs, FINALIZER_NAME, job.clone(), |event| async {
         match event {
             Finalizer::Apply(job) => job.reconcile(ctx.clone()).await,
             Finalizer::Cleanup(job) => job.cleanup(ctx.clone()).await,
         }
     })
     .await
-    .map_err(Error::FinalizerError)
+    .map_err(Error::FinalizerError);
+
+    if result.is_ok() {
+        ctx.backoff.reset(&ObjectRef::from_obj(job.as_ref()).to_string());
+    }
+    // retrying can't fix a permanent error, so it's surfaced until the job changes
+    let permanent_error = result.as_ref().err().filter(|err| err.is_permanent());
+    if let Err(err) = update_reconcile_failed_condition(&job, permanent_error, &jobs).await {
+        warn!(
+            "failed to update condition {}: {:?}",
+            RECONCILE_FAILED_CONDITION, err
+        );
+    }
+    result
+}
+
+/// Sets the permanent error of the last reconciliation as a condition of the job, or clears it.
+async fn update_reconcile_failed_condition(
+    job: &Job,
+    error: Option<&Error>,
+    jobs: &Api<Job>,
+) -> Result<(), kube::Error> {
+    let failed = job
+        .status
+        .as_ref()
+        .and_then(|s| s.condition(RECONCILE_FAILED_CONDITION))
+        .map(|c| c.status == "True")
+        .unwrap_or_default();
+    if error.is_none() && !failed {
+        return Ok(());
+    }
+
+    // the reconciliation may have updated the status of the job since
+    let job = match jobs.get_opt(&job.name_any()).await? {
+        Some(job) => job,
+        None => return Ok(()),
+    };
+    let condition = match error {
+        Some(error) => {
+            let reason = match error.kube_error() {
+                Some(kube::Error::Api(err)) if !err.reason.is_empty() => err.reason.as_str(),
+                _ => "ReconcileError",
+            };
+            new_condition(RECONCILE_FAILED_CONDITION, true, reason, Some(error.to_string()))
+        }
+        None => new_condition(RECONCILE_FAILED_CONDITION, false, "Reconciled", None),
+    };
+
+    let mut status = job.status.clone().unwrap_or_default();
+    let current = status.condition(RECONCILE_FAILED_CONDITION).cloned();
+    status.set_condition(condition);
+    let changed = current.as_ref().map(|c| (&c.status, &c.reason, &c.message))
+        != status
+            .condition(RECONCILE_FAILED_CONDITION)
+            .map(|c| (&c.status, &c.reason, &c.message));
+    if changed {
+        apply_job_status(jobs, &job, status).await?;
+    }
+    Ok(())
 }
 
 fn error_policy(job: Arc<Job>, error: &Error, ctx: Arc<Context>) -> Action {
+    ctx.metrics.reconcile_failure("Job", error);
     job.error_policy(error, ctx)
 }
 
@@ -155,33 +618,203 @@ impl Reconciler for Job {
                 })
                 .await?;
 
-            let new_status = Patch::Merge(serde_json::json!({ "status": JobStatus::default() }));
-            let _ = jobs
-                .patch_status(&name, &PatchParams::default(), &new_status)
-                .await?;
+            // the update of the status reconciles the job again
+            apply_job_status(&jobs, self, JobStatus::default()).await?;
+            return Ok(Action::await_change());
+        }
+
+        let uid = self.uid().unwrap_or_default();
+        let owned_pods = ctx.pods.owned_pods(&uid);
+        if !ctx.expectations.satisfied(&uid, &owned_pods) {
+            // the pod cache may receive the events of the pods after the controller, so check again soon
+            return Ok(Action::requeue(Duration::from_secs(1)));
         }
 
-        let owned_pods = pods
-            .list(&ListParams::default().labels(&new_owned_label(self)))
-            .await?
-            .into_iter()
-            .map(|pod| (pod.name_any(), pod))
-            .collect::<HashMap<_, _>>();
-
-        for pod in build_min_owned_pods(self) {
-            if !owned_pods.contains_key(&pod.name_any()) {
-                // create pod
-                pods.create(&PostParams::default(), &pod).await?;
+        // Pending jobs wait for their queue to admit them before creating any pod
+        let phase = self.status.as_ref().map(|s| s.phase.clone()).unwrap_or_default();
+        if phase == JobStatusPhase::Pending {
+            // the pods of a preempted job are all deleted before it's put back to `Pending`, so the live pods
+            // were created before the job had to be admitted, e.g. by a previous version of the controller
+            if owned_pods
+                .values()
+                .any(|pod| pod.metadata.deletion_timestamp.is_none())
+            {
+                let mut status = self.status.clone().unwrap_or_default();
+                status.phase = JobStatusPhase::Ready;
+                status.set_condition(new_condition(
+                    ADMITTED_CONDITION,
+                    true,
+                    "AlreadyRunning",
+                    Some("the pods of the job were created before its admission".to_string()),
+                ));
+                let admitted = apply_job_status(&jobs, self, status).await?;
+                ctx.snapshot.record_write(self, admitted);
+                return Ok(Action::await_change());
+            }
+            if !owned_pods.is_empty() {
+                // the job was preempted, wait for its pods to terminate before admitting it again
+                return Ok(Action::requeue(Duration::from_secs(5)));
+            }
+
+            // the jobs it depends on are watched, and reconcile the job once they complete
+            if wait_for_dependencies(self, &jobs).await? {
+                return Ok(Action::await_change());
+            }
+
+            // the admission waits for the stores of the snapshot to be listed, not to deny from empty ones
+            if !ctx.snapshot.synced() {
+                return Ok(Action::requeue(Duration::from_secs(5)));
+            }
+            let _admission = ctx.admission.lock().await;
+            let snapshot = ctx.snapshot.snapshot(&ctx.jobs, &ctx.pods);
+            let cluster = Cluster::new(&snapshot, &ctx.config.labels);
+            let mut status = self.status.clone().unwrap_or_default();
+
+            let queue = self.spec.queue.as_deref();
+            let request = job_min_requests(&self.spec);
+            let admission = cluster.admit(queue, &request);
+            if admission.is_err() {
+                // try to make room by preempting lower priority jobs
+                let priority = cluster.priority(&self.spec.priority);
+                if let Some(plan) = cluster.plan_preemption(queue, &request, priority) {
+                    info!("job {}/{} preempts {:?}", ns, name, plan);
+                    preempt(self, plan, ctx.clone()).await?;
+                    return Ok(Action::requeue(Duration::from_secs(5)));
+                }
+            }
+
+            // smaller jobs are backfilled only if they don't delay a larger job waiting for resources
+            match admission.and_then(|()| cluster.backfill(&ns, &name, Utc::now())) {
+                Ok(()) => {
+                    status.phase = JobStatusPhase::Ready;
+                    status.set_condition(new_condition(ADMITTED_CONDITION, true, "Admitted", None));
+                    let admitted = apply_job_status(&jobs, self, status).await?;
+                    // the next admissions account the job before the job stores observe its status
+                    ctx.snapshot.record_write(self, admitted);
+                    recorder
+                        .publish(Event {
+                            type_: EventType::Normal,
+                            reason: "Admitted".into(),
+                            note: Some(format!("Job `{}` is admitted", name)),
+                            action: "Admitting".into(),
+                            secondary: None,
+                        })
+                        .await?;
+                    // the update of the status reconciles the job again, which creates its pods
+                    return Ok(Action::await_change());
+                }
+                Err(reason) => {
+                    info!("job {}/{} is waiting: {}", ns, name, reason);
+                    status.set_condition(new_condition(ADMITTED_CONDITION, false, "Waiting", Some(reason)));
+                    if self.status.as_ref().and_then(|s| s.condition(ADMITTED_CONDITION))
+                        != status.condition(ADMITTED_CONDITION)
+                    {
+                        apply_job_status(&jobs, self, status).await?;
+                    }
+
+                    // the resources are released by other jobs, check again later
+                    return Ok(Action::requeue(Duration::from_secs(30)));
+                }
+            }
+        }
+
+        // the failed pods are handled by the pod failure policies of their tasks first
+        let failure_policies =
+            apply_pod_failure_policies(self, owned_pods.values(), &jobs, &pods, &recorder, &ctx);
+        let deleted = match failure_policies.await? {
+            Some(deleted) => deleted,
+            None => return Ok(Action::await_change()),
+        };
+
+        // sweep jobs start their indexes in order, as the previous ones finish
+        let labels = &ctx.config.labels;
+        let sweep = self.spec.sweep.as_ref();
+        let sweep_statuses = sweep
+            .map(|sweep| index_statuses(self, sweep, owned_pods.values(), labels))
+            .unwrap_or_default();
+        // indexed jobs run every index which hasn't succeeded yet
+        let indexed = self.spec.completion_mode == CompletionMode::Indexed;
+        let completed = match indexed {
+            true => completed_indexes(self, owned_pods.values(), labels),
+            false => Default::default(),
+        };
+        let mut new_pods = match sweep {
+            Some(sweep) => build_sweep_pods(self, sweep, &sweep_statuses, labels),
+            None if indexed => build_indexed_pods(self, &completed, labels),
+            None => build_min_owned_pods(self, labels),
+        };
+        // the pods created before their names were prefixed with the name of their job are still replicas
+        let replicas = owned_pods
+            .values()
+            .filter_map(|pod| replica(pod, labels))
+            .collect::<HashSet<_>>();
+        new_pods.retain(|pod| {
+            !owned_pods.contains_key(&pod.name_any())
+                && !replica(pod, labels)
+                    .map(|r| replicas.contains(&r))
+                    .unwrap_or_default()
+        });
+        // the indexes above `parallelism.min` aren't covered by the admission of the job, so they are
+        // admitted by the queue of the job as well
+        let admission = match indexed && !new_pods.is_empty() && ctx.snapshot.synced() {
+            true => Some(ctx.admission.lock().await),
+            false => None,
+        };
+        let mut held_back = false;
+        if indexed {
+            let cluster = match admission.is_some() {
+                true => Cluster::new(&ctx.snapshot.snapshot(&ctx.jobs, &ctx.pods), labels),
+                // nothing is admitted above the minimum until the stores of the snapshot are listed
+                false => Cluster::default(),
+            };
+            let count = new_pods.len();
+            new_pods = admitted_indexed_pods(self, new_pods, &cluster, labels);
+            held_back = new_pods.len() < count;
+        }
+        for pod in new_pods {
+            // create pod
+            if ctx.expectations.create_pod(&pods, &uid, &pod).await? {
+                ctx.metrics.pods_created.inc();
                 info!("created pod {}/{}", ns, pod.name_any());
+                if admission.is_some() {
+                    // the next admissions account the pod before the pod cache observes it
+                    ctx.snapshot.record_pod(&ns, &pod);
+                }
             }
         }
+        drop(admission);
 
+        let mut index_retries = self
+            .status
+            .as_ref()
+            .map(|s| s.index_retries.clone())
+            .unwrap_or_default();
         let mut pending = 0;
         let mut running = 0;
         let mut succeeded = 0;
         let mut failed = 0;
         let mut terminating = 0;
         for pod in owned_pods.values() {
+            if deleted.contains(&pod.name_any()) {
+                terminating += 1;
+                continue;
+            }
+
+            if indexed && is_failed_index(pod, &completed, labels) {
+                if let Err(reason) = count_index_retry(self, pod, &mut index_retries, labels) {
+                    let message = format!("Pod `{}` failed, {}", pod.name_any(), reason);
+                    fail_job(self, &message, owned_pods.values(), &jobs, &pods, &recorder, &ctx).await?;
+                    return Ok(Action::await_change());
+                }
+                // the index runs again once its failed pod is deleted
+                info!("index of pod <{}/{}> failed, so run it again", ns, pod.name_any());
+                if ctx.expectations.delete_pod(&pods, &uid, &pod.name_any()).await? {
+                    ctx.metrics.pods_deleted.inc();
+                }
+                terminating += 1;
+                continue;
+            }
+
             if pod.meta().deletion_timestamp.is_some() {
                 terminating += 1
             } else if let Some(pod_phase) = pod.status.as_ref().and_then(|status| status.phase.clone()) {
@@ -194,10 +827,9 @@ impl Reconciler for Job {
                 }
             }
 
-            if let Some(replicas_id) = pod.name_any().split('-').last() {
-                if let (Ok(replicas_id), Some(task_name)) =
-                    (replicas_id.parse::<u32>(), pod.labels().get(TASK_NAME_LABEL))
-                {
+            // the pods of sweep jobs are indexes of the parameter matrix, not replicas
+            if let (None, Some(replicas_id)) = (sweep, replica_index(pod, labels)) {
+                if let Some(task_name) = pod.labels().get(&labels.task_name) {
                     for task_spec in self.spec.tasks.iter() {
                         if task_spec.name == *task_name {
                             if replicas_id >= task_spec.parallelism.max {
@@ -209,7 +841,9 @@ impl Reconciler for Job {
                                     ns,
                                     pod.name_any()
                                 );
-                                pods.delete(&pod.name_any(), &DeleteParams::default()).await?;
+                                if ctx.expectations.delete_pod(&pods, &uid, &pod.name_any()).await? {
+                                    ctx.metrics.pods_deleted.inc();
+                                }
                                 terminating += 1;
                             }
                             break;
@@ -219,77 +853,220 @@ impl Reconciler for Job {
             }
         }
         let phase = match (pending, running, succeeded, failed, terminating) {
+            _ if sweep.is_some() => sweep.map(|sweep| sweep_phase(sweep, &sweep_statuses)),
+            (_, running, _, _, _) if indexed => Some(indexed_phase(self, &completed, running > 0)),
             (_, running, _, _, _) if running > 0 => Some(JobStatusPhase::Running),
             (0, 0, succeeded, 0, 0) if succeeded > 0 => Some(JobStatusPhase::Succeeded),
             (0, 0, _, failed, _) if failed > 0 => Some(JobStatusPhase::Failed),
-            (pending, _, _, _, _) if pending > 0 => Some(JobStatusPhase::Pending),
+            // the job is admitted, but its pods are not scheduled yet
+            (pending, _, _, _, _) if pending > 0 => Some(JobStatusPhase::Ready),
             _ => None,
         };
 
-        let patch = Patch::Merge({
-            if let Some(phase) = phase {
-                serde_json::json!({"status": {"pending": pending, "running": running, "succeeded": succeeded, "failed": failed, "terminating": terminating, "phase": phase}})
-            } else {
-                serde_json::json!({"status": {"pending": pending, "running": running, "succeeded": succeeded, "failed": failed, "terminating": terminating}})
-            }
-        });
-        jobs.patch_status(&name, &PatchParams::default(), &patch).await?;
+        let mut status = self.status.clone().unwrap_or_default();
+        status.pending = pending;
+        status.running = running;
+        status.succeeded = succeeded;
+        status.failed = failed;
+        status.terminating = terminating;
+        if let Some(phase) = phase {
+            status.phase = phase;
+        }
+        if sweep.is_some() {
+            status.sweep = sweep_statuses;
+        }
+        if indexed {
+            status.completed_indexes = format_completed_indexes(&completed);
+            status.index_retries = index_retries;
+        }
+        apply_job_status(&jobs, self, status).await?;
+        if held_back {
+            // the resources are released by other jobs, check again later
+            return Ok(Action::requeue(Duration::from_secs(30)));
+        }
         Ok(Action::await_change())
     }
 
-    async fn cleanup(&self, _ctx: Arc<Context>) -> Result<Action, kube::Error> {
+    async fn cleanup(&self, ctx: Arc<Context>) -> Result<Action, kube::Error> {
         info!("delete job");
+        ctx.expectations.forget(&self.uid().unwrap_or_default());
+        ctx.snapshot.forget(self);
+        // the failures of a deleted job would never be reset by a successful reconciliation
+        ctx.backoff.reset(&ObjectRef::from_obj(self).to_string());
 
         Ok(Action::await_change())
     }
 
-    fn error_policy(&self, error: &Error, _ctx: Arc<Context>) -> Action {
+    fn error_policy(&self, error: &Error, ctx: Arc<Context>) -> Action {
         warn!("reconcile failed: {:?}", error);
-        Action::requeue(Duration::from_secs(5 * 60))
+        if error.is_permanent() {
+            // the condition of the job tells why, wait for it to be fixed
+            return Action::await_change();
+        }
+        Action::requeue(ctx.backoff.next(&ObjectRef::from_obj(self).to_string()))
     }
 }
 
-fn new_owned_label(job: &Job) -> String {
-    format!("{}={}", TASK_OWNER_LABEL, job.name_any())
+pub(crate) fn new_condition(
+    type_: &str,
+    status: bool,
+    reason: &str,
+    message: Option<String>,
+) -> JobCondition {
+    JobCondition {
+        type_: type_.to_string(),
+        status: if status { "True" } else { "False" }.to_string(),
+        last_transition_time: Some(Time(Utc::now())),
+        reason: Some(reason.to_string()),
+        message,
+    }
 }
 
-fn build_min_owned_pods(job: &Job) -> Vec<Pod> {
+fn build_min_owned_pods(job: &Job, labels: &LabelNames) -> Vec<Pod> {
     let mut pods = vec![];
-    let oref = job.controller_owner_ref(&()).unwrap();
     for task in &job.spec.tasks {
-        let pod_spec: PodSpec =
-            serde_json::from_str(&serde_json::to_string(&task.template.spec).unwrap()).unwrap();
-
         for i in 0..task.parallelism.min {
-            let annotations = task
-                .template
-                .metadata
-                .as_ref()
-                .and_then(|d| d.annotations.clone());
-            let mut labels = task
-                .template
-                .metadata
-                .as_ref()
-                .and_then(|d| d.labels.clone())
-                .unwrap_or_default();
-            labels.insert(TASK_OWNER_LABEL.to_string(), job.name_any());
-            labels.insert(TASK_NAME_LABEL.to_string(), task.name.clone());
-            let name = format!("{}-{}", task.name, i);
-
-            let pod = Pod {
-                metadata: ObjectMeta {
-                    name: Some(name),
-                    owner_references: Some(vec![oref.clone()]),
-                    labels: Some(labels),
-                    annotations,
-                    ..Default::default()
-                },
-                spec: Some(pod_spec.clone()),
-                ..Default::default()
-            };
-            pods.push(pod);
+            pods.push(build_pod(job, task, i, labels));
         }
     }
 
     pods
 }
+
+/// The task and replica index of a pod, whatever its name.
+fn replica(pod: &Pod, labels: &LabelNames) -> Option<(String, u32)> {
+    let task = pod.labels().get(&labels.task_name)?;
+    Some((task.clone(), replica_index(pod, labels)?))
+}
+
+/// Builds the pod of the given replica index of a task.
+pub(crate) fn build_pod(job: &Job, task: &TaskSpec, index: u32, names: &LabelNames) -> Pod {
+    let oref = job.controller_owner_ref(&()).unwrap();
+    let mut pod_spec: PodSpec =
+        serde_json::from_str(&serde_json::to_string(&task.template.spec).unwrap()).unwrap();
+    pod_spec.scheduler_name = job.spec.scheduler_name.clone();
+
+    let annotations = task
+        .template
+        .metadata
+        .as_ref()
+        .and_then(|d| d.annotations.clone());
+    let mut labels = task
+        .template
+        .metadata
+        .as_ref()
+        .and_then(|d| d.labels.clone())
+        .unwrap_or_default();
+    labels.insert(names.task_owner.clone(), job.name_any());
+    labels.insert(names.task_name.clone(), task.name.clone());
+    labels.insert(names.replica_index.clone(), index.to_string());
+    // pod names are unique per job, as jobs in the same namespace may have tasks of the same name
+    let name = format!("{}-{}-{}", job.name_any(), task.name, index);
+
+    Pod {
+        metadata: ObjectMeta {
+            name: Some(name),
+            owner_references: Some(vec![oref]),
+            labels: Some(labels),
+            annotations,
+            ..Default::default()
+        },
+        spec: Some(pod_spec),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+    use super::{build_pod, queue_changes, replica};
+    use habitat_api::{batch::LabelNames, Job, JobTemplate};
+    use kube::{runtime::watcher::Event, ResourceExt};
+
+    fn job(name: &str, queue: Option<&str>) -> Job {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "batch.habitat/v1beta1",
+            "kind": "Job",
+            "metadata": {"name": name, "namespace": "default"},
+            "spec": {"queue": queue, "tasks": []}
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_pods_of_jobs_from_one_template() {
+        let template = serde_json::from_value::<JobTemplate>(serde_json::json!({
+            "apiVersion": "batch.habitat/v1beta1",
+            "kind": "JobTemplate",
+            "metadata": {"name": "train", "namespace": "default"},
+            "spec": {"job": {"tasks": [{
+                "name": "worker",
+                "parallelism": {},
+                "template": {"spec": {"containers": [{"name": "main", "image": "trainer"}]}}
+            }]}}
+        }))
+        .unwrap();
+        let spec = template.instantiate(&BTreeMap::new()).unwrap();
+
+        let labels = LabelNames::default();
+        let names = ["train-a", "train-b"]
+            .iter()
+            .map(|name| {
+                let mut job = serde_json::from_value::<Job>(serde_json::json!({
+                    "apiVersion": "batch.habitat/v1beta1",
+                    "kind": "Job",
+                    "metadata": {"name": name, "namespace": "default", "uid": name},
+                    "spec": {"templateRef": "train", "tasks": []}
+                }))
+                .unwrap();
+                job.spec.tasks = spec.tasks.clone();
+                build_pod(&job, &job.spec.tasks[0], 0, &labels).name_any()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["train-a-worker-0", "train-b-worker-0"]);
+    }
+
+    #[test]
+    fn test_replica() {
+        let job = serde_json::from_value::<Job>(serde_json::json!({
+            "apiVersion": "batch.habitat/v1beta1",
+            "kind": "Job",
+            "metadata": {"name": "train", "namespace": "default", "uid": "1234"},
+            "spec": {"tasks": [{
+                "name": "worker",
+                "parallelism": {},
+                "template": {"spec": {"containers": []}}
+            }]}
+        }))
+        .unwrap();
+        let legacy = serde_json::from_value(serde_json::json!({
+            "metadata": {"name": "worker-0", "labels": {"habitat-task-owner": "train", "habitat-task": "worker"}}
+        }))
+        .unwrap();
+
+        // the pods created before their names were prefixed with the job name are the same replicas
+        let labels = LabelNames::default();
+        let pod = build_pod(&job, &job.spec.tasks[0], 0, &labels);
+        assert_eq!(replica(&pod, &labels), Some(("worker".to_string(), 0)));
+        assert_eq!(replica(&legacy, &labels), replica(&pod, &labels));
+    }
+
+    #[test]
+    fn test_queue_changes() {
+        let mut job_queues = HashMap::new();
+        let changed = queue_changes(
+            &mut job_queues,
+            &Event::Restarted(vec![job("a", Some("q1")), job("b", None)]),
+        );
+        assert_eq!(changed, BTreeSet::from(["q1".to_string()]));
+
+        // the queue a job is moved out of is recounted as well
+        let changed = queue_changes(&mut job_queues, &Event::Applied(job("a", Some("q2"))));
+        assert_eq!(changed, BTreeSet::from(["q1".to_string(), "q2".to_string()]));
+
+        let changed = queue_changes(&mut job_queues, &Event::Deleted(job("a", Some("q2"))));
+        assert_eq!(changed, BTreeSet::from(["q2".to_string()]));
+        assert!(job_queues.is_empty());
+    }
+}
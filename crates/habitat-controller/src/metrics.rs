@@ -0,0 +1,167 @@
+use std::sync::Arc;
+
+use habitat_api::{batch::JobStatusPhase, Job};
+use prometheus::{
+    histogram_opts, opts, proto::MetricFamily, HistogramTimer, HistogramVec, IntCounter, IntCounterVec,
+    IntGaugeVec, Registry,
+};
+
+use crate::error::Error;
+
+const JOB_PHASES: [JobStatusPhase; 7] = [
+    JobStatusPhase::Pending,
+    JobStatusPhase::Ready,
+    JobStatusPhase::Running,
+    JobStatusPhase::Terminating,
+    JobStatusPhase::Succeeded,
+    JobStatusPhase::Failed,
+    JobStatusPhase::Terminated,
+];
+
+/// Metrics exposed by the web server
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    /// Reconciliations by kind
+    pub reconciliations: IntCounterVec,
+    /// Failed reconciliations by kind and error
+    pub failures: IntCounterVec,
+    /// Reconciliation durations by kind
+    pub reconcile_duration: HistogramVec,
+    /// Jobs by phase
+    pub jobs: IntGaugeVec,
+    /// Pending jobs by queue
+    pub queue_depth: IntGaugeVec,
+    /// Pods created for jobs
+    pub pods_created: IntCounter,
+    /// Pods of jobs deleted by the controller
+    pub pods_deleted: IntCounter,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let registry = Registry::new_custom(Some("habitat_controller".to_string()), None).unwrap();
+        let reconciliations =
+            IntCounterVec::new(opts!("reconciliations_total", "reconciliations"), &["kind"]).unwrap();
+        let failures = IntCounterVec::new(opts!("reconciliation_errors_total", "reconciliation errors"), &[
+            "kind", "error",
+        ])
+        .unwrap();
+        let reconcile_duration = HistogramVec::new(
+            histogram_opts!(
+                "reconcile_duration_seconds",
+                "The duration of reconcile to complete in seconds",
+                vec![0.01, 0.1, 0.25, 0.5, 1., 5., 15., 60.]
+            ),
+            &["kind"],
+        )
+        .unwrap();
+        let jobs = IntGaugeVec::new(opts!("jobs", "jobs by phase"), &["phase"]).unwrap();
+        let queue_depth =
+            IntGaugeVec::new(opts!("queue_pending_jobs", "pending jobs by queue"), &["queue"]).unwrap();
+        let pods_created = IntCounter::new("pods_created_total", "pods created for jobs").unwrap();
+        let pods_deleted =
+            IntCounter::new("pods_deleted_total", "pods of jobs deleted by the controller").unwrap();
+
+        registry.register(Box::new(reconciliations.clone())).unwrap();
+        registry.register(Box::new(failures.clone())).unwrap();
+        registry.register(Box::new(reconcile_duration.clone())).unwrap();
+        registry.register(Box::new(jobs.clone())).unwrap();
+        registry.register(Box::new(queue_depth.clone())).unwrap();
+        registry.register(Box::new(pods_created.clone())).unwrap();
+        registry.register(Box::new(pods_deleted.clone())).unwrap();
+
+        Self {
+            registry,
+            reconciliations,
+            failures,
+            reconcile_duration,
+            jobs,
+            queue_depth,
+            pods_created,
+            pods_deleted,
+        }
+    }
+}
+
+impl Metrics {
+    /// Counts a reconciliation, and measures its duration until the returned timer is dropped.
+    pub fn count_and_measure(&self, kind: &str) -> HistogramTimer {
+        self.reconciliations.with_label_values(&[kind]).inc();
+        self.reconcile_duration.with_label_values(&[kind]).start_timer()
+    }
+
+    /// Counts a failed reconciliation by the kind of its error.
+    pub fn reconcile_failure(&self, kind: &str, error: &Error) {
+        self.failures
+            .with_label_values(&[kind, error.metric_label()])
+            .inc()
+    }
+
+    /// Refreshes the gauges from the known jobs, and gathers all the metrics.
+    pub fn gather(&self, jobs: &[Arc<Job>]) -> Vec<MetricFamily> {
+        self.queue_depth.reset();
+        for phase in JOB_PHASES {
+            let count = jobs
+                .iter()
+                .filter(|job| {
+                    job.status
+                        .as_ref()
+                        .map(|s| &s.phase)
+                        .unwrap_or(&JobStatusPhase::Pending)
+                        == &phase
+                })
+                .count();
+            self.jobs
+                .with_label_values(&[&format!("{:?}", phase)])
+                .set(count as i64);
+        }
+        for job in jobs {
+            let pending = job
+                .status
+                .as_ref()
+                .map(|s| s.phase == JobStatusPhase::Pending)
+                .unwrap_or(true);
+            if let (true, Some(queue)) = (pending, &job.spec.queue) {
+                self.queue_depth.with_label_values(&[queue]).inc();
+            }
+        }
+        self.registry.gather()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::Metrics;
+    use habitat_api::Job;
+
+    fn job(queue: &str, phase: &str) -> Arc<Job> {
+        Arc::new(
+            serde_json::from_value(serde_json::json!({
+                "apiVersion": "batch.habitat/v1beta1",
+                "kind": "Job",
+                "metadata": {"name": "job", "namespace": "default"},
+                "spec": {"queue": queue, "tasks": []},
+                "status": {"phase": phase, "pending": 0, "running": 0, "terminating": 0, "succeeded": 0, "failed": 0}
+            }))
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_gather() {
+        let metrics = Metrics::default();
+        let jobs = vec![job("a", "Pending"), job("a", "Pending"), job("b", "Running")];
+        let families = metrics.gather(&jobs);
+
+        assert_eq!(metrics.jobs.with_label_values(&["Pending"]).get(), 2);
+        assert_eq!(metrics.jobs.with_label_values(&["Running"]).get(), 1);
+        assert_eq!(metrics.jobs.with_label_values(&["Failed"]).get(), 0);
+        assert_eq!(metrics.queue_depth.with_label_values(&["a"]).get(), 2);
+        assert!(families
+            .iter()
+            .any(|f| f.get_name() == "habitat_controller_queue_pending_jobs"));
+    }
+}
@@ -0,0 +1,205 @@
+use std::sync::Arc;
+
+use crate::{
+    manager::{new_condition, Context},
+    status::apply_job_status,
+};
+use habitat_api::{
+    batch::{JobStatusPhase, ADMITTED_CONDITION},
+    Job,
+};
+use habitat_scheduler::preempt::Preemption;
+use k8s_openapi::api::core::v1::Pod;
+use kube::{
+    api::Api,
+    runtime::events::{Event, EventType, Recorder},
+    Resource, ResourceExt,
+};
+use tracing::info;
+
+pub(crate) const PREEMPTED_CONDITION: &str = "Preempted";
+
+/// Executes the planned preemptions on behalf of `preemptor`.
+pub(crate) async fn preempt(
+    preemptor: &Job,
+    plan: Vec<Preemption>,
+    ctx: Arc<Context>,
+) -> Result<(), kube::Error> {
+    let client = ctx.client.clone();
+    let reporter = ctx.diagnostics.read().await.reporter.clone();
+    let preemptor_name = format!(
+        "{}/{}",
+        preemptor.namespace().unwrap_or_default(),
+        preemptor.name_any()
+    );
+
+    for preemption in plan {
+        let (namespace, job_name) = match &preemption {
+            Preemption::ElasticPods { namespace, job, .. } | Preemption::Job { namespace, job } => {
+                (namespace.clone(), job.clone())
+            }
+        };
+        let jobs: Api<Job> = Api::namespaced(client.clone(), &namespace);
+        let pods: Api<Pod> = Api::namespaced(client.clone(), &namespace);
+        let victim = match jobs.get_opt(&job_name).await? {
+            Some(victim) => victim,
+            None => continue,
+        };
+        let victim_uid = victim.uid().unwrap_or_default();
+        let mut status = victim.status.clone().unwrap_or_default();
+
+        let (preempted_pods, note) = match preemption {
+            Preemption::ElasticPods { pods, .. } => {
+                let note = format!("Pods {} are preempted by job {}", pods.join(", "), preemptor_name);
+                (pods, note)
+            }
+            Preemption::Job { .. } => {
+                let owned_pods = ctx.pods.owned_pods(&victim_uid).into_keys().collect();
+                status.phase = JobStatusPhase::Pending;
+                status.set_condition(new_condition(
+                    ADMITTED_CONDITION,
+                    false,
+                    "Preempted",
+                    Some(format!("preempted by job {}", preemptor_name)),
+                ));
+                (owned_pods, format!("Job is preempted by job {}", preemptor_name))
+            }
+        };
+
+        for pod in &preempted_pods {
+            info!("preempt pod {}/{} for job {}", namespace, pod, preemptor_name);
+            if ctx.expectations.delete_pod(&pods, &victim_uid, pod).await? {
+                ctx.metrics.pods_deleted.inc();
+            }
+        }
+
+        status.set_condition(new_condition(
+            PREEMPTED_CONDITION,
+            true,
+            "Preempted",
+            Some(note.clone()),
+        ));
+        let preempted = apply_job_status(&jobs, &victim, status).await?;
+        ctx.snapshot.record_write(&victim, preempted);
+
+        Recorder::new(client.clone(), reporter.clone(), victim.object_ref(&()))
+            .publish(Event {
+                type_: EventType::Warning,
+                reason: "Preempted".into(),
+                note: Some(note),
+                action: "Preempting".into(),
+                secondary: Some(preemptor.object_ref(&())),
+            })
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use crate::indexed::{admitted_indexed_pods, build_indexed_pods};
+    use habitat_api::batch::LabelNames;
+    use habitat_scheduler::{fairshare::Cluster, preempt::Preemption, snapshot::Snapshot};
+    use kube::ResourceExt;
+
+    const SNAPSHOT: &str = r#"
+apiVersion: v1
+kind: Node
+metadata: {name: node-1}
+status:
+  allocatable: {cpu: "4"}
+---
+apiVersion: batch.habitat/v1beta1
+kind: Job
+metadata: {name: elastic, namespace: default, uid: "1234", creationTimestamp: "2022-01-01T00:00:00Z"}
+spec:
+  priority: 1
+  completionMode: Indexed
+  tasks:
+  - name: worker
+    parallelism: {min: 2, max: 4}
+    template:
+      spec:
+        containers: [{name: main, resources: {requests: {cpu: "1"}}}]
+status: {phase: Running, pending: 0, running: 4, terminating: 0, succeeded: 0, failed: 0}
+---
+apiVersion: batch.habitat/v1beta1
+kind: Job
+metadata: {name: urgent, namespace: default, creationTimestamp: "2022-01-01T01:00:00Z"}
+spec:
+  priority: 10
+  tasks:
+  - name: worker
+    parallelism: {min: 2, max: 2}
+    template:
+      spec:
+        containers: [{name: main, resources: {requests: {cpu: "1"}}}]
+status: {phase: Pending, pending: 0, running: 0, terminating: 0, succeeded: 0, failed: 0}
+"#;
+
+    fn worker(index: u32) -> String {
+        format!(
+            r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: elastic-worker-{0}
+  namespace: default
+  labels: {{habitat-task-owner: elastic, habitat-task: worker, habitat-replica-index: "{0}"}}
+spec:
+  containers: [{{name: main, resources: {{requests: {{cpu: "1"}}}}}}]
+status: {{phase: Running}}
+"#,
+            index
+        )
+    }
+
+    /// The pods of the victim the reconciliation creates, given the pods of the snapshot.
+    fn created_pods(snapshot: &Snapshot) -> Vec<String> {
+        let labels = LabelNames::default();
+        let job = &snapshot.jobs[0];
+        let mut pods = build_indexed_pods(job, &BTreeMap::new(), &labels);
+        pods.retain(|pod| !snapshot.pods.iter().any(|p| p.name_any() == pod.name_any()));
+        let cluster = Cluster::new(snapshot, &labels);
+        admitted_indexed_pods(job, pods, &cluster, &labels)
+            .iter()
+            .map(|pod| pod.name_any())
+            .collect()
+    }
+
+    #[test]
+    fn test_preempted_pods_stay_down() {
+        let yaml = (0..4)
+            .map(worker)
+            .fold(SNAPSHOT.to_string(), |yaml, pod| yaml + "---" + &pod);
+        let mut snapshot = Snapshot::from_yaml(&yaml).unwrap();
+        let labels = LabelNames::default();
+
+        let cluster = Cluster::new(&snapshot, &labels);
+        let request = cluster.pending[0].min_request.clone();
+        let plan = cluster.plan_preemption(None, &request, 10).unwrap();
+        let preempted = match &plan[..] {
+            [Preemption::ElasticPods { pods, .. }] => pods.clone(),
+            _ => panic!("unexpected plan {:?}", plan),
+        };
+        assert_eq!(preempted.len(), 2);
+
+        // the preempted pods are deleted, but not created again while the job preempting them waits
+        snapshot.pods.retain(|pod| !preempted.contains(&pod.name_any()));
+        assert!(created_pods(&snapshot).is_empty());
+
+        // nor once it is admitted
+        snapshot.jobs[1].status.as_mut().unwrap().phase = habitat_api::batch::JobStatusPhase::Running;
+        assert!(created_pods(&snapshot).is_empty());
+
+        // the pods are created again once it completes
+        snapshot.jobs[1].status.as_mut().unwrap().phase = habitat_api::batch::JobStatusPhase::Succeeded;
+        assert_eq!(created_pods(&snapshot), vec![
+            "elastic-worker-2",
+            "elastic-worker-3"
+        ]);
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use crate::{
+    error::{Error, Result},
+    manager::{Context, Reconciler},
+    status::apply_status,
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use habitat_api::{
+    batch::{JobStatusPhase, QueueStatus},
+    Queue,
+};
+use kube::{
+    api::Api,
+    runtime::{controller::Action, reflector::ObjectRef},
+    ResourceExt,
+};
+use tracing::{info, warn};
+
+pub(crate) async fn reconciler(queue: Arc<Queue>, ctx: Arc<Context>) -> Result<Action> {
+    let _timer = ctx.metrics.count_and_measure("Queue");
+    ctx.diagnostics.write().await.last_event = Utc::now();
+    let result = queue.reconcile(ctx.clone()).await.map_err(Error::KubeError);
+    if result.is_ok() {
+        ctx.backoff
+            .reset(&ObjectRef::from_obj(queue.as_ref()).to_string());
+    }
+    result
+}
+
+pub(crate) fn error_policy(queue: Arc<Queue>, error: &Error, ctx: Arc<Context>) -> Action {
+    ctx.metrics.reconcile_failure("Queue", error);
+    queue.error_policy(error, ctx)
+}
+
+#[async_trait]
+impl Reconciler for Queue {
+    async fn reconcile(&self, ctx: Arc<Context>) -> Result<Action, kube::Error> {
+        let name = self.name_any();
+        info!("reconcile queue {}", name);
+
+        let queues: Api<Queue> = Api::all(ctx.client.clone());
+        let mut status = QueueStatus::default();
+        for job in ctx.jobs.iter().flat_map(|store| store.state()) {
+            if job.spec.queue.as_ref() != Some(&name) {
+                continue;
+            }
+            match job.status.as_ref().map(|s| s.phase.clone()).unwrap_or_default() {
+                JobStatusPhase::Pending | JobStatusPhase::Ready => status.pending += 1,
+                JobStatusPhase::Running => status.running += 1,
+                JobStatusPhase::Terminating => status.terminating += 1,
+                JobStatusPhase::Succeeded => status.succeeded += 1,
+                JobStatusPhase::Failed | JobStatusPhase::Terminated => status.failed += 1,
+            }
+        }
+
+        apply_status(&queues, self, &status).await?;
+
+        // the changes of the jobs trigger their queues
+        Ok(Action::await_change())
+    }
+
+    async fn cleanup(&self, _ctx: Arc<Context>) -> Result<Action, kube::Error> {
+        Ok(Action::await_change())
+    }
+
+    fn error_policy(&self, error: &Error, ctx: Arc<Context>) -> Action {
+        warn!("reconcile queue failed: {:?}", error);
+        Action::requeue(ctx.backoff.next(&ObjectRef::from_obj(self).to_string()))
+    }
+}
@@ -0,0 +1,358 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use futures::{FutureExt, StreamExt, TryStreamExt};
+use habitat_api::{
+    batch::{replica_index, LabelNames},
+    Job,
+};
+use habitat_scheduler::framework::{Framework, NodeInfo, Placement, PodInfo};
+use k8s_openapi::{
+    api::core::v1::{Binding, Node, ObjectReference, Pod},
+    apimachinery::pkg::apis::meta::v1::Time,
+};
+use kube::{
+    api::{Api, DeleteParams, ListParams, PostParams},
+    client::Client,
+    core::ObjectMeta,
+    runtime::{
+        events::{Event, EventType, Recorder, Reporter},
+        reflector::{self, reflector, ObjectRef, Store},
+        watcher::{self, watcher},
+    },
+    Resource, ResourceExt,
+};
+use tokio::time::Duration;
+use tracing::{info, warn};
+
+/// Pods which must be bound together
+struct Gang {
+    /// `namespace/job` for the pods of a job, `namespace/pod` otherwise
+    key: String,
+    /// The object the scheduling events are reported on
+    object: ObjectReference,
+    priority: i32,
+    creation_timestamp: Option<Time>,
+    pods: Vec<PodInfo>,
+}
+
+/// Binds the pods with the `schedulerName` of the scheduler to nodes. The `parallelism.min` pods of a job
+/// are bound all at once, or not at all.
+pub struct Scheduler {
+    client: Client,
+    name: String,
+    framework: Framework,
+    /// The labels of the pods created for the jobs, the same as the controller's
+    labels: LabelNames,
+    reporter: Reporter,
+    /// Why each gang is waiting, to only report when the reason changes
+    waiting: HashMap<String, String>,
+    /// The stores the scheduling cycles read, fed by `run`
+    nodes: Store<Node>,
+    pods: Store<Pod>,
+    jobs: Store<Job>,
+    /// The nodes of the pods bound by the scheduler, by pod uid, until the pod store observes the bindings
+    assumed: HashMap<String, String>,
+}
+
+impl Scheduler {
+    pub fn new(client: Client, name: impl Into<String>, framework: Framework, labels: LabelNames) -> Self {
+        Self {
+            client,
+            name: name.into(),
+            framework,
+            labels,
+            reporter: "habitat-scheduler".into(),
+            waiting: HashMap::new(),
+            nodes: reflector::store().0,
+            pods: reflector::store().0,
+            jobs: reflector::store().0,
+            assumed: HashMap::new(),
+        }
+    }
+
+    /// Watches the nodes, pods and jobs into the stores, and schedules the pending pods whenever the stores
+    /// change, and every `interval` as the bound pods release their resources.
+    pub async fn run(mut self, interval: Duration) {
+        let lp = ListParams::default();
+        let (nodes, nodes_writer) = reflector::store();
+        let (pods, pods_writer) = reflector::store();
+        let (jobs, jobs_writer) = reflector::store();
+        self.nodes = nodes;
+        self.pods = pods;
+        self.jobs = jobs;
+        // the events yield the index of the store once it's listed
+        let mut changes = futures::stream::select_all(vec![
+            reflector(nodes_writer, watcher(Api::all(self.client.clone()), lp.clone()))
+                .map_ok(|event| listed(0, &event))
+                .boxed(),
+            reflector(pods_writer, watcher(Api::all(self.client.clone()), lp.clone()))
+                .map_ok(|event| listed(1, &event))
+                .boxed(),
+            reflector(jobs_writer, watcher(Api::all(self.client.clone()), lp))
+                .map_ok(|event| listed(2, &event))
+                .boxed(),
+        ]);
+
+        let mut synced = HashSet::new();
+        loop {
+            // the cycles wait for every store to be listed, not to report the pods unschedulable
+            if synced.len() == 3 {
+                if let Err(error) = self.schedule_once().await {
+                    warn!("scheduling failed: {:?}", error);
+                }
+            }
+
+            match tokio::time::timeout(interval, changes.try_next()).await {
+                Ok(Ok(None)) => break,
+                Ok(Ok(Some(store))) => synced.extend(store),
+                Ok(Err(error)) => {
+                    warn!("watch failed: {:?}", error);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                Err(_) => (),
+            }
+            // coalesce the burst of changes, e.g. all the pods of a job being created
+            while let Some(Ok(Some(store))) = changes.try_next().now_or_never() {
+                synced.extend(store);
+            }
+        }
+    }
+
+    /// Runs one scheduling cycle over all the pending pods of the stores.
+    pub async fn schedule_once(&mut self) -> Result<(), kube::Error> {
+        let mut nodes = self
+            .nodes
+            .state()
+            .iter()
+            .map(|node| NodeInfo::new(node.as_ref().clone()))
+            .collect::<Vec<_>>();
+
+        let mut pending = vec![];
+        let mut bound = HashMap::<(String, String), Vec<Pod>>::new();
+        let mut assumed = HashMap::new();
+        for pod in self.pods.state() {
+            let pod = pod.as_ref().clone();
+            let phase = pod.status.as_ref().and_then(|s| s.phase.as_deref());
+            if matches!(phase, Some("Succeeded" | "Failed")) {
+                continue;
+            }
+            let spec = pod.spec.as_ref();
+            let node_name = match spec.and_then(|s| s.node_name.clone()) {
+                Some(node_name) => Some(node_name),
+                // the pods bound by the previous cycles may not be observed bound yet
+                None => self
+                    .assumed
+                    .remove_entry(&pod.uid().unwrap_or_default())
+                    .map(|(uid, node_name)| {
+                        assumed.insert(uid, node_name.clone());
+                        node_name
+                    }),
+            };
+            match node_name.as_deref() {
+                Some(node_name) => {
+                    let info = PodInfo::new(pod.clone());
+                    if let Some(node) = nodes.iter_mut().find(|n| n.name() == node_name) {
+                        node.add_pod(&info);
+                    }
+                    if let (Some(ns), Some(owner)) =
+                        (pod.namespace(), pod.labels().get(&self.labels.task_owner))
+                    {
+                        bound.entry((ns, owner.clone())).or_default().push(pod);
+                    }
+                }
+                None if spec.and_then(|s| s.scheduler_name.as_deref()) == Some(&self.name)
+                    && pod.metadata.deletion_timestamp.is_none() =>
+                {
+                    pending.push(pod)
+                }
+                None => (),
+            }
+        }
+        // the bindings observed, or of the pods deleted, are forgotten
+        self.assumed = assumed;
+
+        for gang in self.gangs(pending, &bound).await? {
+            match self.framework.schedule_gang(&gang.pods, &mut nodes) {
+                Ok(placements) => {
+                    self.waiting.remove(&gang.key);
+                    self.bind(&gang, placements).await?;
+                }
+                Err(reason) => self.report_waiting(&gang, reason).await?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Groups the pending pods into gangs, the highest priority and oldest first.
+    async fn gangs(
+        &mut self,
+        pending: Vec<Pod>,
+        bound: &HashMap<(String, String), Vec<Pod>>,
+    ) -> Result<Vec<Gang>, kube::Error> {
+        let mut gangs = vec![];
+        let mut jobs = BTreeMap::<(String, String), Vec<Pod>>::new();
+        for pod in pending {
+            let ns = pod.namespace().unwrap_or_default();
+            match pod.labels().get(&self.labels.task_owner).cloned() {
+                Some(owner) => jobs.entry((ns, owner)).or_default().push(pod),
+                None => gangs.push(pod_gang(pod)),
+            }
+        }
+
+        for ((ns, owner), pods) in jobs {
+            let job = match self.jobs.get(&ObjectRef::new(&owner).within(&ns)) {
+                Some(job) => job,
+                None => continue,
+            };
+            let min = job
+                .spec
+                .tasks
+                .iter()
+                .map(|t| (t.name.as_str(), t.parallelism.min))
+                .collect::<HashMap<_, _>>();
+            let is_min_pod = |pod: &Pod| {
+                let task_min = pod
+                    .labels()
+                    .get(&self.labels.task_name)
+                    .and_then(|t| min.get(t.as_str()));
+                matches!((task_min, replica_index(pod, &self.labels)), (Some(min), Some(idx)) if idx < *min)
+            };
+
+            let (min_pods, elastic_pods): (Vec<_>, Vec<_>) = pods.into_iter().partition(|p| is_min_pod(p));
+            if min_pods.is_empty() {
+                // the gang is already running, the other pods are scheduled one by one
+                gangs.extend(elastic_pods.into_iter().map(pod_gang));
+                continue;
+            }
+
+            let gang = Gang {
+                key: format!("{}/{}", ns, owner),
+                object: job.object_ref(&()),
+                priority: min_pods.iter().map(pod_priority).max().unwrap_or_default(),
+                creation_timestamp: job.creation_timestamp(),
+                pods: min_pods.into_iter().map(PodInfo::new).collect(),
+            };
+            let created = gang.pods.len()
+                + bound
+                    .get(&(ns, owner))
+                    .into_iter()
+                    .flatten()
+                    .filter(|p| is_min_pod(p))
+                    .count();
+            let total = min.values().sum::<u32>() as usize;
+            if created < total {
+                let reason = format!("{}/{} pods of the gang are created", created, total);
+                self.report_waiting(&gang, reason).await?;
+                continue;
+            }
+            gangs.push(gang);
+        }
+
+        gangs.sort_by(|a, b| {
+            b.priority
+                .cmp(&a.priority)
+                .then_with(|| a.creation_timestamp.cmp(&b.creation_timestamp))
+        });
+        Ok(gangs)
+    }
+
+    /// Binds the pods of a gang to their nodes. If a binding fails, the pods bound so far are deleted so
+    /// that the gang is scheduled again as a whole once they are recreated.
+    async fn bind(&mut self, gang: &Gang, placements: Vec<Placement>) -> Result<(), kube::Error> {
+        let mut bound: Vec<String> = vec![];
+        for (pod, placement) in gang.pods.iter().zip(placements) {
+            let ns = pod.pod.namespace().unwrap_or_default();
+            let pods: Api<Pod> = Api::namespaced(self.client.clone(), &ns);
+            let binding = Binding {
+                metadata: ObjectMeta {
+                    name: Some(placement.pod.clone()),
+                    namespace: Some(ns.clone()),
+                    ..Default::default()
+                },
+                target: ObjectReference {
+                    api_version: Some("v1".to_string()),
+                    kind: Some("Node".to_string()),
+                    name: Some(placement.node.clone()),
+                    ..Default::default()
+                },
+            };
+            let data = serde_json::to_vec(&binding).map_err(kube::Error::SerdeError)?;
+
+            if let Err(error) = pods
+                .create_subresource::<serde_json::Value>(
+                    "binding",
+                    &placement.pod,
+                    &PostParams::default(),
+                    data,
+                )
+                .await
+            {
+                warn!("failed to bind pod {}/{}: {:?}", ns, placement.pod, error);
+                for pod in &bound {
+                    pods.delete(pod, &DeleteParams::default()).await?;
+                }
+                return Err(error);
+            }
+            info!("bound pod {}/{} to node {}", ns, placement.pod, placement.node);
+            bound.push(placement.pod.clone());
+            self.assumed
+                .insert(pod.pod.uid().unwrap_or_default(), placement.node.clone());
+
+            Recorder::new(
+                self.client.clone(),
+                self.reporter.clone(),
+                pod.pod.object_ref(&()),
+            )
+            .publish(Event {
+                type_: EventType::Normal,
+                reason: "Scheduled".into(),
+                note: Some(format!(
+                    "Successfully assigned {}/{} to {}",
+                    ns, placement.pod, placement.node
+                )),
+                action: "Binding".into(),
+                secondary: None,
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn report_waiting(&mut self, gang: &Gang, reason: String) -> Result<(), kube::Error> {
+        if self.waiting.get(&gang.key) == Some(&reason) {
+            return Ok(());
+        }
+        info!("gang {} is waiting: {}", gang.key, reason);
+        Recorder::new(self.client.clone(), self.reporter.clone(), gang.object.clone())
+            .publish(Event {
+                type_: EventType::Warning,
+                reason: "FailedScheduling".into(),
+                note: Some(reason.clone()),
+                action: "Scheduling".into(),
+                secondary: None,
+            })
+            .await?;
+        self.waiting.insert(gang.key.clone(), reason);
+        Ok(())
+    }
+}
+
+/// The index of the store of a reflector once the event lists it.
+fn listed<K>(store: usize, event: &watcher::Event<K>) -> Option<usize> {
+    matches!(event, watcher::Event::Restarted(_)).then(|| store)
+}
+
+fn pod_gang(pod: Pod) -> Gang {
+    Gang {
+        key: format!("{}/{}", pod.namespace().unwrap_or_default(), pod.name_any()),
+        object: pod.object_ref(&()),
+        priority: pod_priority(&pod),
+        creation_timestamp: pod.creation_timestamp(),
+        pods: vec![PodInfo::new(pod)],
+    }
+}
+
+fn pod_priority(pod: &Pod) -> i32 {
+    pod.spec.as_ref().and_then(|s| s.priority).unwrap_or_default()
+}
@@ -0,0 +1,309 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use futures::{future::BoxFuture, FutureExt, StreamExt};
+use habitat_api::{Job, Queue};
+use habitat_scheduler::snapshot::Snapshot;
+use k8s_openapi::api::{
+    core::v1::{Node, Pod},
+    scheduling::v1::PriorityClass,
+};
+use kube::{
+    api::{Api, ListParams},
+    runtime::{
+        reflector::{self, reflector, store::Writer, ObjectRef, Store},
+        watcher::{self, watcher},
+    },
+    Client, Resource, ResourceExt,
+};
+use serde::de::DeserializeOwned;
+use tracing::warn;
+
+use crate::{cache::PodCache, config::Config};
+
+/// The jobs written by the admissions, with the resource version they were written from
+type Writes = HashMap<ObjectRef<Job>, (Option<String>, Job)>;
+
+/// The stores of the objects the admission of the jobs is decided from, fed by their watchers so the
+/// admissions read them from memory instead of listing them.
+///
+/// The task pods of the unwatched namespaces are watched as well, so the ones of the jobs of the other
+/// namespaces, e.g. admitted by another namespace-scoped controller, are accounted as using the nodes. Their
+/// jobs aren't accounted in their queues though, so the controllers sharing a queue should watch the same
+/// namespaces.
+///
+/// A namespace-scoped controller may not be allowed to watch the cluster-scoped objects, or the pods of the
+/// other namespaces. Those it isn't allowed to list are left out: the jobs are then admitted regardless of
+/// the capacity of the cluster without the nodes, or of their queues without the queues, and with the lowest
+/// priority without the priority classes.
+#[derive(Clone)]
+pub struct SnapshotStores {
+    nodes: Store<Node>,
+    queues: Store<Queue>,
+    priority_classes: Store<PriorityClass>,
+    /// The task pods of the unwatched namespaces, the ones of the watched namespaces are in the pod cache
+    other_pods: Store<Pod>,
+    /// The number of stores listed once
+    listed: Arc<AtomicUsize>,
+    /// The number of stores fed by watchers
+    watched: usize,
+    /// Whether the controller isn't allowed to list the nodes
+    nodes_forbidden: Arc<AtomicBool>,
+    /// The jobs whose status was written by the admissions, but not observed in the job stores yet, with
+    /// the resource version they were written from
+    written: Arc<Mutex<Writes>>,
+    /// The pods created by the admissions, but not observed in the pod cache yet, by namespace and name
+    created: Arc<Mutex<HashMap<(String, String), Pod>>>,
+}
+
+impl SnapshotStores {
+    /// Creates the stores, and the future feeding them.
+    pub fn new(client: Client, config: &Config) -> (Self, BoxFuture<'static, ()>) {
+        let lp = ListParams::default();
+        let (nodes, nodes_writer) = reflector::store();
+        let (queues, queues_writer) = reflector::store();
+        let (priority_classes, priority_classes_writer) = reflector::store();
+        let (other_pods, other_pods_writer) = reflector::store();
+        let listed = Arc::new(AtomicUsize::new(0));
+        let nodes_forbidden = Arc::new(AtomicBool::new(false));
+        // the cluster-wide permissions are only required to watch all the namespaces
+        let optional = !config.namespaces.is_empty();
+
+        let mut reflectors = vec![
+            watch_store(
+                Api::all(client.clone()),
+                lp.clone(),
+                nodes_writer,
+                listed.clone(),
+                optional.then(|| nodes_forbidden.clone()),
+            ),
+            watch_store(
+                Api::all(client.clone()),
+                lp.clone(),
+                queues_writer,
+                listed.clone(),
+                optional.then(Default::default),
+            ),
+            watch_store(
+                Api::all(client.clone()),
+                lp,
+                priority_classes_writer,
+                listed.clone(),
+                optional.then(Default::default),
+            ),
+        ];
+        // the pod cache has the task pods of all the namespaces when they are all watched
+        if !config.namespaces.is_empty() {
+            let unwatched = config
+                .namespaces
+                .iter()
+                .map(|ns| format!("metadata.namespace!={}", ns))
+                .collect::<Vec<_>>()
+                .join(",");
+            let lp = ListParams::default()
+                .labels(&config.labels.task_owner)
+                .fields(&unwatched);
+            reflectors.push(watch_store(
+                Api::<Pod>::all(client),
+                lp,
+                other_pods_writer,
+                listed.clone(),
+                Some(Default::default()),
+            ));
+        }
+
+        let stores = Self {
+            nodes,
+            queues,
+            priority_classes,
+            other_pods,
+            listed,
+            watched: reflectors.len(),
+            nodes_forbidden,
+            written: Default::default(),
+            created: Default::default(),
+        };
+        let reflectors = futures::future::join_all(reflectors).map(|_| ()).boxed();
+        (stores, reflectors)
+    }
+
+    /// Whether every store has been listed once.
+    pub fn synced(&self) -> bool {
+        self.listed.load(Ordering::Relaxed) == self.watched
+    }
+
+    /// Records the status of a job written by an admission, e.g. the job admitted or preempted, so the next
+    /// admissions read it until the job stores observe the write.
+    pub fn record_write(&self, from: &Job, written: Job) {
+        self.written
+            .lock()
+            .unwrap()
+            .insert(ObjectRef::from_obj(from), (from.resource_version(), written));
+    }
+
+    /// Records a pod created by an admission, e.g. above the `parallelism.min` of its job, so the next
+    /// admissions account it until the pod cache observes it.
+    pub fn record_pod(&self, namespace: &str, pod: &Pod) {
+        let mut pod = pod.clone();
+        pod.metadata.namespace = Some(namespace.to_string());
+        self.created
+            .lock()
+            .unwrap()
+            .insert((namespace.to_string(), pod.name_any()), pod);
+    }
+
+    /// Forgets the writes of a deleted job.
+    pub fn forget(&self, job: &Job) {
+        self.written.lock().unwrap().remove(&ObjectRef::from_obj(job));
+    }
+
+    /// The objects the admission of the jobs is decided from, with the jobs of the watched namespaces and
+    /// their pods.
+    pub fn snapshot(&self, jobs: &[Store<Job>], pods: &PodCache) -> Snapshot {
+        let mut all_pods = pods.pods();
+        all_pods.extend(cloned(&self.other_pods));
+        let mut created = self.created.lock().unwrap();
+        if !created.is_empty() {
+            for pod in &all_pods {
+                created.remove(&(pod.namespace().unwrap_or_default(), pod.name_any()));
+            }
+            all_pods.extend(created.values().cloned());
+        }
+        Snapshot {
+            nodes: cloned(&self.nodes),
+            pods: all_pods,
+            queues: cloned(&self.queues),
+            jobs: observed_jobs(&mut self.written.lock().unwrap(), jobs.iter().flat_map(cloned)),
+            priority_classes: cloned(&self.priority_classes),
+            nodes_unknown: self.nodes_forbidden.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The jobs of the stores, with the writes they haven't observed yet. The writes observed, i.e. whose job
+/// changed since the version they were written from, are forgotten.
+fn observed_jobs(written: &mut Writes, jobs: impl Iterator<Item = Job>) -> Vec<Job> {
+    let mut observed = vec![];
+    for job in jobs {
+        let job_ref = ObjectRef::from_obj(&job);
+        match written.get(&job_ref) {
+            Some((from, _)) if *from == job.resource_version() => continue,
+            Some(_) => {
+                written.remove(&job_ref);
+            }
+            None => (),
+        }
+        observed.push(job);
+    }
+    observed.extend(written.values().map(|(_, job)| job.clone()));
+    observed
+}
+
+fn cloned<K>(store: &Store<K>) -> Vec<K>
+where
+    K: Resource + Clone + 'static,
+    K::DynamicType: Eq + std::hash::Hash + Clone,
+{
+    store.state().iter().map(|obj| obj.as_ref().clone()).collect()
+}
+
+/// Feeds a store from its watcher, counting it once listed.
+///
+/// If the watch is optional, i.e. `forbidden` is given, the objects the controller isn't allowed to list
+/// aren't watched. The store is left empty and counted as listed then, and `forbidden` is set.
+fn watch_store<K>(
+    api: Api<K>,
+    lp: ListParams,
+    writer: Writer<K>,
+    listed: Arc<AtomicUsize>,
+    forbidden: Option<Arc<AtomicBool>>,
+) -> BoxFuture<'static, ()>
+where
+    K: Resource + Clone + DeserializeOwned + Debug + Send + Sync + 'static,
+    K::DynamicType: Eq + Hash + Clone + Default,
+{
+    async move {
+        if let Some(forbidden) = forbidden {
+            if let Err(err) = api.list(&ListParams::default().limit(1)).await {
+                if is_forbidden(&err) {
+                    warn!(
+                        "not allowed to list {}, the jobs are admitted without them: {}",
+                        K::plural(&Default::default()),
+                        err
+                    );
+                    forbidden.store(true, Ordering::Relaxed);
+                    listed.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+            }
+        }
+
+        let mut counted = false;
+        reflector(writer, watcher(api, lp))
+            .for_each(move |event| {
+                if let (false, Ok(watcher::Event::Restarted(_))) = (counted, event) {
+                    counted = true;
+                    listed.fetch_add(1, Ordering::Relaxed);
+                }
+                futures::future::ready(())
+            })
+            .await
+    }
+    .boxed()
+}
+
+/// Whether an api request was forbidden, e.g. by the RBAC of a namespace-scoped controller.
+pub(crate) fn is_forbidden(err: &kube::Error) -> bool {
+    matches!(err, kube::Error::Api(err) if err.code == 403)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::observed_jobs;
+    use habitat_api::{batch::JobStatusPhase, Job};
+    use kube::runtime::reflector::ObjectRef;
+
+    fn job(resource_version: &str, phase: &str) -> Job {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "batch.habitat/v1beta1",
+            "kind": "Job",
+            "metadata": {"name": "train", "namespace": "default", "resourceVersion": resource_version},
+            "spec": {"tasks": []},
+            "status": {"phase": phase, "pending": 0, "running": 0, "terminating": 0, "succeeded": 0, "failed": 0}
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_observed_jobs() {
+        let pending = job("1", "Pending");
+        let mut written = HashMap::from([(
+            ObjectRef::from_obj(&pending),
+            (Some("1".to_string()), job("2", "Ready")),
+        )]);
+        let phases = |jobs: Vec<Job>| {
+            jobs.into_iter()
+                .map(|j| j.status.unwrap().phase)
+                .collect::<Vec<_>>()
+        };
+
+        // the admission is read until the store observes it
+        let jobs = observed_jobs(&mut written, vec![pending].into_iter());
+        assert_eq!(phases(jobs), vec![JobStatusPhase::Ready]);
+        let jobs = observed_jobs(&mut written, vec![].into_iter());
+        assert_eq!(phases(jobs), vec![JobStatusPhase::Ready]);
+
+        let jobs = observed_jobs(&mut written, vec![job("3", "Running")].into_iter());
+        assert_eq!(phases(jobs), vec![JobStatusPhase::Running]);
+        assert!(written.is_empty());
+    }
+}
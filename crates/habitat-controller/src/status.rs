@@ -0,0 +1,85 @@
+use std::fmt::Debug;
+
+use habitat_api::{batch::JobStatus, Job};
+use kube::{
+    api::{Api, Patch, PatchParams},
+    Resource, ResourceExt,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// The field manager of the statuses applied by the controller
+pub const FIELD_MANAGER: &str = "habitat-controller";
+
+/// Applies the status of an object with server side apply.
+///
+/// The status is the whole status owned by the controller, the fields it omits are removed. The
+/// resource version of the object is a precondition, so a status computed from a stale object is rejected
+/// with a conflict instead of overwriting a newer one.
+pub(crate) async fn apply_status<K>(api: &Api<K>, obj: &K, status: &impl Serialize) -> Result<K, kube::Error>
+where
+    K: Resource<DynamicType = ()> + Clone + DeserializeOwned + Debug,
+{
+    let patch = Patch::Apply(status_patch(obj, status));
+    api.patch_status(
+        &obj.name_any(),
+        &PatchParams::apply(FIELD_MANAGER).force(),
+        &patch,
+    )
+    .await
+}
+
+/// The partial object applied to update the status of an object.
+fn status_patch<K>(obj: &K, status: &impl Serialize) -> serde_json::Value
+where
+    K: Resource<DynamicType = ()>,
+{
+    serde_json::json!({
+        "apiVersion": K::api_version(&()),
+        "kind": K::kind(&()),
+        "metadata": {
+            "name": obj.name_any(),
+            "resourceVersion": obj.resource_version(),
+        },
+        "status": status,
+    })
+}
+
+/// Applies the status of a job, observing the generation of its spec.
+pub(crate) async fn apply_job_status(
+    jobs: &Api<Job>,
+    job: &Job,
+    mut status: JobStatus,
+) -> Result<Job, kube::Error> {
+    status.observed_generation = job.metadata.generation;
+    apply_status(jobs, job, &status).await
+}
+
+#[cfg(test)]
+mod test {
+    use habitat_api::{batch::JobStatus, Job};
+
+    use super::status_patch;
+
+    #[test]
+    fn test_status_patch() {
+        let job: Job = serde_json::from_value(serde_json::json!({
+            "apiVersion": "batch.habitat/v1beta1",
+            "kind": "Job",
+            "metadata": {"name": "job", "namespace": "default", "resourceVersion": "42", "generation": 3},
+            "spec": {"tasks": []}
+        }))
+        .unwrap();
+        let status = JobStatus {
+            observed_generation: job.metadata.generation,
+            ..Default::default()
+        };
+
+        let patch = status_patch(&job, &status);
+        assert_eq!(patch["apiVersion"], "batch.habitat/v1beta1");
+        assert_eq!(patch["kind"], "Job");
+        assert_eq!(patch["metadata"]["resourceVersion"], "42");
+        assert_eq!(patch["status"]["observedGeneration"], 3);
+        // the unset times are omitted instead of applied as null
+        assert!(patch["status"].get("startTime").is_none());
+    }
+}
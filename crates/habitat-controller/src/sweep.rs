@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+
+use habitat_api::{
+    batch::{
+        replica_index, JobStatusPhase, LabelNames, SweepIndexPhase, SweepIndexStatus, SweepSpec,
+        SWEEP_INDEX_ENV,
+    },
+    Job,
+};
+use k8s_openapi::api::core::v1::{EnvVar, Pod};
+use kube::Resource;
+
+use crate::manager::build_pod;
+
+/// The results of the indexes whose pods were created, in index order. The finished indexes recorded in
+/// the job status stay finished, even once their pods are gone.
+pub(crate) fn index_statuses<'a>(
+    job: &Job,
+    sweep: &SweepSpec,
+    pods: impl IntoIterator<Item = &'a Pod>,
+    labels: &LabelNames,
+) -> Vec<SweepIndexStatus> {
+    let finished = job
+        .status
+        .iter()
+        .flat_map(|s| &s.sweep)
+        .filter(|s| s.index < sweep.len() && s.phase.is_finished())
+        .map(|s| (s.index, s.clone()))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut phases = BTreeMap::<u32, Vec<Option<&str>>>::new();
+    for pod in pods {
+        if let Some(index) = replica_index(pod, labels).filter(|i| *i < sweep.len()) {
+            let phase = if pod.meta().deletion_timestamp.is_some() {
+                None
+            } else {
+                pod.status.as_ref().and_then(|s| s.phase.as_deref())
+            };
+            phases.entry(index).or_default().push(phase);
+        }
+    }
+
+    let mut statuses = phases
+        .into_iter()
+        .filter(|(index, _)| !finished.contains_key(index))
+        .map(|(index, phases)| {
+            let phase = if phases.contains(&Some("Failed")) {
+                SweepIndexPhase::Failed
+            } else if phases.iter().all(|p| *p == Some("Succeeded")) {
+                SweepIndexPhase::Succeeded
+            } else if phases.contains(&Some("Running")) {
+                SweepIndexPhase::Running
+            } else {
+                SweepIndexPhase::Pending
+            };
+            SweepIndexStatus {
+                index,
+                parameters: sweep.combination(index),
+                phase,
+            }
+        })
+        .collect::<Vec<_>>();
+    statuses.extend(finished.into_values());
+    statuses.sort_by_key(|s| s.index);
+    statuses
+}
+
+/// The pods of the next indexes to start, so that at most `maxConcurrent` indexes are running, as many as
+/// the job was admitted with.
+pub(crate) fn build_sweep_pods(
+    job: &Job,
+    sweep: &SweepSpec,
+    statuses: &[SweepIndexStatus],
+    labels: &LabelNames,
+) -> Vec<Pod> {
+    let active = statuses.iter().filter(|s| !s.phase.is_finished()).count() as u32;
+    let available = sweep.concurrency(&job.spec.tasks).saturating_sub(active);
+
+    let mut pods = vec![];
+    let next_indexes = (0..sweep.len())
+        .filter(|index| !statuses.iter().any(|s| s.index == *index))
+        .take(available as usize);
+    for index in next_indexes {
+        let mut env = vec![EnvVar {
+            name: SWEEP_INDEX_ENV.to_string(),
+            value: Some(index.to_string()),
+            ..Default::default()
+        }];
+        env.extend(sweep.combination(index).into_iter().map(|(name, value)| EnvVar {
+            name,
+            value: Some(value),
+            ..Default::default()
+        }));
+
+        for task in &job.spec.tasks {
+            let mut pod = build_pod(job, task, index, labels);
+            for container in pod.spec.iter_mut().flat_map(|s| s.containers.iter_mut()) {
+                container.env.get_or_insert_with(Vec::new).extend(env.clone());
+            }
+            pods.push(pod);
+        }
+    }
+    pods
+}
+
+/// The phase of a sweep job, which completes once all its indexes are finished. The job succeeds only if
+/// all of them succeeded.
+pub(crate) fn sweep_phase(sweep: &SweepSpec, statuses: &[SweepIndexStatus]) -> JobStatusPhase {
+    let finished = statuses.iter().filter(|s| s.phase.is_finished()).count() as u32;
+    if finished == sweep.len() {
+        if statuses.iter().all(|s| s.phase == SweepIndexPhase::Succeeded) {
+            JobStatusPhase::Succeeded
+        } else {
+            JobStatusPhase::Failed
+        }
+    } else if statuses.iter().any(|s| s.phase == SweepIndexPhase::Running) {
+        JobStatusPhase::Running
+    } else {
+        JobStatusPhase::Ready
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{build_sweep_pods, index_statuses, sweep_phase};
+    use habitat_api::{
+        batch::{JobStatusPhase, LabelNames, SweepIndexPhase, SweepSpec},
+        Job,
+    };
+    use k8s_openapi::api::core::v1::Pod;
+    use kube::ResourceExt;
+
+    fn pod(name: &str, phase: &str) -> Pod {
+        serde_json::from_value(serde_json::json!({
+            "metadata": {"name": name},
+            "status": {"phase": phase}
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_index_statuses() {
+        let sweep = serde_json::from_value::<SweepSpec>(serde_json::json!({
+            "parameters": [{"name": "LR", "values": ["0.1", "0.01", "0.001"]}],
+            "maxConcurrent": 2
+        }))
+        .unwrap();
+        let pods = vec![
+            pod("ps-0", "Succeeded"),
+            pod("worker-0", "Succeeded"),
+            pod("ps-1", "Running"),
+            pod("worker-1", "Failed"),
+            pod("ps-2", "Pending"),
+            pod("worker-2", "Running"),
+        ];
+
+        let job = serde_json::from_value::<Job>(serde_json::json!({
+            "apiVersion": "batch.habitat/v1beta1",
+            "kind": "Job",
+            "metadata": {"name": "sweep", "namespace": "default", "uid": "1234"},
+            "spec": {"tasks": []}
+        }))
+        .unwrap();
+
+        let statuses = index_statuses(&job, &sweep, &pods, &LabelNames::default());
+        let phases = statuses.iter().map(|s| s.phase).collect::<Vec<_>>();
+        assert_eq!(phases, vec![
+            SweepIndexPhase::Succeeded,
+            SweepIndexPhase::Failed,
+            SweepIndexPhase::Running
+        ]);
+        assert_eq!(statuses[2].parameters["LR"], "0.001");
+        assert_eq!(sweep_phase(&sweep, &statuses), JobStatusPhase::Running);
+        assert_eq!(sweep_phase(&sweep, &statuses[..2]), JobStatusPhase::Ready);
+
+        // the recorded indexes stay finished once their pods are gone, e.g. garbage collected
+        let job = Job {
+            status: Some(serde_json::from_value(serde_json::json!({
+                "phase": "Running", "pending": 0, "running": 0, "terminating": 0, "succeeded": 0, "failed": 0,
+                "sweep": [{"index": 0, "parameters": {"LR": "0.1"}, "phase": "Succeeded"}]
+            }))
+            .unwrap()),
+            ..job
+        };
+        let statuses = index_statuses(&job, &sweep, &pods[2..], &LabelNames::default());
+        let phases = statuses.iter().map(|s| (s.index, s.phase)).collect::<Vec<_>>();
+        assert_eq!(phases, vec![
+            (0, SweepIndexPhase::Succeeded),
+            (1, SweepIndexPhase::Failed),
+            (2, SweepIndexPhase::Running)
+        ]);
+    }
+
+    #[test]
+    fn test_build_sweep_pods() {
+        let job = serde_json::from_value::<Job>(serde_json::json!({
+            "apiVersion": "batch.habitat/v1beta1",
+            "kind": "Job",
+            "metadata": {"name": "sweep", "namespace": "default", "uid": "1234"},
+            "spec": {
+                "sweep": {"parameters": [{"name": "LR", "values": ["0.1", "0.01", "0.001"]}]},
+                "tasks": [{"name": "worker", "parallelism": {"min": 2}, "template": {"spec": {"containers": []}}}]
+            }
+        }))
+        .unwrap();
+        let sweep = job.spec.sweep.as_ref().unwrap();
+
+        // without `maxConcurrent`, only the indexes the job was admitted with run at once
+        let names = build_sweep_pods(&job, sweep, &[], &LabelNames::default())
+            .iter()
+            .map(|p| p.name_any())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["sweep-worker-0", "sweep-worker-1"]);
+    }
+}
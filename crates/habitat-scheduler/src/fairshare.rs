@@ -0,0 +1,911 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use habitat_api::{
+    batch::{
+        replica_index, JobStatusPhase, LabelNames, Priority, ADMITTED_CONDITION,
+        WAITING_FOR_DEPENDENCIES_CONDITION,
+    },
+    resource::{
+        add_resources, core_pod_requests, job_min_requests, pod_requests, to_resource_list, ResourceList,
+    },
+    Job,
+};
+use k8s_openapi::{
+    api::core::v1::Pod,
+    chrono::{DateTime, Duration, Utc},
+};
+use kube::ResourceExt;
+
+use crate::snapshot::Snapshot;
+
+/// Resources allocated to the admitted jobs of a queue
+#[derive(Clone, Debug, Default)]
+pub struct QueueShare {
+    /// The relative weight of the queue
+    pub weight: u32,
+    /// The upper bound of the resources the queue can use
+    pub capacity: Option<ResourceList>,
+    /// The resources of the admitted jobs of the queue
+    pub allocated: ResourceList,
+    /// Whether the queue has jobs waiting for admission
+    pub waiting: bool,
+    /// Whether the resources of the queue can be reclaimed by the other queues
+    pub reclaimable: bool,
+}
+
+/// Resources used by an admitted job
+#[derive(Clone, Debug, Default)]
+pub struct JobUsage {
+    pub namespace: String,
+    pub name: String,
+    pub queue: Option<String>,
+    /// The resolved priority of the job
+    pub priority: i32,
+    /// The resources requested by the minimum number of pods
+    pub min_request: ResourceList,
+    /// The live pods above `parallelism.min`, with their requested resources
+    pub elastic_pods: Vec<(String, ResourceList)>,
+    /// When the job is expected to complete, if its running time is known
+    pub end: Option<DateTime<Utc>>,
+}
+
+/// A job waiting for admission
+#[derive(Clone, Debug, Default)]
+pub struct PendingJob {
+    pub namespace: String,
+    pub name: String,
+    pub queue: Option<String>,
+    /// The resolved priority of the job
+    pub priority: i32,
+    pub creation_timestamp: Option<DateTime<Utc>>,
+    /// The resources requested by the minimum number of pods
+    pub min_request: ResourceList,
+    /// The expected running time of the job in seconds
+    pub duration: Option<u64>,
+}
+
+/// A snapshot of the cluster resources, used to decide which jobs are admitted
+#[derive(Clone, Debug, Default)]
+pub struct Cluster {
+    /// The allocatable resources of the schedulable nodes
+    pub capacity: ResourceList,
+    /// Whether the nodes are unknown, in which case the jobs are only bounded by the capacity of their
+    /// queues
+    pub nodes_unknown: bool,
+    /// The resources of all the admitted jobs, including the ones without queue, and of the live pods of
+    /// the jobs out of the snapshot
+    pub allocated: ResourceList,
+    /// The queues by name
+    pub queues: BTreeMap<String, QueueShare>,
+    /// The admitted jobs, the most recently created first
+    pub jobs: Vec<JobUsage>,
+    /// The jobs waiting for admission, the highest priority and oldest first
+    pub pending: Vec<PendingJob>,
+    /// The values of the priority classes by name
+    pub priority_classes: BTreeMap<String, i32>,
+}
+
+impl Cluster {
+    /// Accounts the schedulable nodes, the queues and the admitted jobs of a snapshot, whose pods are
+    /// matched to their jobs by the given labels.
+    ///
+    /// The live pods of the jobs which aren't in the snapshot, e.g. of the namespaces not watched by a
+    /// namespace-scoped controller, still use the nodes, so they are accounted without queue.
+    pub fn new(snapshot: &Snapshot, labels: &LabelNames) -> Self {
+        let mut cluster = Cluster {
+            nodes_unknown: snapshot.nodes_unknown,
+            ..Default::default()
+        };
+
+        for node in &snapshot.nodes {
+            if node
+                .spec
+                .as_ref()
+                .and_then(|s| s.unschedulable)
+                .unwrap_or_default()
+            {
+                continue;
+            }
+            if let Some(allocatable) = node.status.as_ref().and_then(|s| s.allocatable.as_ref()) {
+                add_resources(&mut cluster.capacity, &to_resource_list(allocatable));
+            }
+        }
+
+        for queue in &snapshot.queues {
+            let share = QueueShare {
+                weight: queue.spec.weight,
+                capacity: queue.spec.capacity.as_ref().map(to_resource_list),
+                reclaimable: queue.spec.reclaimable,
+                ..Default::default()
+            };
+            cluster.queues.insert(queue.name_any(), share);
+        }
+
+        for priority_class in &snapshot.priority_classes {
+            cluster
+                .priority_classes
+                .insert(priority_class.name_any(), priority_class.value);
+        }
+
+        let mut owned_pods: HashMap<(String, String), Vec<&Pod>> = HashMap::new();
+        for pod in &snapshot.pods {
+            if let (Some(ns), Some(owner)) = (pod.namespace(), pod.labels().get(&labels.task_owner).cloned())
+            {
+                owned_pods.entry((ns, owner)).or_default().push(pod);
+            }
+        }
+
+        let mut jobs = snapshot.jobs.iter().collect::<Vec<_>>();
+        jobs.sort_by_key(|job| std::cmp::Reverse(job.creation_timestamp()));
+        for job in jobs {
+            let phase = job.status.as_ref().map(|s| s.phase.clone()).unwrap_or_default();
+            match phase {
+                // the jobs waiting for their dependencies aren't candidates for admission yet
+                JobStatusPhase::Pending if waits_for_dependencies(job) => (),
+                JobStatusPhase::Pending => {
+                    if let Some(share) = job.spec.queue.as_ref().and_then(|q| cluster.queues.get_mut(q)) {
+                        share.waiting = true;
+                    }
+                    cluster.pending.push(PendingJob {
+                        namespace: job.namespace().unwrap_or_default(),
+                        name: job.name_any(),
+                        queue: job.spec.queue.clone(),
+                        priority: cluster.priority(&job.spec.priority),
+                        creation_timestamp: job.creation_timestamp().map(|t| t.0),
+                        min_request: job_min_requests(&job.spec),
+                        duration: job.spec.expected_duration(),
+                    });
+                }
+                JobStatusPhase::Ready | JobStatusPhase::Running | JobStatusPhase::Terminating => {
+                    let key = (job.namespace().unwrap_or_default(), job.name_any());
+                    let pods = owned_pods.remove(&key).unwrap_or_default();
+                    let usage = JobUsage {
+                        priority: cluster.priority(&job.spec.priority),
+                        min_request: job_min_requests(&job.spec),
+                        elastic_pods: elastic_pods(job, &pods, labels),
+                        end: expected_end(job),
+                        queue: job.spec.queue.clone(),
+                        namespace: key.0,
+                        name: key.1,
+                    };
+                    cluster.allocate(&usage);
+                    cluster.jobs.push(usage);
+                }
+                _ => (),
+            }
+        }
+
+        let known = snapshot
+            .jobs
+            .iter()
+            .map(|job| (job.namespace().unwrap_or_default(), job.name_any()))
+            .collect::<HashSet<_>>();
+        let unknown_pods = owned_pods
+            .iter()
+            .filter(|(key, _)| !known.contains(key))
+            .flat_map(|(_, pods)| pods);
+        for pod in unknown_pods.filter(|pod| is_live(pod)) {
+            if let Some(spec) = &pod.spec {
+                add_resources(&mut cluster.allocated, &core_pod_requests(spec));
+            }
+        }
+
+        cluster.pending.sort_by(|a, b| {
+            b.priority
+                .cmp(&a.priority)
+                .then_with(|| a.creation_timestamp.cmp(&b.creation_timestamp))
+        });
+
+        cluster
+    }
+
+    /// Decides whether a job requesting `request` can be admitted in `queue`, following the weighted
+    /// dominant resource fairness between the queues. A queue may grow beyond its fair share only if no
+    /// other waiting queue has a lower weighted dominant share.
+    ///
+    /// Returns the reason why the job has to wait otherwise.
+    pub fn admit(&self, queue: Option<&str>, request: &ResourceList) -> Result<(), String> {
+        self.fits(queue, request)?;
+
+        let (queue, share) = match queue.and_then(|name| self.queues.get(name).map(|share| (name, share))) {
+            Some(queue) => queue,
+            None => return Ok(()),
+        };
+
+        let mut allocated = share.allocated.clone();
+        add_resources(&mut allocated, request);
+
+        let active_weight = self
+            .queues
+            .iter()
+            .filter(|(name, share)| {
+                *name == queue || share.waiting || share.allocated.values().any(|v| *v > 0.0)
+            })
+            .map(|(_, share)| share.weight.max(1) as f64)
+            .sum::<f64>();
+        // the queues of weight 0 are weighted as 1, so the fair share is always defined
+        let fair_share = share.weight.max(1) as f64 / active_weight;
+        if self.dominant_share(&allocated) <= fair_share {
+            return Ok(());
+        }
+
+        let weighted_share = self.weighted_dominant_share(share);
+        let starving = self.queues.iter().find(|(name, other)| {
+            *name != queue && other.waiting && self.weighted_dominant_share(other) < weighted_share
+        });
+        match starving {
+            Some((name, _)) => Err(format!(
+                "queue `{}` is over its fair share, queue `{}` goes first",
+                queue, name
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that the resources requested by a job are free in the cluster and within the capacity of
+    /// its queue.
+    pub fn fits(&self, queue: Option<&str>, request: &ResourceList) -> Result<(), String> {
+        for (name, value) in request {
+            let free = self.capacity.get(name).copied().unwrap_or_default()
+                - self.allocated.get(name).copied().unwrap_or_default();
+            if *value > free && !self.nodes_unknown {
+                return Err(format!("insufficient {} in the cluster", name));
+            }
+        }
+
+        let (queue, share) = match queue.and_then(|name| self.queues.get(name).map(|share| (name, share))) {
+            Some(queue) => queue,
+            None => return Ok(()),
+        };
+        if let Some(capacity) = &share.capacity {
+            let mut allocated = share.allocated.clone();
+            add_resources(&mut allocated, request);
+            // the resources without capacity aren't bounded in the queue
+            for (name, capacity) in capacity {
+                if allocated.get(name).copied().unwrap_or_default() > *capacity {
+                    return Err(format!("insufficient {} in queue `{}` capacity", name, queue));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Decides how many pods above `parallelism.min` an admitted job of `priority` may create, given the
+    /// requests of the pods in the order they are created. The pods are admitted one at a time, as the
+    /// minimum pods of a job are.
+    ///
+    /// The resources are reserved for the waiting jobs which could preempt the pods, so the preempted pods
+    /// aren't created again before the jobs they were preempted for are admitted.
+    pub fn admit_elastic_pods(&self, queue: Option<&str>, priority: i32, requests: &[ResourceList]) -> usize {
+        let job = JobUsage {
+            queue: queue.map(str::to_string),
+            priority,
+            ..Default::default()
+        };
+        let mut cluster = self.clone();
+        let preemptors = self.pending.iter().filter(|pending| {
+            pending.priority > priority
+                && self.preemptable(&job, pending.queue.as_deref())
+                && self.fits_when_empty(pending)
+        });
+        for pending in preemptors {
+            cluster.allocate(&JobUsage {
+                queue: pending.queue.clone(),
+                min_request: pending.min_request.clone(),
+                ..Default::default()
+            });
+        }
+        for (admitted, request) in requests.iter().enumerate() {
+            if cluster.admit(queue, request).is_err() {
+                return admitted;
+            }
+            cluster.allocate(&JobUsage {
+                queue: queue.map(str::to_string),
+                min_request: request.clone(),
+                ..Default::default()
+            });
+        }
+        requests.len()
+    }
+
+    /// Decides whether a pending job may be admitted while a job ahead of it waits for resources.
+    ///
+    /// The resources are reserved for the first job blocked on resources the job competes for, i.e. short
+    /// of cluster resources, or of the capacity of the queue of the job, from the time the admitted jobs are
+    /// expected to release enough of them. The job is backfilled only if it completes before that time, or
+    /// if it still leaves enough resources for the blocked job at that time. Nothing is reserved while that
+    /// time is unknown, as the admitted jobs have an unknown duration.
+    pub fn backfill(&self, namespace: &str, name: &str, now: DateTime<Utc>) -> Result<(), String> {
+        let idx = match self
+            .pending
+            .iter()
+            .position(|job| job.namespace == namespace && job.name == name)
+        {
+            Some(idx) => idx,
+            None => return Ok(()),
+        };
+        let job = &self.pending[idx];
+        let head = self.pending[..idx].iter().find(|head| {
+            // a job blocked on the capacity of another queue doesn't compete with the job
+            let blocked = self.fits(None, &head.min_request).is_err()
+                || (head.queue == job.queue && self.fits(head.queue.as_deref(), &head.min_request).is_err());
+            blocked && self.fits_when_empty(head)
+        });
+        let head = match head {
+            Some(head) => head,
+            None => return Ok(()),
+        };
+
+        // release the admitted jobs in the order they complete, until the blocked job fits
+        let mut admitted = self
+            .jobs
+            .iter()
+            .filter(|usage| usage.end.is_some())
+            .collect::<Vec<_>>();
+        admitted.sort_by_key(|usage| usage.end);
+        let mut shadow = self.clone();
+        let mut start = None;
+        for usage in admitted {
+            shadow.release_job(usage);
+            if shadow.fits(head.queue.as_deref(), &head.min_request).is_ok() {
+                start = usage.end;
+                break;
+            }
+        }
+
+        let start = match start {
+            Some(start) => start,
+            None => return Ok(()),
+        };
+        if let Some(duration) = job.duration {
+            if now + Duration::seconds(duration as i64) <= start {
+                return Ok(());
+            }
+        }
+
+        shadow.allocate(&JobUsage {
+            queue: head.queue.clone(),
+            min_request: head.min_request.clone(),
+            ..Default::default()
+        });
+        shadow.fits(job.queue.as_deref(), &job.min_request).map_err(|_| {
+            format!(
+                "resources are reserved for job `{}/{}`, expected to start at {}",
+                head.namespace,
+                head.name,
+                start.to_rfc3339()
+            )
+        })
+    }
+
+    /// Resolves the priority of a job, jobs without priority or with an unknown priority class have the
+    /// lowest priority.
+    pub fn priority(&self, priority: &Option<Priority>) -> i32 {
+        match priority {
+            Some(Priority::Value(value)) => i32::try_from(*value).unwrap_or(i32::MAX),
+            Some(Priority::Name(name)) => self.priority_classes.get(name).copied().unwrap_or_default(),
+            None => 0,
+        }
+    }
+
+    /// Adds the resources of an admitted job to the cluster and its queue.
+    pub fn allocate(&mut self, usage: &JobUsage) {
+        let requests = std::iter::once(&usage.min_request).chain(usage.elastic_pods.iter().map(|(_, r)| r));
+        for request in requests {
+            add_resources(&mut self.allocated, request);
+            if let Some(share) = usage.queue.as_ref().and_then(|q| self.queues.get_mut(q)) {
+                add_resources(&mut share.allocated, request);
+            }
+        }
+    }
+
+    /// Removes released resources from the cluster and the given queue.
+    pub fn release(&mut self, queue: Option<&str>, request: &ResourceList) {
+        for (name, value) in request {
+            *self.allocated.entry(name.clone()).or_default() -= value;
+        }
+        if let Some(share) = queue.and_then(|q| self.queues.get_mut(q)) {
+            for (name, value) in request {
+                *share.allocated.entry(name.clone()).or_default() -= value;
+            }
+        }
+    }
+
+    /// Removes the resources of a completed job from the cluster and its queue.
+    pub fn release_job(&mut self, usage: &JobUsage) {
+        let requests = std::iter::once(&usage.min_request).chain(usage.elastic_pods.iter().map(|(_, r)| r));
+        for request in requests {
+            self.release(usage.queue.as_deref(), request);
+        }
+    }
+
+    /// The highest ratio of any resource allocated over the cluster capacity.
+    pub fn dominant_share(&self, allocated: &ResourceList) -> f64 {
+        allocated
+            .iter()
+            .filter_map(|(name, value)| {
+                let capacity = self.capacity.get(name).copied().unwrap_or_default();
+                (capacity > 0.0).then(|| value / capacity)
+            })
+            .fold(0.0, f64::max)
+    }
+
+    /// Whether a job would fit if no job was admitted, jobs which never fit don't reserve resources.
+    fn fits_when_empty(&self, job: &PendingJob) -> bool {
+        let mut empty = self.clone();
+        empty.allocated.clear();
+        for share in empty.queues.values_mut() {
+            share.allocated.clear();
+        }
+        empty.fits(job.queue.as_deref(), &job.min_request).is_ok()
+    }
+
+    fn weighted_dominant_share(&self, share: &QueueShare) -> f64 {
+        self.dominant_share(&share.allocated) / share.weight.max(1) as f64
+    }
+}
+
+/// Whether a job has dependencies which aren't known to be completed yet.
+fn waits_for_dependencies(job: &Job) -> bool {
+    let completed = job
+        .status
+        .as_ref()
+        .and_then(|s| s.condition(WAITING_FOR_DEPENDENCIES_CONDITION))
+        .map(|c| c.status == "False")
+        .unwrap_or_default();
+    !job.spec.depends_on.is_empty() && !completed
+}
+
+/// When an admitted job is expected to complete, from the time it was admitted and its expected running
+/// time.
+fn expected_end(job: &Job) -> Option<DateTime<Utc>> {
+    let status = job.status.as_ref();
+    let admitted = status
+        .and_then(|s| s.condition(ADMITTED_CONDITION))
+        .filter(|c| c.status == "True")
+        .and_then(|c| c.last_transition_time.clone())
+        .or_else(|| status.and_then(|s| s.start_time.clone()))
+        .or_else(|| job.creation_timestamp())?;
+    let duration = job.spec.expected_duration()?;
+    Some(admitted.0 + Duration::seconds(duration as i64))
+}
+
+/// Whether a pod still uses the resources it requests.
+fn is_live(pod: &Pod) -> bool {
+    let phase = pod.status.as_ref().and_then(|s| s.phase.as_deref());
+    pod.metadata.deletion_timestamp.is_none() && !matches!(phase, Some("Succeeded") | Some("Failed"))
+}
+
+/// The live pods of a job whose replica index is at least `parallelism.min`. The pods of a sweep job are
+/// indexes of its parameter matrix, which are all requested by the job.
+fn elastic_pods(job: &Job, pods: &[&Pod], labels: &LabelNames) -> Vec<(String, ResourceList)> {
+    if job.spec.sweep.is_some() {
+        return vec![];
+    }
+    pods.iter()
+        .filter(|pod| is_live(pod))
+        .filter_map(|pod| {
+            let task_name = pod.labels().get(&labels.task_name)?;
+            let task = job.spec.tasks.iter().find(|t| &t.name == task_name)?;
+            (replica_index(pod, labels)? >= task.parallelism.min)
+                .then(|| (pod.name_any(), pod_requests(&task.template.spec)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Cluster, JobUsage, PendingJob, QueueShare};
+    use crate::snapshot::Snapshot;
+    use habitat_api::{batch::LabelNames, resource::ResourceList};
+    use k8s_openapi::chrono::{Duration, Utc};
+
+    fn resources(cpu: f64, memory: f64) -> ResourceList {
+        [("cpu".to_string(), cpu), ("memory".to_string(), memory)]
+            .into_iter()
+            .collect()
+    }
+
+    fn cluster(a: QueueShare, b: QueueShare) -> Cluster {
+        let mut allocated = a.allocated.clone();
+        habitat_api::resource::add_resources(&mut allocated, &b.allocated);
+        Cluster {
+            capacity: resources(10.0, 100.0),
+            allocated,
+            queues: [("a".to_string(), a), ("b".to_string(), b)].into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_admit_within_fair_share() {
+        let cluster = cluster(
+            QueueShare {
+                weight: 1,
+                waiting: true,
+                ..Default::default()
+            },
+            QueueShare {
+                weight: 1,
+                allocated: resources(5.0, 10.0),
+                waiting: true,
+                ..Default::default()
+            },
+        );
+        assert!(cluster.admit(Some("a"), &resources(4.0, 10.0)).is_ok());
+        assert!(cluster.admit(None, &resources(4.0, 10.0)).is_ok());
+        assert!(cluster.admit(Some("a"), &resources(6.0, 10.0)).is_err());
+    }
+
+    #[test]
+    fn test_admit_over_fair_share() {
+        let cluster = cluster(
+            QueueShare {
+                weight: 1,
+                allocated: resources(1.0, 10.0),
+                waiting: true,
+                ..Default::default()
+            },
+            QueueShare {
+                weight: 1,
+                allocated: resources(4.0, 10.0),
+                waiting: true,
+                ..Default::default()
+            },
+        );
+        // `b` would exceed its half, and `a` is waiting with a lower share
+        assert_eq!(
+            cluster.admit(Some("b"), &resources(2.0, 0.0)).unwrap_err(),
+            "queue `b` is over its fair share, queue `a` goes first"
+        );
+        // `a` would also exceed its half, but no waiting queue has a lower share
+        assert!(cluster.admit(Some("a"), &resources(5.0, 0.0)).is_ok());
+    }
+
+    #[test]
+    fn test_admit_weighted() {
+        let cluster = cluster(
+            QueueShare {
+                weight: 3,
+                allocated: resources(6.0, 0.0),
+                waiting: true,
+                ..Default::default()
+            },
+            QueueShare {
+                weight: 1,
+                allocated: resources(1.0, 0.0),
+                waiting: true,
+                ..Default::default()
+            },
+        );
+        // `a` deserves 3/4 of the cluster
+        assert!(cluster.admit(Some("a"), &resources(1.5, 0.0)).is_ok());
+        assert!(cluster.admit(Some("a"), &resources(2.0, 0.0)).is_err());
+    }
+
+    #[test]
+    fn test_admit_zero_weights() {
+        let cluster = cluster(
+            QueueShare {
+                weight: 0,
+                waiting: true,
+                ..Default::default()
+            },
+            QueueShare {
+                weight: 0,
+                allocated: resources(5.0, 10.0),
+                waiting: true,
+                ..Default::default()
+            },
+        );
+        assert!(cluster.admit(Some("a"), &resources(4.0, 10.0)).is_ok());
+        assert_eq!(
+            cluster.admit(Some("b"), &resources(1.0, 10.0)).unwrap_err(),
+            "queue `b` is over its fair share, queue `a` goes first"
+        );
+    }
+
+    #[test]
+    fn test_admit_queue_capacity() {
+        let cluster = cluster(
+            QueueShare {
+                weight: 1,
+                capacity: Some(resources(2.0, 100.0)),
+                ..Default::default()
+            },
+            QueueShare {
+                weight: 1,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            cluster.admit(Some("a"), &resources(3.0, 0.0)).unwrap_err(),
+            "insufficient cpu in queue `a` capacity"
+        );
+    }
+
+    #[test]
+    fn test_admit_partial_queue_capacity() {
+        let cpu_only = [("cpu".to_string(), 2.0)].into_iter().collect();
+        let cluster = cluster(
+            QueueShare {
+                weight: 1,
+                capacity: Some(cpu_only),
+                ..Default::default()
+            },
+            QueueShare {
+                weight: 1,
+                ..Default::default()
+            },
+        );
+        assert!(cluster.admit(Some("a"), &resources(2.0, 10.0)).is_ok());
+        assert_eq!(
+            cluster.admit(Some("a"), &resources(3.0, 10.0)).unwrap_err(),
+            "insufficient cpu in queue `a` capacity"
+        );
+    }
+
+    #[test]
+    fn test_admit_without_nodes() {
+        let mut cluster = cluster(
+            QueueShare {
+                weight: 1,
+                capacity: Some(resources(2.0, 100.0)),
+                ..Default::default()
+            },
+            QueueShare {
+                weight: 1,
+                ..Default::default()
+            },
+        );
+        cluster.capacity.clear();
+        assert!(cluster.admit(None, &resources(1.0, 10.0)).is_err());
+
+        // the capacity of the cluster is unknown, but the one of the queues isn't
+        cluster.nodes_unknown = true;
+        assert!(cluster.admit(None, &resources(20.0, 10.0)).is_ok());
+        assert!(cluster.admit(Some("b"), &resources(20.0, 10.0)).is_ok());
+        assert!(cluster.admit(Some("a"), &resources(3.0, 10.0)).is_err());
+    }
+
+    #[test]
+    fn test_admit_elastic_pods() {
+        let cluster = cluster(
+            QueueShare {
+                weight: 1,
+                capacity: Some(resources(4.0, 100.0)),
+                allocated: resources(1.0, 10.0),
+                ..Default::default()
+            },
+            QueueShare {
+                weight: 1,
+                ..Default::default()
+            },
+        );
+        let requests = vec![resources(1.0, 10.0); 5];
+        // the pods are admitted up to the capacity of the queue
+        assert_eq!(cluster.admit_elastic_pods(Some("a"), 0, &requests), 3);
+        assert_eq!(cluster.admit_elastic_pods(None, 0, &requests), 5);
+    }
+
+    #[test]
+    fn test_admit_elastic_pods_after_preemptors() {
+        let pending = |queue: &str, priority: i32| PendingJob {
+            queue: Some(queue.to_string()),
+            priority,
+            min_request: resources(4.0, 0.0),
+            ..Default::default()
+        };
+        let mut cluster = cluster(
+            QueueShare {
+                weight: 1,
+                ..Default::default()
+            },
+            QueueShare {
+                weight: 1,
+                ..Default::default()
+            },
+        );
+        cluster.pending = vec![pending("a", 10), pending("b", 10), pending("a", 1)];
+        let requests = vec![resources(1.0, 0.0); 10];
+        // only the higher priority job of the same queue may preempt the pods
+        assert_eq!(cluster.admit_elastic_pods(Some("a"), 5, &requests), 6);
+    }
+
+    #[test]
+    fn test_backfill() {
+        let now = Utc::now();
+        let pending = |name: &str, cpu: f64, duration: Option<u64>| PendingJob {
+            namespace: "default".to_string(),
+            name: name.to_string(),
+            min_request: resources(cpu, 0.0),
+            duration,
+            ..Default::default()
+        };
+        let mut cluster = Cluster {
+            capacity: resources(10.0, 100.0),
+            pending: vec![
+                pending("large", 9.0, None),
+                pending("short", 2.0, Some(1800)),
+                pending("long", 2.0, Some(7200)),
+                pending("unknown", 2.0, None),
+            ],
+            ..Default::default()
+        };
+        let running = JobUsage {
+            min_request: resources(8.0, 0.0),
+            end: Some(now + Duration::hours(1)),
+            ..Default::default()
+        };
+        cluster.allocate(&running);
+        cluster.jobs.push(running);
+
+        assert!(cluster.admit(None, &resources(9.0, 0.0)).is_err());
+        assert_eq!(cluster.backfill("default", "large", now), Ok(()));
+        assert_eq!(cluster.backfill("default", "short", now), Ok(()));
+        assert!(cluster
+            .backfill("default", "long", now)
+            .unwrap_err()
+            .starts_with("resources are reserved for job `default/large`, expected to start at"));
+        assert!(cluster.backfill("default", "unknown", now).is_err());
+
+        // nothing is reserved while the admitted jobs have an unknown duration
+        cluster.jobs[0].end = None;
+        assert_eq!(cluster.backfill("default", "long", now), Ok(()));
+        assert_eq!(cluster.backfill("default", "unknown", now), Ok(()));
+    }
+
+    #[test]
+    fn test_backfill_other_queue() {
+        let now = Utc::now();
+        let pending = |name: &str, queue: &str, cpu: f64| PendingJob {
+            namespace: "default".to_string(),
+            name: name.to_string(),
+            queue: Some(queue.to_string()),
+            min_request: resources(cpu, 0.0),
+            duration: Some(7200),
+            ..Default::default()
+        };
+        let mut cluster = cluster(
+            QueueShare {
+                weight: 1,
+                capacity: Some(resources(4.0, 100.0)),
+                ..Default::default()
+            },
+            QueueShare {
+                weight: 1,
+                ..Default::default()
+            },
+        );
+        cluster.pending = vec![
+            pending("large", "a", 4.0),
+            pending("other", "b", 2.0),
+            pending("same", "a", 1.0),
+        ];
+        let running = JobUsage {
+            queue: Some("a".to_string()),
+            min_request: resources(3.0, 0.0),
+            end: Some(now + Duration::hours(1)),
+            ..Default::default()
+        };
+        cluster.allocate(&running);
+        cluster.jobs.push(running);
+
+        // `large` is only short of the capacity of queue `a`, which `b` doesn't compete for
+        assert_eq!(cluster.backfill("default", "other", now), Ok(()));
+        assert!(cluster
+            .backfill("default", "same", now)
+            .unwrap_err()
+            .starts_with("resources are reserved for job `default/large`"));
+    }
+
+    #[test]
+    fn test_pods_of_unknown_jobs() {
+        // a namespace-scoped controller doesn't list the jobs of the other namespaces
+        let snapshot = Snapshot::from_yaml(
+            r#"
+apiVersion: v1
+kind: Node
+metadata:
+  name: node-1
+status:
+  allocatable: {cpu: "4"}
+---
+apiVersion: v1
+kind: List
+items:
+- apiVersion: v1
+  kind: Pod
+  metadata:
+    name: train-worker-0
+    namespace: other
+    labels: {habitat-task-owner: train}
+  spec:
+    containers:
+    - name: main
+      resources: {requests: {cpu: "3"}}
+  status: {phase: Running}
+- apiVersion: v1
+  kind: Pod
+  metadata:
+    name: done-worker-0
+    namespace: other
+    labels: {habitat-task-owner: done}
+  spec:
+    containers:
+    - name: main
+      resources: {requests: {cpu: "3"}}
+  status: {phase: Succeeded}
+"#,
+        )
+        .unwrap();
+
+        let cluster = Cluster::new(&snapshot, &LabelNames::default());
+        assert_eq!(cluster.allocated["cpu"], 3.0);
+        assert_eq!(
+            cluster.admit(None, &resources(2.0, 0.0)).unwrap_err(),
+            "insufficient cpu in the cluster"
+        );
+    }
+
+    #[test]
+    fn test_jobs_waiting_for_dependencies() {
+        let snapshot = Snapshot::from_yaml(
+            r#"
+apiVersion: batch.habitat/v1beta1
+kind: Job
+metadata: {name: waiting, namespace: default}
+spec:
+  dependsOn: [{name: preprocess}]
+  tasks: []
+status:
+  phase: Pending
+  pending: 0
+  running: 0
+  terminating: 0
+  succeeded: 0
+  failed: 0
+  conditions: [{type: WaitingForDependencies, status: "True", reason: Waiting}]
+---
+apiVersion: batch.habitat/v1beta1
+kind: Job
+metadata: {name: unchecked, namespace: default}
+spec:
+  dependsOn: [{name: preprocess}]
+  tasks: []
+---
+apiVersion: batch.habitat/v1beta1
+kind: Job
+metadata: {name: ready, namespace: default}
+spec:
+  dependsOn: [{name: preprocess}]
+  tasks: []
+status:
+  phase: Pending
+  pending: 0
+  running: 0
+  terminating: 0
+  succeeded: 0
+  failed: 0
+  conditions: [{type: WaitingForDependencies, status: "False", reason: DependenciesCompleted}]
+---
+apiVersion: batch.habitat/v1beta1
+kind: Job
+metadata: {name: independent, namespace: default}
+spec:
+  tasks: []
+"#,
+        )
+        .unwrap();
+
+        // the jobs waiting for their dependencies don't hold the resources of the queue for backfilling
+        let cluster = Cluster::new(&snapshot, &LabelNames::default());
+        let mut pending = cluster
+            .pending
+            .iter()
+            .map(|job| job.name.as_str())
+            .collect::<Vec<_>>();
+        pending.sort_unstable();
+        assert_eq!(pending, vec!["independent", "ready"]);
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::BTreeMap;
+
+use habitat_api::resource::{add_resources, core_pod_requests, to_resource_list, ResourceList};
+use k8s_openapi::api::core::v1::{Node, Pod};
+use kube::ResourceExt;
+
+use crate::plugins::{
+    BinPack, NodeResourcesFit, NodeSelector, NodeUnschedulable, Spread, Strategy, TaintToleration,
+};
+
+/// The highest score a plugin gives to a node
+pub const MAX_NODE_SCORE: i64 = 100;
+
+/// A node and the resources requested by the pods bound to it
+#[derive(Clone, Debug)]
+pub struct NodeInfo {
+    pub node: Node,
+    /// The allocatable resources of the node, including the number of `pods`
+    pub allocatable: ResourceList,
+    /// The resources requested by the pods bound to the node, including the number of `pods`
+    pub requested: ResourceList,
+}
+
+impl NodeInfo {
+    pub fn new(node: Node) -> Self {
+        let allocatable = node
+            .status
+            .as_ref()
+            .and_then(|s| s.allocatable.as_ref())
+            .map(to_resource_list)
+            .unwrap_or_default();
+        Self {
+            node,
+            allocatable,
+            requested: ResourceList::new(),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.node.name_any()
+    }
+
+    /// Accounts the resources of a pod bound to the node.
+    pub fn add_pod(&mut self, pod: &PodInfo) {
+        add_resources(&mut self.requested, &pod.requests);
+    }
+
+    /// The amount of a resource which is not requested yet.
+    pub fn free(&self, resource: &str) -> f64 {
+        self.allocatable.get(resource).copied().unwrap_or_default()
+            - self.requested.get(resource).copied().unwrap_or_default()
+    }
+}
+
+/// A pod to schedule
+#[derive(Clone, Debug)]
+pub struct PodInfo {
+    pub pod: Pod,
+    /// The resources requested by the pod, including one of `pods`
+    pub requests: ResourceList,
+}
+
+impl PodInfo {
+    pub fn new(pod: Pod) -> Self {
+        let mut requests = pod.spec.as_ref().map(core_pod_requests).unwrap_or_default();
+        requests.insert("pods".to_string(), 1.0);
+        Self { pod, requests }
+    }
+
+    pub fn name(&self) -> String {
+        self.pod.name_any()
+    }
+}
+
+/// An extension point of the scheduler. A plugin filters out the nodes a pod can't run on, and scores the
+/// remaining ones.
+pub trait Plugin: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Returns why the pod can't run on the node, if it can't.
+    fn filter(&self, _pod: &PodInfo, _node: &NodeInfo) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Scores a feasible node from 0 to [`MAX_NODE_SCORE`], the higher the better.
+    fn score(&self, _pod: &PodInfo, _node: &NodeInfo) -> i64 {
+        0
+    }
+
+    /// The weight of the plugin scores among all the plugins.
+    fn weight(&self) -> i64 {
+        1
+    }
+}
+
+/// The node chosen for a pod
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Placement {
+    pub pod: String,
+    pub node: String,
+}
+
+/// Runs the plugins to place pods on nodes.
+#[derive(Default)]
+pub struct Framework {
+    plugins: Vec<Box<dyn Plugin>>,
+}
+
+impl Framework {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The built-in predicates, and the scoring of the given strategy.
+    pub fn with_default_plugins(strategy: Strategy) -> Self {
+        let framework = Self::new()
+            .with_plugin(NodeUnschedulable)
+            .with_plugin(NodeSelector)
+            .with_plugin(TaintToleration)
+            .with_plugin(NodeResourcesFit);
+        match strategy {
+            Strategy::BinPack => framework.with_plugin(BinPack),
+            Strategy::Spread => framework.with_plugin(Spread),
+        }
+    }
+
+    pub fn with_plugin(mut self, plugin: impl Plugin + 'static) -> Self {
+        self.plugins.push(Box::new(plugin));
+        self
+    }
+
+    pub fn plugins(&self) -> impl Iterator<Item = &dyn Plugin> {
+        self.plugins.iter().map(|p| p.as_ref())
+    }
+
+    /// Finds the best node for a pod.
+    ///
+    /// Returns why no node fits otherwise, e.g. `0/3 nodes are available: 1 Insufficient cpu, 2 node(s)
+    /// were unschedulable.`
+    pub fn schedule(&self, pod: &PodInfo, nodes: &[NodeInfo]) -> Result<usize, String> {
+        let mut reasons = BTreeMap::<String, usize>::new();
+        let mut best: Option<(usize, i64)> = None;
+
+        for (idx, node) in nodes.iter().enumerate() {
+            if let Err(reason) = self.plugins.iter().try_for_each(|p| p.filter(pod, node)) {
+                *reasons.entry(reason).or_default() += 1;
+                continue;
+            }
+            let score = self
+                .plugins
+                .iter()
+                .map(|p| p.score(pod, node) * p.weight())
+                .sum::<i64>();
+            // the first node wins among the ones with the same score
+            if best.map(|(_, best)| score > best).unwrap_or(true) {
+                best = Some((idx, score));
+            }
+        }
+
+        best.map(|(idx, _)| idx).ok_or_else(|| {
+            let reasons = reasons
+                .into_iter()
+                .map(|(reason, count)| format!("{} {}", count, reason))
+                .collect::<Vec<_>>();
+            format!("0/{} nodes are available: {}.", nodes.len(), reasons.join(", "))
+        })
+    }
+
+    /// Finds a node for every pod of a gang, so that they can all run at the same time. Nothing is placed if
+    /// any of the pods doesn't fit.
+    ///
+    /// On success, the resources of the gang are accounted on the chosen nodes.
+    pub fn schedule_gang(&self, pods: &[PodInfo], nodes: &mut [NodeInfo]) -> Result<Vec<Placement>, String> {
+        let mut assumed = nodes.to_vec();
+        let mut placements = vec![];
+
+        for pod in pods {
+            let idx = self
+                .schedule(pod, &assumed)
+                .map_err(|reason| format!("pod {}: {}", pod.name(), reason))?;
+            assumed[idx].add_pod(pod);
+            placements.push(Placement {
+                pod: pod.name(),
+                node: assumed[idx].name(),
+            });
+        }
+
+        nodes.clone_from_slice(&assumed);
+        Ok(placements)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Framework, NodeInfo, Placement, PodInfo};
+    use crate::plugins::{NodeResourcesFit, Strategy};
+    use k8s_openapi::api::core::v1::{Node, Pod};
+
+    fn node(name: &str, cpu: &str) -> NodeInfo {
+        NodeInfo::new(
+            serde_json::from_value::<Node>(serde_json::json!({
+                "metadata": {"name": name},
+                "status": {"allocatable": {"cpu": cpu, "memory": "8Gi", "pods": "110"}}
+            }))
+            .unwrap(),
+        )
+    }
+
+    fn pod(name: &str, cpu: &str) -> PodInfo {
+        PodInfo::new(
+            serde_json::from_value::<Pod>(serde_json::json!({
+                "metadata": {"name": name},
+                "spec": {"containers": [{"name": "main", "resources": {"requests": {"cpu": cpu}}}]}
+            }))
+            .unwrap(),
+        )
+    }
+
+    fn placements(placements: &[(&str, &str)]) -> Vec<Placement> {
+        placements
+            .iter()
+            .map(|(pod, node)| Placement {
+                pod: pod.to_string(),
+                node: node.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_schedule_gang_bin_packing() {
+        let framework = Framework::with_default_plugins(Strategy::BinPack);
+        let mut nodes = vec![node("a", "4"), node("b", "8")];
+        let pods = vec![pod("worker-0", "2"), pod("worker-1", "2"), pod("worker-2", "2")];
+
+        assert_eq!(
+            framework.schedule_gang(&pods, &mut nodes),
+            Ok(placements(&[
+                ("worker-0", "a"),
+                ("worker-1", "a"),
+                ("worker-2", "b")
+            ]))
+        );
+        assert_eq!(nodes[0].free("cpu"), 0.0);
+        assert_eq!(nodes[1].free("cpu"), 6.0);
+    }
+
+    #[test]
+    fn test_schedule_gang_spread() {
+        let framework = Framework::with_default_plugins(Strategy::Spread);
+        let mut nodes = vec![node("a", "4"), node("b", "4")];
+        let pods = vec![pod("worker-0", "1"), pod("worker-1", "1")];
+
+        assert_eq!(
+            framework.schedule_gang(&pods, &mut nodes),
+            Ok(placements(&[("worker-0", "a"), ("worker-1", "b")]))
+        );
+    }
+
+    #[test]
+    fn test_schedule_gang_all_or_nothing() {
+        let framework = Framework::new().with_plugin(NodeResourcesFit);
+        let mut nodes = vec![node("a", "4"), node("b", "2")];
+        let pods = vec![pod("worker-0", "3"), pod("worker-1", "3")];
+
+        assert_eq!(
+            framework.schedule_gang(&pods, &mut nodes),
+            Err("pod worker-1: 0/2 nodes are available: 2 Insufficient cpu.".to_string())
+        );
+        // nothing is accounted when the gang doesn't fit
+        assert_eq!(nodes[0].free("cpu"), 4.0);
+    }
+}
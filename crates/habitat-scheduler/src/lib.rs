@@ -0,0 +1,6 @@
+pub mod fairshare;
+pub mod framework;
+pub mod plugins;
+pub mod preempt;
+pub mod simulate;
+pub mod snapshot;
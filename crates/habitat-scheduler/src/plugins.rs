@@ -0,0 +1,237 @@
+use std::str::FromStr;
+
+use k8s_openapi::api::core::v1::{Taint, Toleration};
+
+use crate::framework::{NodeInfo, Plugin, PodInfo, MAX_NODE_SCORE};
+
+/// The resources the scoring strategies balance
+const SCORED_RESOURCES: [&str; 2] = ["cpu", "memory"];
+
+/// How the pods are placed among the feasible nodes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// Fill the most allocated nodes first, keeping whole nodes free for large gangs
+    BinPack,
+    /// Place the pods on the least allocated nodes
+    Spread,
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "binpack" => Ok(Self::BinPack),
+            "spread" => Ok(Self::Spread),
+            _ => Err(format!(
+                "unknown strategy `{}`, expected one of binpack, spread",
+                s
+            )),
+        }
+    }
+}
+
+/// Filters out the nodes marked as unschedulable.
+pub struct NodeUnschedulable;
+
+impl Plugin for NodeUnschedulable {
+    fn name(&self) -> &'static str {
+        "NodeUnschedulable"
+    }
+
+    fn filter(&self, pod: &PodInfo, node: &NodeInfo) -> Result<(), String> {
+        let unschedulable = node
+            .node
+            .spec
+            .as_ref()
+            .and_then(|s| s.unschedulable)
+            .unwrap_or_default();
+        let taint = Taint {
+            key: "node.kubernetes.io/unschedulable".to_string(),
+            effect: "NoSchedule".to_string(),
+            ..Default::default()
+        };
+        if unschedulable && !tolerations(pod).iter().any(|t| tolerates(t, &taint)) {
+            return Err("node(s) were unschedulable".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Filters out the nodes which don't match the node selector of the pod.
+pub struct NodeSelector;
+
+impl Plugin for NodeSelector {
+    fn name(&self) -> &'static str {
+        "NodeSelector"
+    }
+
+    fn filter(&self, pod: &PodInfo, node: &NodeInfo) -> Result<(), String> {
+        let selector = pod.pod.spec.as_ref().and_then(|s| s.node_selector.as_ref());
+        let labels = node.node.metadata.labels.as_ref();
+        let matches = selector
+            .into_iter()
+            .flatten()
+            .all(|(key, value)| labels.and_then(|l| l.get(key)) == Some(value));
+        if !matches {
+            return Err("node(s) didn't match Pod's node selector".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Filters out the nodes with a `NoSchedule` or `NoExecute` taint the pod doesn't tolerate.
+pub struct TaintToleration;
+
+impl Plugin for TaintToleration {
+    fn name(&self) -> &'static str {
+        "TaintToleration"
+    }
+
+    fn filter(&self, pod: &PodInfo, node: &NodeInfo) -> Result<(), String> {
+        let tolerations = tolerations(pod);
+        let taints = node.node.spec.as_ref().and_then(|s| s.taints.as_ref());
+        for taint in taints.into_iter().flatten() {
+            if taint.effect == "PreferNoSchedule" || tolerations.iter().any(|t| tolerates(t, taint)) {
+                continue;
+            }
+            return Err(format!(
+                "node(s) had untolerated taint {{{}: {}}}",
+                taint.key,
+                taint.value.as_deref().unwrap_or_default()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Filters out the nodes without enough free resources for the pod.
+pub struct NodeResourcesFit;
+
+impl Plugin for NodeResourcesFit {
+    fn name(&self) -> &'static str {
+        "NodeResourcesFit"
+    }
+
+    fn filter(&self, pod: &PodInfo, node: &NodeInfo) -> Result<(), String> {
+        for (resource, request) in &pod.requests {
+            if *request > 0.0 && *request > node.free(resource) {
+                return Err(format!("Insufficient {}", resource));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Favors the most allocated nodes.
+pub struct BinPack;
+
+impl Plugin for BinPack {
+    fn name(&self) -> &'static str {
+        "BinPack"
+    }
+
+    fn score(&self, pod: &PodInfo, node: &NodeInfo) -> i64 {
+        allocation_score(pod, node)
+    }
+}
+
+/// Favors the least allocated nodes.
+pub struct Spread;
+
+impl Plugin for Spread {
+    fn name(&self) -> &'static str {
+        "Spread"
+    }
+
+    fn score(&self, pod: &PodInfo, node: &NodeInfo) -> i64 {
+        MAX_NODE_SCORE - allocation_score(pod, node)
+    }
+}
+
+/// Scores how much of the node would be allocated once the pod is placed on it.
+fn allocation_score(pod: &PodInfo, node: &NodeInfo) -> i64 {
+    let ratios = SCORED_RESOURCES
+        .iter()
+        .filter_map(|resource| {
+            let allocatable = node.allocatable.get(*resource).copied().filter(|a| *a > 0.0)?;
+            let requested = node.requested.get(*resource).copied().unwrap_or_default()
+                + pod.requests.get(*resource).copied().unwrap_or_default();
+            Some((requested / allocatable).min(1.0))
+        })
+        .collect::<Vec<_>>();
+    if ratios.is_empty() {
+        return 0;
+    }
+    (ratios.iter().sum::<f64>() / ratios.len() as f64 * MAX_NODE_SCORE as f64) as i64
+}
+
+fn tolerations(pod: &PodInfo) -> &[Toleration] {
+    pod.pod
+        .spec
+        .as_ref()
+        .and_then(|s| s.tolerations.as_deref())
+        .unwrap_or_default()
+}
+
+fn tolerates(toleration: &Toleration, taint: &Taint) -> bool {
+    if toleration
+        .effect
+        .as_deref()
+        .map(|e| !e.is_empty() && e != taint.effect)
+        .unwrap_or_default()
+    {
+        return false;
+    }
+    match toleration.key.as_deref() {
+        // an empty key with operator Exists tolerates everything
+        None | Some("") => toleration.operator.as_deref() == Some("Exists"),
+        Some(key) if key != taint.key => false,
+        Some(_) => match toleration.operator.as_deref() {
+            Some("Exists") => true,
+            _ => toleration.value == taint.value,
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::tolerates;
+    use k8s_openapi::api::core::v1::{Taint, Toleration};
+
+    #[test]
+    fn test_tolerates() {
+        let taint = Taint {
+            key: "gpu".to_string(),
+            value: Some("a100".to_string()),
+            effect: "NoSchedule".to_string(),
+            ..Default::default()
+        };
+        let toleration =
+            |key: Option<&str>, operator: &str, value: Option<&str>, effect: Option<&str>| Toleration {
+                key: key.map(String::from),
+                operator: Some(operator.to_string()),
+                value: value.map(String::from),
+                effect: effect.map(String::from),
+                ..Default::default()
+            };
+
+        assert!(tolerates(
+            &toleration(Some("gpu"), "Equal", Some("a100"), None),
+            &taint
+        ));
+        assert!(tolerates(
+            &toleration(Some("gpu"), "Exists", None, Some("NoSchedule")),
+            &taint
+        ));
+        assert!(tolerates(&toleration(None, "Exists", None, None), &taint));
+        assert!(!tolerates(
+            &toleration(Some("gpu"), "Equal", Some("v100"), None),
+            &taint
+        ));
+        assert!(!tolerates(
+            &toleration(Some("gpu"), "Exists", None, Some("NoExecute")),
+            &taint
+        ));
+    }
+}
@@ -0,0 +1,177 @@
+use habitat_api::resource::ResourceList;
+
+use crate::fairshare::{Cluster, JobUsage};
+
+/// What to preempt from a lower priority job
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Preemption {
+    /// Terminate the pods of the job above `parallelism.min`
+    ElasticPods {
+        namespace: String,
+        job: String,
+        pods: Vec<String>,
+    },
+    /// Terminate all the pods of the job, and put it back to `Pending`
+    Job { namespace: String, job: String },
+}
+
+impl Cluster {
+    /// Plans the preemptions which let a job of `priority` requesting `request` be admitted in `queue`.
+    ///
+    /// The victims are the admitted jobs of lower priority in the same queue or in reclaimable queues, the
+    /// lowest priority and most recent ones first. Their elastic pods are preempted before any whole job.
+    /// Returns `None` if even preempting all of them isn't enough.
+    pub fn plan_preemption(
+        &self,
+        queue: Option<&str>,
+        request: &ResourceList,
+        priority: i32,
+    ) -> Option<Vec<Preemption>> {
+        let mut victims = self
+            .jobs
+            .iter()
+            .filter(|job| job.priority < priority && self.preemptable(job, queue))
+            .collect::<Vec<_>>();
+        // stable sort keeps the most recent jobs first for the same priority
+        victims.sort_by_key(|job| job.priority);
+
+        let mut cluster = self.clone();
+        let mut plan = vec![];
+
+        for victim in &victims {
+            if victim.elastic_pods.is_empty() {
+                continue;
+            }
+            let mut pods = vec![];
+            for (pod, pod_request) in &victim.elastic_pods {
+                cluster.release(victim.queue.as_deref(), pod_request);
+                pods.push(pod.clone());
+                if cluster.admit(queue, request).is_ok() {
+                    break;
+                }
+            }
+            plan.push(Preemption::ElasticPods {
+                namespace: victim.namespace.clone(),
+                job: victim.name.clone(),
+                pods,
+            });
+            if cluster.admit(queue, request).is_ok() {
+                return Some(plan);
+            }
+        }
+
+        for victim in &victims {
+            cluster.release(victim.queue.as_deref(), &victim.min_request);
+            plan.push(Preemption::Job {
+                namespace: victim.namespace.clone(),
+                job: victim.name.clone(),
+            });
+            if cluster.admit(queue, request).is_ok() {
+                return Some(plan);
+            }
+        }
+
+        None
+    }
+
+    /// Whether a job of `queue` may preempt the victim.
+    pub(crate) fn preemptable(&self, victim: &JobUsage, queue: Option<&str>) -> bool {
+        if victim.queue.as_deref() == queue {
+            return true;
+        }
+        victim
+            .queue
+            .as_ref()
+            .and_then(|q| self.queues.get(q))
+            .map(|share| share.reclaimable)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Preemption;
+    use crate::fairshare::{Cluster, JobUsage, QueueShare};
+    use habitat_api::resource::ResourceList;
+
+    fn cpu(value: f64) -> ResourceList {
+        [("cpu".to_string(), value)].into_iter().collect()
+    }
+
+    fn cluster(jobs: Vec<JobUsage>) -> Cluster {
+        let mut cluster = Cluster {
+            capacity: cpu(10.0),
+            queues: [
+                ("a".to_string(), QueueShare {
+                    weight: 1,
+                    ..Default::default()
+                }),
+                ("b".to_string(), QueueShare {
+                    weight: 1,
+                    reclaimable: false,
+                    ..Default::default()
+                }),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        for job in jobs {
+            cluster.allocate(&job);
+            cluster.jobs.push(job);
+        }
+        cluster
+    }
+
+    fn job(name: &str, queue: &str, priority: i32, min: f64, elastic: &[f64]) -> JobUsage {
+        JobUsage {
+            namespace: "default".to_string(),
+            name: name.to_string(),
+            queue: Some(queue.to_string()),
+            priority,
+            min_request: cpu(min),
+            elastic_pods: elastic
+                .iter()
+                .enumerate()
+                .map(|(i, value)| (format!("{}-{}", name, i), cpu(*value)))
+                .collect(),
+            end: None,
+        }
+    }
+
+    #[test]
+    fn test_preempt_elastic_pods_first() {
+        let cluster = cluster(vec![
+            job("low", "a", 1, 4.0, &[2.0, 2.0]),
+            job("other", "b", 0, 2.0, &[]),
+        ]);
+        assert_eq!(
+            cluster.plan_preemption(Some("a"), &cpu(2.0), 10),
+            Some(vec![Preemption::ElasticPods {
+                namespace: "default".to_string(),
+                job: "low".to_string(),
+                pods: vec!["low-0".to_string()],
+            }])
+        );
+    }
+
+    #[test]
+    fn test_preempt_whole_jobs() {
+        let cluster = cluster(vec![
+            job("newer", "a", 1, 4.0, &[]),
+            job("older", "a", 1, 4.0, &[]),
+            job("other", "b", 0, 2.0, &[]),
+        ]);
+        assert_eq!(
+            cluster.plan_preemption(Some("a"), &cpu(2.0), 10),
+            Some(vec![Preemption::Job {
+                namespace: "default".to_string(),
+                job: "newer".to_string(),
+            }])
+        );
+        // the jobs of the same or higher priority are never preempted
+        assert_eq!(cluster.plan_preemption(Some("a"), &cpu(2.0), 1), None);
+        // jobs in non reclaimable queues are not preempted by other queues
+        assert_eq!(cluster.plan_preemption(Some("a"), &cpu(10.0), 10), None);
+    }
+}
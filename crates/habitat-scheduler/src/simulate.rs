@@ -0,0 +1,226 @@
+use std::fmt;
+
+use habitat_api::{batch::LabelNames, Job};
+use k8s_openapi::{
+    api::core::v1::{Pod, PodSpec},
+    chrono::{DateTime, Duration, Utc},
+};
+use kube::{core::ObjectMeta, ResourceExt};
+
+use crate::{
+    fairshare::{Cluster, JobUsage},
+    framework::{Framework, NodeInfo, Placement, PodInfo},
+    snapshot::Snapshot,
+};
+
+/// What would happen to a pending job
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The job is admitted, and its `parallelism.min` pods are placed on these nodes
+    Start(Vec<Placement>),
+    /// The job keeps waiting for this reason
+    Wait(String),
+}
+
+/// The outcome of a pending job
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JobReport {
+    pub namespace: String,
+    pub name: String,
+    pub queue: Option<String>,
+    pub outcome: Outcome,
+}
+
+impl fmt::Display for JobReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.namespace, self.name)?;
+        if let Some(queue) = &self.queue {
+            write!(f, " (queue {})", queue)?;
+        }
+        match &self.outcome {
+            Outcome::Start(placements) => {
+                write!(f, ": start")?;
+                for placement in placements {
+                    write!(f, "\n    {} -> {}", placement.pod, placement.node)?;
+                }
+                Ok(())
+            }
+            Outcome::Wait(reason) => write!(f, ": wait: {}", reason),
+        }
+    }
+}
+
+/// Runs the queue admission, the backfilling and the gang scheduling over the pending jobs of a snapshot at
+/// `now`, the highest priority and oldest first, as if each admitted job took its resources right away.
+pub fn simulate(
+    snapshot: &Snapshot,
+    framework: &Framework,
+    labels: &LabelNames,
+    now: DateTime<Utc>,
+) -> Vec<JobReport> {
+    let mut cluster = Cluster::new(snapshot, labels);
+    let mut nodes = snapshot
+        .nodes
+        .iter()
+        .cloned()
+        .map(NodeInfo::new)
+        .collect::<Vec<_>>();
+    for pod in &snapshot.pods {
+        let phase = pod.status.as_ref().and_then(|s| s.phase.as_deref());
+        let node_name = pod.spec.as_ref().and_then(|s| s.node_name.as_deref());
+        if let (Some(node_name), false) = (node_name, matches!(phase, Some("Succeeded" | "Failed"))) {
+            if let Some(node) = nodes.iter_mut().find(|n| n.name() == node_name) {
+                node.add_pod(&PodInfo::new(pod.clone()));
+            }
+        }
+    }
+
+    let mut reports = vec![];
+    for pending in cluster.pending.clone() {
+        for (name, share) in cluster.queues.iter_mut() {
+            share.waiting = cluster.pending.iter().any(|p| p.queue.as_ref() == Some(name));
+        }
+        let job = snapshot
+            .jobs
+            .iter()
+            .find(|job| {
+                job.namespace().unwrap_or_default() == pending.namespace && job.name_any() == pending.name
+            })
+            .expect("pending jobs come from the snapshot");
+
+        let queue = pending.queue.as_deref();
+        let outcome = match cluster
+            .admit(queue, &pending.min_request)
+            .and_then(|()| cluster.backfill(&pending.namespace, &pending.name, now))
+        {
+            Err(reason) => Outcome::Wait(reason),
+            Ok(()) => match framework.schedule_gang(&min_pods(job), &mut nodes) {
+                Ok(placements) => {
+                    let usage = JobUsage {
+                        namespace: pending.namespace.clone(),
+                        name: pending.name.clone(),
+                        queue: pending.queue.clone(),
+                        priority: pending.priority,
+                        min_request: pending.min_request.clone(),
+                        elastic_pods: vec![],
+                        end: pending.duration.map(|d| now + Duration::seconds(d as i64)),
+                    };
+                    cluster.allocate(&usage);
+                    cluster.jobs.insert(0, usage);
+                    cluster
+                        .pending
+                        .retain(|p| p.namespace != pending.namespace || p.name != pending.name);
+                    Outcome::Start(placements)
+                }
+                Err(reason) => Outcome::Wait(reason),
+            },
+        };
+
+        reports.push(JobReport {
+            namespace: pending.namespace,
+            name: pending.name,
+            queue: pending.queue,
+            outcome,
+        });
+    }
+
+    reports
+}
+
+/// The `parallelism.min` pods the controller would create for a job.
+fn min_pods(job: &Job) -> Vec<PodInfo> {
+    let mut pods = vec![];
+    for task in &job.spec.tasks {
+        let spec: PodSpec = serde_json::to_value(&task.template.spec)
+            .and_then(serde_json::from_value)
+            .unwrap_or_default();
+        for i in 0..task.parallelism.min {
+            pods.push(PodInfo::new(Pod {
+                metadata: ObjectMeta {
+                    name: Some(format!("{}-{}", task.name, i)),
+                    namespace: job.namespace(),
+                    ..Default::default()
+                },
+                spec: Some(spec.clone()),
+                ..Default::default()
+            }));
+        }
+    }
+    pods
+}
+
+#[cfg(test)]
+mod test {
+    use super::{simulate, Outcome};
+    use crate::{framework::Framework, plugins::Strategy, snapshot::Snapshot};
+    use habitat_api::batch::LabelNames;
+    use k8s_openapi::chrono::Utc;
+
+    #[test]
+    fn test_simulate() {
+        let snapshot = Snapshot::from_yaml(
+            r#"
+apiVersion: v1
+kind: Node
+metadata:
+  name: node-1
+status:
+  allocatable: {cpu: "4", memory: 8Gi, pods: "110"}
+---
+apiVersion: v1
+kind: Node
+metadata:
+  name: node-2
+status:
+  allocatable: {cpu: "4", memory: 8Gi, pods: "110"}
+---
+apiVersion: batch.habitat/v1beta1
+kind: Job
+metadata:
+  name: small
+  namespace: default
+  creationTimestamp: "2022-01-01T00:00:00Z"
+spec:
+  tasks:
+  - name: worker
+    parallelism: {min: 3}
+    template:
+      spec:
+        containers:
+        - name: main
+          image: busybox
+          resources: {requests: {cpu: "2"}}
+---
+apiVersion: batch.habitat/v1beta1
+kind: Job
+metadata:
+  name: large
+  namespace: default
+  creationTimestamp: "2022-01-02T00:00:00Z"
+spec:
+  tasks:
+  - name: worker
+    parallelism: {min: 2}
+    template:
+      spec:
+        containers:
+        - name: main
+          image: busybox
+          resources: {requests: {cpu: "2"}}
+"#,
+        )
+        .unwrap();
+
+        let framework = Framework::with_default_plugins(Strategy::BinPack);
+        let reports = simulate(&snapshot, &framework, &LabelNames::default(), Utc::now());
+        assert_eq!(reports.len(), 2);
+        assert_eq!(
+            reports[0].to_string(),
+            "default/small: start\n    worker-0 -> node-1\n    worker-1 -> node-1\n    worker-2 -> node-2"
+        );
+        assert_eq!(
+            reports[1].outcome,
+            Outcome::Wait("insufficient cpu in the cluster".to_string())
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use habitat_api::{Job, Queue};
+use k8s_openapi::api::{
+    core::v1::{Node, Pod},
+    scheduling::v1::PriorityClass,
+};
+use serde::Deserialize;
+use serde_yaml::Value;
+
+/// The objects the scheduling decisions are made from, either listed from the cluster or recorded in a file
+#[derive(Clone, Debug, Default)]
+pub struct Snapshot {
+    pub nodes: Vec<Node>,
+    pub pods: Vec<Pod>,
+    pub queues: Vec<Queue>,
+    pub jobs: Vec<Job>,
+    pub priority_classes: Vec<PriorityClass>,
+    /// Whether the nodes couldn't be listed, e.g. by a namespace-scoped controller, so the capacity of the
+    /// cluster is unknown
+    pub nodes_unknown: bool,
+}
+
+impl Snapshot {
+    /// Parses the objects of a YAML stream, e.g. the output of `kubectl get
+    /// nodes,pods,priorityclasses,hqueues,hjobs -A -o yaml`. The stream may have several documents and `List`
+    /// objects, the objects of any other kind are ignored.
+    pub fn from_yaml(yaml: &str) -> Result<Self, serde_yaml::Error> {
+        let mut snapshot = Self::default();
+        for document in serde_yaml::Deserializer::from_str(yaml) {
+            snapshot.add(Value::deserialize(document)?)?;
+        }
+        Ok(snapshot)
+    }
+
+    fn add(&mut self, object: Value) -> Result<(), serde_yaml::Error> {
+        let field = |name: &str| {
+            object
+                .get(name)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string()
+        };
+        let (api_version, kind) = (field("apiVersion"), field("kind"));
+        let habitat = api_version.starts_with("batch.habitat/");
+
+        match kind.as_str() {
+            "List" => {
+                let items = object.get("items").and_then(Value::as_sequence).cloned();
+                for item in items.unwrap_or_default() {
+                    self.add(item)?;
+                }
+            }
+            "Node" => self.nodes.push(serde_yaml::from_value(object)?),
+            "Pod" => self.pods.push(serde_yaml::from_value(object)?),
+            "PriorityClass" => self.priority_classes.push(serde_yaml::from_value(object)?),
+            "Queue" if habitat => self.queues.push(serde_yaml::from_value(object)?),
+            "Job" if habitat => self.jobs.push(serde_yaml::from_value(object)?),
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Snapshot;
+
+    #[test]
+    fn test_snapshot_from_yaml() {
+        let snapshot = Snapshot::from_yaml(
+            r#"
+apiVersion: v1
+kind: List
+items:
+- apiVersion: v1
+  kind: Node
+  metadata:
+    name: node-1
+- apiVersion: batch/v1
+  kind: Job
+  metadata:
+    name: not-habitat
+---
+apiVersion: batch.habitat/v1beta1
+kind: Job
+metadata:
+  name: train
+  namespace: default
+spec:
+  tasks: []
+---
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(snapshot.nodes.len(), 1);
+        assert_eq!(snapshot.jobs.len(), 1);
+        assert_eq!(snapshot.jobs[0].metadata.name.as_deref(), Some("train"));
+    }
+}